@@ -0,0 +1,55 @@
+//! Lookup benchmarks against the FILES module.
+//!
+//! Run with `cargo bench`. Add `--features users-compat` to also measure the
+//! same lookups through the `users` crate (libc's resolver) for comparison.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use truenas_rust_nss::{getpwall, getpwnam, getpwuid, warm_up, NssModule};
+
+const HIT_NAME: &str = "root";
+const HIT_UID: u32 = 0;
+const MISSING_NAME: &str = "nonexistent_bench_user_12345";
+
+fn bench_files(c: &mut Criterion) {
+    // Keep the one-off dlopen out of the first sample
+    warm_up(NssModule::Files).expect("FILES module is required for benchmarks");
+
+    let mut group = c.benchmark_group("files");
+    group.bench_function("getpwnam_hit", |b| {
+        b.iter(|| getpwnam(black_box(HIT_NAME), Some(NssModule::Files)).unwrap())
+    });
+    group.bench_function("getpwuid_hit", |b| {
+        b.iter(|| getpwuid(black_box(HIT_UID), Some(NssModule::Files)).unwrap())
+    });
+    group.bench_function("getpwnam_not_found", |b| {
+        b.iter(|| getpwnam(black_box(MISSING_NAME), Some(NssModule::Files)).unwrap_err())
+    });
+    group.bench_function("getpwall", |b| {
+        b.iter(|| getpwall(Some(NssModule::Files)).unwrap())
+    });
+    group.finish();
+}
+
+#[cfg(feature = "users-compat")]
+fn bench_users_crate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("users_crate");
+    group.bench_function("get_user_by_name_hit", |b| {
+        b.iter(|| users::get_user_by_name(black_box(HIT_NAME)).unwrap())
+    });
+    group.bench_function("get_user_by_uid_hit", |b| {
+        b.iter(|| users::get_user_by_uid(black_box(HIT_UID)).unwrap())
+    });
+    group.bench_function("get_user_by_name_not_found", |b| {
+        b.iter(|| assert!(users::get_user_by_name(black_box(MISSING_NAME)).is_none()))
+    });
+    group.bench_function("all_users", |b| {
+        b.iter(|| unsafe { users::all_users() }.count())
+    });
+    group.finish();
+}
+
+#[cfg(feature = "users-compat")]
+criterion_group!(benches, bench_files, bench_users_crate);
+#[cfg(not(feature = "users-compat"))]
+criterion_group!(benches, bench_files);
+criterion_main!(benches);
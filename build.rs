@@ -1,8 +1,38 @@
+use std::env;
+use std::process;
+
+const LIBRARY_NAME: &str = "libtruenas_rust_nss.so";
+
+/// Soname for the shared library: `TNSS_SONAME` verbatim when set (e.g.
+/// `libtruenas_rust_nss.so.0.1` for packagers pinning `major.minor`),
+/// otherwise `libtruenas_rust_nss.so.<major>` from the crate version.
+fn soname() -> Result<String, String> {
+    if let Ok(soname) = env::var("TNSS_SONAME") {
+        if soname.is_empty() || soname.contains('/') || soname.contains(char::is_whitespace) {
+            return Err(format!("TNSS_SONAME {soname:?} is not a valid soname"));
+        }
+        return Ok(soname);
+    }
+
+    let version = env::var("CARGO_PKG_VERSION").map_err(|_| "CARGO_PKG_VERSION is not set".to_string())?;
+    let major = version
+        .split('.')
+        .next()
+        .filter(|major| !major.is_empty() && major.bytes().all(|b| b.is_ascii_digit()))
+        .ok_or_else(|| format!("cannot derive a soname from CARGO_PKG_VERSION {version:?}; set TNSS_SONAME"))?;
+
+    Ok(format!("{LIBRARY_NAME}.{major}"))
+}
+
 fn main() {
-    // Set soname for the shared library
-    let version = env!("CARGO_PKG_VERSION");
-    let major_version = version.split('.').next()
-        .expect("Failed to parse major version from CARGO_PKG_VERSION");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=TNSS_SONAME");
 
-    println!("cargo:rustc-cdylib-link-arg=-Wl,-soname,libtruenas_rust_nss.so.{}", major_version);
-}
\ No newline at end of file
+    match soname() {
+        Ok(soname) => println!("cargo:rustc-cdylib-link-arg=-Wl,-soname,{soname}"),
+        Err(message) => {
+            eprintln!("error: {message}");
+            process::exit(1);
+        }
+    }
+}
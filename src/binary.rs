@@ -0,0 +1,145 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{GroupEntry, NssError, NssResult, PasswdEntry};
+
+/// Version of the binary layout, written as the first byte of every encoding.
+///
+/// Bump this whenever `PasswdEntry` or `GroupEntry` change shape so that stale
+/// cache contents are rejected instead of being misread.
+pub const BINARY_FORMAT_VERSION: u8 = 3;
+
+fn encode<T: Serialize>(value: &T) -> NssResult<Vec<u8>> {
+    let mut bytes = vec![BINARY_FORMAT_VERSION];
+    bincode::serialize_into(&mut bytes, value)
+        .map_err(|e| NssError::SerializationError(e.to_string()))?;
+    Ok(bytes)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> NssResult<T> {
+    match bytes.split_first() {
+        Some((&BINARY_FORMAT_VERSION, payload)) => bincode::deserialize(payload)
+            .map_err(|e| NssError::SerializationError(e.to_string())),
+        Some((&version, _)) => Err(NssError::SerializationError(format!(
+            "unsupported binary format version {version} (expected {BINARY_FORMAT_VERSION})"
+        ))),
+        None => Err(NssError::SerializationError("empty input".to_string())),
+    }
+}
+
+impl PasswdEntry {
+    /// Encode the entry in a compact, versioned binary form.
+    ///
+    /// The raw bytes behind the `*_os` accessors are not encoded; after decoding,
+    /// those accessors return the `String` fields.
+    ///
+    /// # Errors
+    /// Returns `NssError::SerializationError` if encoding fails.
+    pub fn to_bytes(&self) -> NssResult<Vec<u8>> {
+        encode(self)
+    }
+
+    /// Decode an entry previously produced by `to_bytes`.
+    ///
+    /// # Errors
+    /// Returns `NssError::SerializationError` if the data is malformed or was
+    /// written with a different `BINARY_FORMAT_VERSION`.
+    pub fn from_bytes(bytes: &[u8]) -> NssResult<Self> {
+        decode(bytes)
+    }
+}
+
+impl GroupEntry {
+    /// Encode the entry in a compact, versioned binary form.
+    ///
+    /// # Errors
+    /// Returns `NssError::SerializationError` if encoding fails.
+    pub fn to_bytes(&self) -> NssResult<Vec<u8>> {
+        encode(self)
+    }
+
+    /// Decode an entry previously produced by `to_bytes`.
+    ///
+    /// # Errors
+    /// Returns `NssError::SerializationError` if the data is malformed or was
+    /// written with a different `BINARY_FORMAT_VERSION`.
+    pub fn from_bytes(bytes: &[u8]) -> NssResult<Self> {
+        decode(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::passwd::RawPasswdFields;
+    use crate::NssModule;
+
+    fn sample_passwd() -> PasswdEntry {
+        PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1000,
+            pw_gecos: "Test User".to_string(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: "/bin/bash".to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_passwd_round_trip() {
+        let entry = sample_passwd();
+        let bytes = entry.to_bytes().unwrap();
+        assert_eq!(bytes[0], BINARY_FORMAT_VERSION);
+
+        let decoded = PasswdEntry::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.pw_name, entry.pw_name);
+        assert_eq!(decoded.pw_uid, entry.pw_uid);
+        assert_eq!(decoded.pw_gid, entry.pw_gid);
+        assert_eq!(decoded.pw_gecos, entry.pw_gecos);
+        assert_eq!(decoded.pw_dir, entry.pw_dir);
+        assert_eq!(decoded.pw_shell, entry.pw_shell);
+        assert_eq!(decoded.source, entry.source);
+    }
+
+    #[test]
+    fn test_group_round_trip() {
+        let entry = GroupEntry {
+            gr_name: "testgroup".to_string(),
+            gr_gid: 1000,
+            gr_mem: vec!["user1".to_string(), "user2".to_string()],
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
+        };
+
+        let decoded = GroupEntry::from_bytes(&entry.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.gr_name, entry.gr_name);
+        assert_eq!(decoded.gr_gid, entry.gr_gid);
+        assert_eq!(decoded.gr_mem, entry.gr_mem);
+        assert_eq!(decoded.source, entry.source);
+    }
+
+    #[test]
+    fn test_bumped_version_rejected() {
+        let mut bytes = sample_passwd().to_bytes().unwrap();
+        bytes[0] = BINARY_FORMAT_VERSION + 1;
+
+        match PasswdEntry::from_bytes(&bytes) {
+            Err(NssError::SerializationError(msg)) => assert!(msg.contains("version")),
+            other => panic!("Expected SerializationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_input_rejected() {
+        assert!(matches!(
+            GroupEntry::from_bytes(&[]),
+            Err(NssError::SerializationError(_))
+        ));
+    }
+}
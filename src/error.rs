@@ -1,3 +1,4 @@
+use libc::c_int;
 use thiserror::Error;
 use crate::nss_common::{NssReturnCode, NssOperation, NssModule};
 
@@ -12,6 +13,11 @@ pub enum NssError {
         return_code: NssReturnCode,
         module: NssModule,
     },
+    #[error("NSS operation {operation:?} on module [{module:?}] was denied; elevated privileges may be required")]
+    PermissionDenied {
+        operation: NssOperation,
+        module: NssModule,
+    },
     #[error("Buffer too small, need {needed} bytes")]
     BufferTooSmall { needed: usize },
     #[error("Invalid UTF-8 string")]
@@ -20,6 +26,172 @@ pub enum NssError {
     NullPointer,
     #[error("Library loading error: {0}")]
     LibraryError(String),
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+    #[error("Malformed entry line: {0}")]
+    MalformedLine(String),
+    #[error("Unknown NSS module id {0}")]
+    UnknownModule(u8),
+    #[error("No NSS module at index {index}; {count} configured")]
+    ModuleIndexOutOfRange { index: usize, count: usize },
+    #[error("Invalid default module list: {0}")]
+    InvalidModuleList(String),
+    #[error("NSS operation {operation:?} on module [{module:?}] timed out")]
+    Timeout {
+        operation: NssOperation,
+        module: NssModule,
+    },
+    #[error("No NSS module returned an entry for {operation:?}: {}", format_attempts(attempts))]
+    AllModulesFailed {
+        operation: NssOperation,
+        attempts: Vec<(NssModule, NssReturnCode)>,
+    },
+}
+
+fn breadcrumb(module: NssModule, code: NssReturnCode) -> String {
+    format!("tried {}: {code:?}", module.upper_name())
+}
+
+fn format_attempts(attempts: &[(NssModule, NssReturnCode)]) -> String {
+    attempts
+        .iter()
+        .map(|&(module, code)| breadcrumb(module, code))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl NssError {
+    /// Build the error for an NSS call that reported a non-zero `errno`.
+    ///
+    /// Permission failures (`EACCES`/`EPERM`) get their own variant so callers
+    /// can tell users to retry with privileges; everything else is reported as
+    /// `NssOperationFailed`.
+    pub(crate) fn from_errno(
+        errno: c_int,
+        operation: NssOperation,
+        return_code: NssReturnCode,
+        module: NssModule,
+    ) -> Self {
+        match errno {
+            libc::EACCES | libc::EPERM => NssError::PermissionDenied { operation, module },
+            _ => NssError::NssOperationFailed {
+                errno: errno.unsigned_abs(),
+                operation,
+                return_code,
+                module,
+            },
+        }
+    }
+
+    /// Whether this error only means that the requested entry does not exist.
+    ///
+    /// `AllModulesFailed` counts as not found: every module queried either had
+    /// no entry or was unavailable.
+    #[must_use]
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, .. }
+                | NssError::AllModulesFailed { .. }
+        )
+    }
+
+    /// Step-by-step description of how this error came about, for logging.
+    ///
+    /// For `AllModulesFailed` this is one breadcrumb per module attempted, in
+    /// order (e.g. `"tried FILES: NotFound"`); other errors yield their message.
+    #[must_use]
+    pub fn context_chain(&self) -> Vec<String> {
+        match self {
+            NssError::AllModulesFailed { attempts, .. } => attempts
+                .iter()
+                .map(|&(module, code)| breadcrumb(module, code))
+                .collect(),
+            other => vec![other.to_string()],
+        }
+    }
+}
+
+/// What an operation looks up, for messages.
+fn subject(operation: NssOperation) -> &'static str {
+    match operation {
+        NssOperation::GetPwNam | NssOperation::GetPwUid | NssOperation::GetPwEnt
+        | NssOperation::SetPwEnt | NssOperation::EndPwEnt => "user",
+        NssOperation::GetGrNam | NssOperation::GetGrGid | NssOperation::GetGrEnt
+        | NssOperation::SetGrEnt | NssOperation::EndGrEnt => "group",
+        NssOperation::GetHostByAddr => "host",
+    }
+}
+
+/// `strerror(3)` text for `errno`, e.g. "Connection timed out".
+fn strerror(errno: u32) -> String {
+    let mut buffer = [0 as libc::c_char; 256];
+    let Ok(errnum) = c_int::try_from(errno) else {
+        return format!("Unknown error {errno}");
+    };
+    // The XSI variant: fills `buffer` and returns 0 on success
+    if unsafe { libc::strerror_r(errnum, buffer.as_mut_ptr(), buffer.len()) } != 0 {
+        return format!("Unknown error {errno}");
+    }
+    unsafe { std::ffi::CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn module_list(modules: &[NssModule]) -> String {
+    modules.iter().map(NssModule::upper_name).collect::<Vec<_>>().join(", ")
+}
+
+/// Describe `err` as one sentence for operators, e.g. in CLI output.
+///
+/// Where `Display` spells out the variant's fields, this names what was being
+/// looked up, where, and why it failed in words: for example
+/// `"user not found in FILES, SSS"`, or `"user lookup on SSS failed: service
+/// unavailable (errno 110: Connection timed out)"`.
+#[must_use]
+pub fn explain(err: &NssError) -> String {
+    match err {
+        NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, operation, module, .. } => {
+            format!("{} not found in {}", subject(*operation), module.upper_name())
+        }
+        NssError::NssOperationFailed { errno, operation, return_code, module } => {
+            let mut message = format!(
+                "{} lookup on {} failed: {}",
+                subject(*operation),
+                module.upper_name(),
+                return_code.description()
+            );
+            if *errno != 0 {
+                message.push_str(&format!(" (errno {}: {})", errno, strerror(*errno)));
+            }
+            message
+        }
+        NssError::PermissionDenied { operation, module } => format!(
+            "{} lookup on {} was denied; elevated privileges may be required",
+            subject(*operation),
+            module.upper_name()
+        ),
+        NssError::Timeout { operation, module } => {
+            format!("{} lookup on {} timed out", subject(*operation), module.upper_name())
+        }
+        NssError::LibraryError(detail) => format!("NSS module could not be loaded: {detail}"),
+        NssError::AllModulesFailed { operation, attempts } => {
+            let with_code = |wanted: NssReturnCode| -> Vec<NssModule> {
+                attempts.iter().filter(|(_, code)| *code == wanted).map(|&(module, _)| module).collect()
+            };
+            let mut message = format!("{} not found", subject(*operation));
+            let not_found = with_code(NssReturnCode::NotFound);
+            if !not_found.is_empty() {
+                message.push_str(&format!(" in {}", module_list(&not_found)));
+            }
+            let unavailable = with_code(NssReturnCode::Unavail);
+            if !unavailable.is_empty() {
+                message.push_str(&format!("; {} unavailable", module_list(&unavailable)));
+            }
+            message
+        }
+        other => other.to_string(),
+    }
 }
 
 #[cfg(test)]
@@ -42,6 +214,94 @@ mod tests {
         assert!(error_str.contains("errno 2"));
     }
 
+    #[test]
+    fn test_is_not_found() {
+        let not_found = NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::GetGrNam,
+            return_code: NssReturnCode::NotFound,
+            module: NssModule::Files,
+        };
+        let unavail = NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::GetGrNam,
+            return_code: NssReturnCode::Unavail,
+            module: NssModule::Sss,
+        };
+
+        assert!(not_found.is_not_found());
+        assert!(!unavail.is_not_found());
+        assert!(!NssError::InvalidUtf8.is_not_found());
+        assert!(NssError::AllModulesFailed { operation: NssOperation::GetPwNam, attempts: vec![] }.is_not_found());
+    }
+
+    #[test]
+    fn test_all_modules_failed_context_chain() {
+        let error = NssError::AllModulesFailed {
+            operation: NssOperation::GetPwNam,
+            attempts: vec![
+                (NssModule::Files, NssReturnCode::NotFound),
+                (NssModule::Sss, NssReturnCode::Unavail),
+            ],
+        };
+
+        assert_eq!(error.context_chain(), vec!["tried FILES: NotFound", "tried SSS: Unavail"]);
+        assert_eq!(
+            error.to_string(),
+            "No NSS module returned an entry for GetPwNam: tried FILES: NotFound; tried SSS: Unavail"
+        );
+        assert_eq!(NssError::InvalidUtf8.context_chain(), vec!["Invalid UTF-8 string"]);
+    }
+
+    #[test]
+    fn test_from_errno_maps_permission_errors() {
+        for errno in [libc::EACCES, libc::EPERM] {
+            let error = NssError::from_errno(errno, NssOperation::GetPwNam, NssReturnCode::Unavail, NssModule::Files);
+            assert!(matches!(
+                error,
+                NssError::PermissionDenied { operation: NssOperation::GetPwNam, module: NssModule::Files }
+            ));
+        }
+
+        let error = NssError::from_errno(libc::ETIMEDOUT, NssOperation::GetPwNam, NssReturnCode::Unavail, NssModule::Sss);
+        assert!(matches!(error, NssError::NssOperationFailed { errno: 110, .. }));
+    }
+
+    #[test]
+    fn test_explain_not_found() {
+        let error = NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::GetPwNam,
+            return_code: NssReturnCode::NotFound,
+            module: NssModule::Files,
+        };
+        assert_eq!(explain(&error), "user not found in FILES");
+
+        let error = NssError::AllModulesFailed {
+            operation: NssOperation::GetGrNam,
+            attempts: vec![
+                (NssModule::Files, NssReturnCode::NotFound),
+                (NssModule::Sss, NssReturnCode::Unavail),
+            ],
+        };
+        assert_eq!(explain(&error), "group not found in FILES; SSS unavailable");
+    }
+
+    #[test]
+    fn test_explain_timeout() {
+        let error = NssError::from_errno(libc::ETIMEDOUT, NssOperation::GetGrGid, NssReturnCode::Unavail, NssModule::Sss);
+        assert_eq!(
+            explain(&error),
+            "group lookup on SSS failed: service unavailable (errno 110: Connection timed out)"
+        );
+    }
+
+    #[test]
+    fn test_explain_deadline_timeout() {
+        let error = NssError::Timeout { operation: NssOperation::GetPwNam, module: NssModule::Winbind };
+        assert_eq!(explain(&error), "user lookup on WINBIND timed out");
+    }
+
     #[test]
     fn test_buffer_too_small_error() {
         let error = NssError::BufferTooSmall { needed: 2048 };
@@ -59,4 +319,10 @@ mod tests {
         let error = NssError::LibraryError("Failed to load libnss_files.so.2".to_string());
         assert_eq!(error.to_string(), "Library loading error: Failed to load libnss_files.so.2");
     }
+
+    #[test]
+    fn test_serialization_error() {
+        let error = NssError::SerializationError("unsupported format version 9".to_string());
+        assert_eq!(error.to_string(), "Serialization error: unsupported format version 9");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,132 @@
+use std::os::unix::ffi::OsStrExt;
+
+use crate::{GroupEntry, PasswdEntry};
+
+/// 64-bit FNV-1a, chosen because its output is fixed by specification and so
+/// stays the same across processes, platforms and Rust releases.
+struct StableHasher(u64);
+
+impl StableHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        StableHasher(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    /// Length-prefixed so that adjacent fields cannot run into each other.
+    fn write_field(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_le_bytes());
+        self.write(bytes);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl PasswdEntry {
+    /// Stable hash of the entry's attributes, for detecting changes between runs.
+    ///
+    /// Covers every field except `source`, `source_module` and `extra`, so
+    /// the same account served by two modules has the same fingerprint.
+    /// String fields are hashed from the raw bytes the module returned. The
+    /// value is reproducible across processes.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_field(self.name_os().as_bytes());
+        hasher.write_u32(self.pw_uid);
+        hasher.write_u32(self.pw_gid);
+        hasher.write_field(self.gecos_os().as_bytes());
+        hasher.write_field(self.dir_os().as_bytes());
+        hasher.write_field(self.shell_os().as_bytes());
+        hasher.finish()
+    }
+}
+
+impl GroupEntry {
+    /// Stable hash of the entry's attributes, for detecting changes between runs.
+    ///
+    /// Covers the name, gid and members in order, but not the source, so the
+    /// same group served by two modules has the same fingerprint. The value is
+    /// reproducible across processes.
+    #[must_use]
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        hasher.write_field(self.gr_name.as_bytes());
+        hasher.write_u32(self.gr_gid);
+        hasher.write(&(self.gr_mem.len() as u64).to_le_bytes());
+        for member in &self.gr_mem {
+            hasher.write_field(member.as_bytes());
+        }
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::passwd::RawPasswdFields;
+    use crate::NssModule;
+
+    fn sample_passwd() -> PasswdEntry {
+        PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1000,
+            pw_gecos: "Test User".to_string(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: "/bin/bash".to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_fnv1a_reference_value() {
+        let mut hasher = StableHasher::new();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+
+    #[test]
+    fn test_passwd_fingerprint_changes_with_shell() {
+        let entry = sample_passwd();
+        let changed = PasswdEntry { pw_shell: "/bin/zsh".to_string(), ..sample_passwd() };
+        assert_ne!(entry.fingerprint(), changed.fingerprint());
+
+        let other_source = PasswdEntry { source: "WINBIND".to_string(), ..sample_passwd() };
+        assert_eq!(entry.fingerprint(), other_source.fingerprint());
+    }
+
+    #[test]
+    fn test_group_fingerprint_tracks_members() {
+        let group = |members: &[&str]| GroupEntry {
+            gr_name: "staff".to_string(),
+            gr_gid: 50,
+            gr_mem: members.iter().map(|m| m.to_string()).collect(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
+        };
+
+        assert_eq!(group(&["alice", "bob"]).fingerprint(), group(&["alice", "bob"]).fingerprint());
+        assert_ne!(group(&["alice", "bob"]).fingerprint(), group(&["alicebob"]).fingerprint());
+        assert_ne!(group(&["alice"]).fingerprint(), group(&[]).fingerprint());
+    }
+}
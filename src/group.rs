@@ -1,24 +1,115 @@
 use libc::{c_char, c_int, gid_t, group};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::ControlFlow;
+use std::thread;
 
-use crate::{NssError, NssResult, NssModule, NssOperation, NssReturnCode};
-use crate::nss_common::get_nss_function;
+use crate::{NssError, NssResult, NssModule, NssOperation, NssReturnCode, PasswdEntry};
+use crate::metrics;
+use crate::nss_common::{NssFn, NssSymbol};
+use crate::nsswitch::{enumerate_chain, lookup_chain, FallbackPolicy};
+use crate::options::LookupOptions;
+use crate::passwd::{getpwall, getpwnam, id_collisions};
 
 const GROUP_INIT_BUFLEN: usize = 1024;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GroupEntry {
     pub gr_name: String,
     pub gr_gid: gid_t,
     pub gr_mem: Vec<String>,
+    /// Display label of `source_module` (see `NssModule::source_label`),
+    /// derived from it when the entry is built; for a merged group, the labels
+    /// of every contributing module joined by `+`.
     pub source: String,
+    /// Module that produced the entry; this, not `source`, is what to match
+    /// on. `None` for entries that did not come from exactly one module:
+    /// groups merged from several, or those converted from other crates'
+    /// types.
+    pub source_module: Option<NssModule>,
+    /// Number of member names that were not valid UTF-8 and were decoded
+    /// with replacement characters by a `LookupOptions::lossy` lookup.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub invalid_members: usize,
 }
 
+impl GroupEntry {
+    /// `gr_mem` sorted lexicographically with duplicates removed.
+    ///
+    /// Modules may list members in any order, and some repeat them; this is
+    /// the form to compare or diff membership in.
+    #[must_use]
+    pub fn members_sorted(&self) -> Vec<String> {
+        let mut members = self.gr_mem.clone();
+        members.sort_unstable();
+        members.dedup();
+        members
+    }
+
+    /// Full membership: `gr_mem` plus users whose primary group is this group.
+    ///
+    /// NSS only lists supplementary members in `gr_mem`; users with a matching
+    /// `pw_gid` are found by enumerating the whole passwd database of `module`
+    /// (or of all modules for `None`), which can be expensive for large
+    /// directories or unavailable when enumeration is disabled.
+    ///
+    /// # Errors
+    /// Returns `NssError` if enumerating passwd entries fails.
+    pub fn all_members(&self, module: Option<NssModule>) -> NssResult<Vec<String>> {
+        let users = getpwall(module)?;
+
+        let mut members = self.gr_mem.clone();
+        let mut seen: HashSet<String> = members.iter().cloned().collect();
+        for user in users {
+            if user.pw_gid == self.gr_gid && seen.insert(user.pw_name.clone()) {
+                members.push(user.pw_name);
+            }
+        }
 
+        Ok(members)
+    }
+}
+
+/// Entries compare by `gr_gid`, then `gr_name`.
+///
+/// **Only those two fields participate.** Members and `source` are ignored, so
+/// the same group returned by two modules compares equal even if their member
+/// lists differ; compare `gr_mem` explicitly to detect membership changes.
+impl PartialEq for GroupEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for GroupEntry {}
+
+impl PartialOrd for GroupEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GroupEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.gr_gid
+            .cmp(&other.gr_gid)
+            .then_with(|| self.gr_name.cmp(&other.gr_name))
+    }
+}
+
+
+/// Decode a `group` record. Member names that are not valid UTF-8 fail the
+/// entry with `NssError::InvalidUtf8` unless `lossy` is set, in which case
+/// they are kept with replacement characters and counted in
+/// `invalid_members`.
 unsafe fn parse_group_result(
     result: *const group,
     module: &NssModule,
+    lossy: bool,
 ) -> NssResult<Option<GroupEntry>> {
     if result.is_null() {
         return Ok(None);
@@ -36,19 +127,23 @@ unsafe fn parse_group_result(
         .to_string();
 
     let mut gr_mem = Vec::new();
+    let mut invalid_members = 0;
     if !group_ref.gr_mem.is_null() {
-        let mut i = 0;
-        loop {
-            let member_ptr = *group_ref.gr_mem.offset(i);
-            if member_ptr.is_null() {
-                break;
+        // Count the members first so that a group with tens of thousands of
+        // them gets one allocation rather than a series of regrowths
+        let count = (0..).take_while(|&i| !(*group_ref.gr_mem.add(i)).is_null()).count();
+        gr_mem.reserve_exact(count);
+
+        for i in 0..count {
+            let member = CStr::from_ptr(*group_ref.gr_mem.add(i));
+            match member.to_str() {
+                Ok(name) => gr_mem.push(name.to_string()),
+                Err(_) if lossy => {
+                    invalid_members += 1;
+                    gr_mem.push(member.to_string_lossy().into_owned());
+                }
+                Err(_) => return Err(NssError::InvalidUtf8),
             }
-            let member = CStr::from_ptr(member_ptr)
-                .to_str()
-                .map_err(|_| NssError::InvalidUtf8)?
-                .to_string();
-            gr_mem.push(member);
-            i += 1;
         }
     }
 
@@ -56,32 +151,48 @@ unsafe fn parse_group_result(
         gr_name,
         gr_gid: group_ref.gr_gid,
         gr_mem,
-        source: module.upper_name().to_string(),
+        source: module.source_label(),
+        source_module: Some(*module),
+        invalid_members,
     }))
 }
 
-type GetGrNameFn = unsafe extern "C" fn(
-    name: *const c_char,
-    result: *mut group,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
+struct GetGrNameFn;
+
+unsafe impl NssSymbol for GetGrNameFn {
+    type Fn = unsafe extern "C" fn(
+        name: *const c_char,
+        result: *mut group,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetGrNam;
+}
 
 unsafe fn getgrnam_r_impl(
     name: &str,
     module: NssModule,
     buffer_len: usize,
 ) -> NssResult<Option<GroupEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetGrNam, module)?;
-    let getgrnam_r: GetGrNameFn = mem::transmute(func_ptr);
+    let buffer_len = metrics::initial_buflen(NssOperation::GetGrNam, buffer_len);
+    getgrnam_r_call(NssFn::load(module)?, name, module, buffer_len, false)
+}
 
+/// The body of `getgrnam_r_impl`, calling an already resolved `getgrnam_r`.
+unsafe fn getgrnam_r_call(
+    getgrnam_r: NssFn<GetGrNameFn>,
+    name: &str,
+    module: NssModule,
+    buffer_len: usize,
+    lossy: bool,
+) -> NssResult<Option<GroupEntry>> {
     let name_c = CString::new(name).map_err(|_| NssError::InvalidUtf8)?;
     let mut result: group = mem::zeroed();
     let mut buffer = vec![0u8; buffer_len];
     let mut errno: c_int = 0;
 
-    let ret_code = getgrnam_r(
+    let ret_code = getgrnam_r.get()(
         name_c.as_ptr(),
         &mut result,
         buffer.as_mut_ptr().cast::<c_char>(),
@@ -90,18 +201,13 @@ unsafe fn getgrnam_r_impl(
     );
 
     match errno {
-        0 => {} // Success
+        0 => metrics::record_buflen(NssOperation::GetGrNam, buffer_len), // Success
         libc::ERANGE => {
             // Buffer too small, try with larger buffer
-            return getgrnam_r_impl(name, module, buffer_len * 2);
+            return getgrnam_r_call(getgrnam_r, name, module, buffer_len * 2, lossy);
         }
         _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetGrNam,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+            return Err(NssError::from_errno(errno, NssOperation::GetGrNam, NssReturnCode::from(ret_code), module));
         }
     }
 
@@ -119,30 +225,47 @@ unsafe fn getgrnam_r_impl(
         });
     }
 
-    parse_group_result(&result, &module)
+    parse_group_result(&result, &module, lossy)
 }
 
-type GetGrGidFn = unsafe extern "C" fn(
-    gid: gid_t,
-    result: *mut group,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
+struct GetGrGidFn;
+
+unsafe impl NssSymbol for GetGrGidFn {
+    type Fn = unsafe extern "C" fn(
+        gid: gid_t,
+        result: *mut group,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetGrGid;
+}
 
-unsafe fn getgrgid_r_impl(
+/// Call `getgrgid_r`, handing a found record to `on_found` while the buffer
+/// backing it is still alive.
+unsafe fn getgrgid_r_with<T>(
     gid: gid_t,
     module: NssModule,
     buffer_len: usize,
-) -> NssResult<Option<GroupEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetGrGid, module)?;
-    let getgrgid_r: GetGrGidFn = mem::transmute(func_ptr);
+    on_found: impl FnOnce(&group) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
+    let buffer_len = metrics::initial_buflen(NssOperation::GetGrGid, buffer_len);
+    getgrgid_r_call(NssFn::load(module)?, gid, module, buffer_len, on_found)
+}
 
+/// The body of `getgrgid_r_with`, calling an already resolved `getgrgid_r`.
+unsafe fn getgrgid_r_call<T>(
+    getgrgid_r: NssFn<GetGrGidFn>,
+    gid: gid_t,
+    module: NssModule,
+    buffer_len: usize,
+    on_found: impl FnOnce(&group) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
     let mut result: group = mem::zeroed();
     let mut buffer = vec![0u8; buffer_len];
     let mut errno: c_int = 0;
 
-    let ret_code = getgrgid_r(
+    let ret_code = getgrgid_r.get()(
         gid,
         &mut result,
         buffer.as_mut_ptr().cast::<c_char>(),
@@ -151,18 +274,13 @@ unsafe fn getgrgid_r_impl(
     );
 
     match errno {
-        0 => {} // Success
+        0 => metrics::record_buflen(NssOperation::GetGrGid, buffer_len), // Success
         libc::ERANGE => {
             // Buffer too small, try with larger buffer
-            return getgrgid_r_impl(gid, module, buffer_len * 2);
+            return getgrgid_r_call(getgrgid_r, gid, module, buffer_len * 2, on_found);
         }
         _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetGrGid,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+            return Err(NssError::from_errno(errno, NssOperation::GetGrGid, NssReturnCode::from(ret_code), module));
         }
     }
 
@@ -180,7 +298,7 @@ unsafe fn getgrgid_r_impl(
         });
     }
 
-    parse_group_result(&result, &module)
+    on_found(&result)
 }
 
 /// Get group entry by group name.
@@ -188,26 +306,41 @@ unsafe fn getgrgid_r_impl(
 /// # Errors
 /// Returns `NssError` if the group is not found or NSS operation fails.
 pub fn getgrnam(name: &str, module: Option<NssModule>) -> NssResult<GroupEntry> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
-    };
+    getgrnam_opts(name, module, &LookupOptions::new())
+}
 
-    for mod_enum in modules {
-        match unsafe { getgrnam_r_impl(name, mod_enum, GROUP_INIT_BUFLEN) } {
-            Ok(Some(entry)) => return Ok(entry),
-            Ok(None) => continue,
-            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => continue,
-            Err(NssError::LibraryError(_)) => continue, // Skip unavailable modules
-            Err(e) => return Err(e),
-        }
-    }
+/// Like `getgrnam`, with `gr_mem` replaced by `members_sorted`.
+///
+/// # Errors
+/// Returns `NssError` if the group is not found or NSS operation fails.
+pub fn getgrnam_canonical(name: &str, module: Option<NssModule>) -> NssResult<GroupEntry> {
+    let mut entry = getgrnam(name, module)?;
+    entry.gr_mem = entry.members_sorted();
+    Ok(entry)
+}
 
-    Err(NssError::NssOperationFailed {
-        errno: 0,
-        operation: NssOperation::GetGrNam,
-        return_code: NssReturnCode::NotFound,
-        module: NssModule::Files, // Placeholder
+/// Get group entry by group name, tuned by `opts`; see `getpwnam_opts`.
+///
+/// # Errors
+/// Returns `NssError::Timeout` if a module does not answer within the
+/// timeout, otherwise `NssError` if the group is not found or NSS operation
+/// fails.
+pub fn getgrnam_opts(name: &str, module: Option<NssModule>, opts: &LookupOptions) -> NssResult<GroupEntry> {
+    let buflen = opts.buflen.unwrap_or(GROUP_INIT_BUFLEN);
+    let lossy = opts.lossy;
+
+    lookup_chain(NssOperation::GetGrNam, module, opts.policy, |mod_enum| {
+        let getgrnam_r = unsafe { NssFn::<GetGrNameFn>::load(mod_enum)? };
+        let call = move |name: &str| unsafe {
+            getgrnam_r_call(getgrnam_r, name, mod_enum, metrics::initial_buflen(NssOperation::GetGrNam, buflen), lossy)
+        };
+        match opts.timeout {
+            Some(timeout) => {
+                let name = name.to_string();
+                opts.run_within(NssOperation::GetGrNam, mod_enum, timeout, move || call(&name))
+            }
+            None => opts.retry(|| call(name)),
+        }
     })
 }
 
@@ -216,41 +349,85 @@ pub fn getgrnam(name: &str, module: Option<NssModule>) -> NssResult<GroupEntry>
 /// # Errors
 /// Returns `NssError` if the group is not found or NSS operation fails.
 pub fn getgrgid(gid: gid_t, module: Option<NssModule>) -> NssResult<GroupEntry> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
-    };
+    getgrgid_opts(gid, module, &LookupOptions::new())
+}
 
-    for mod_enum in modules {
-        match unsafe { getgrgid_r_impl(gid, mod_enum, GROUP_INIT_BUFLEN) } {
-            Ok(Some(entry)) => return Ok(entry),
-            Ok(None) => continue,
-            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => continue,
-            Err(NssError::LibraryError(_)) => continue, // Skip unavailable modules
-            Err(e) => return Err(e),
+/// Get group entry by group ID, tuned by `opts`; see `getpwnam_opts`.
+///
+/// # Errors
+/// Returns `NssError::Timeout` if a module does not answer within the
+/// timeout, otherwise `NssError` if the group is not found or NSS operation
+/// fails.
+pub fn getgrgid_opts(gid: gid_t, module: Option<NssModule>, opts: &LookupOptions) -> NssResult<GroupEntry> {
+    let buflen = opts.buflen.unwrap_or(GROUP_INIT_BUFLEN);
+    let lossy = opts.lossy;
+
+    lookup_chain(NssOperation::GetGrGid, module, opts.policy, |mod_enum| {
+        let getgrgid_r = unsafe { NssFn::<GetGrGidFn>::load(mod_enum)? };
+        let call = move || unsafe {
+            let buflen = metrics::initial_buflen(NssOperation::GetGrGid, buflen);
+            getgrgid_r_call(getgrgid_r, gid, mod_enum, buflen, |result| parse_group_result(result, &mod_enum, lossy))
+        };
+        match opts.timeout {
+            Some(timeout) => opts.run_within(NssOperation::GetGrGid, mod_enum, timeout, call),
+            None => opts.retry(call),
         }
+    })
+}
+
+/// Group entry of the calling process's effective group.
+///
+/// Shorthand for `getgrgid(getegid(), None)`.
+///
+/// # Errors
+/// Returns `NssError` if no module has the group or an NSS operation fails.
+pub fn getgrgid_self() -> NssResult<GroupEntry> {
+    getgrgid(unsafe { libc::getegid() }, None)
+}
+
+/// Whether any group has the given GID.
+///
+/// Cheaper than `getgrgid` when only existence matters: the record is not
+/// decoded.
+///
+/// # Errors
+/// Returns `NssError` if an NSS operation fails with a hard error.
+pub fn gid_exists(gid: gid_t, module: Option<NssModule>) -> NssResult<bool> {
+    match lookup_chain(NssOperation::GetGrGid, module, FallbackPolicy::default(), |mod_enum| unsafe {
+        getgrgid_r_with(gid, mod_enum, GROUP_INIT_BUFLEN, |_| Ok(Some(())))
+    }) {
+        Ok(()) => Ok(true),
+        Err(NssError::AllModulesFailed { .. }) => Ok(false),
+        Err(e) => Err(e),
     }
+}
 
-    Err(NssError::NssOperationFailed {
-        errno: 0,
-        operation: NssOperation::GetGrGid,
-        return_code: NssReturnCode::NotFound,
-        module: NssModule::Files, // Placeholder
-    })
+struct SetGrEntFn;
+
+unsafe impl NssSymbol for SetGrEntFn {
+    type Fn = unsafe extern "C" fn() -> c_int;
+    const OPERATION: NssOperation = NssOperation::SetGrEnt;
 }
+struct EndGrEntFn;
 
-type SetGrEntFn = unsafe extern "C" fn() -> c_int;
-type EndGrEntFn = unsafe extern "C" fn() -> c_int;
-type GetGrEntFn = unsafe extern "C" fn(
-    result: *mut group,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
+unsafe impl NssSymbol for EndGrEntFn {
+    type Fn = unsafe extern "C" fn() -> c_int;
+    const OPERATION: NssOperation = NssOperation::EndGrEnt;
+}
+struct GetGrEntFn;
+
+unsafe impl NssSymbol for GetGrEntFn {
+    type Fn = unsafe extern "C" fn(
+        result: *mut group,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetGrEnt;
+}
 
 unsafe fn setgrent_impl(module: NssModule) -> NssResult<()> {
-    let func_ptr = get_nss_function(NssOperation::SetGrEnt, module)?;
-    let setgrent: SetGrEntFn = mem::transmute(func_ptr);
+    let setgrent = NssFn::<SetGrEntFn>::load(module)?.get();
 
     let ret_code = setgrent();
     let nss_code = NssReturnCode::from(ret_code);
@@ -267,9 +444,8 @@ unsafe fn setgrent_impl(module: NssModule) -> NssResult<()> {
     Ok(())
 }
 
-unsafe fn endgrent_impl(module: NssModule) -> NssResult<()> {
-    let func_ptr = get_nss_function(NssOperation::EndGrEnt, module)?;
-    let endgrent: EndGrEntFn = mem::transmute(func_ptr);
+pub(crate) unsafe fn endgrent_impl(module: NssModule) -> NssResult<()> {
+    let endgrent = NssFn::<EndGrEntFn>::load(module)?.get();
 
     let ret_code = endgrent();
     let nss_code = NssReturnCode::from(ret_code);
@@ -286,12 +462,15 @@ unsafe fn endgrent_impl(module: NssModule) -> NssResult<()> {
     Ok(())
 }
 
-unsafe fn getgrent_r_impl(
+/// Call `getgrent_r`, handing the next record to `on_found` while the buffer
+/// backing it is still alive.
+unsafe fn getgrent_r_with<T>(
     module: NssModule,
     buffer_len: usize,
-) -> NssResult<Option<GroupEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetGrEnt, module)?;
-    let getgrent_r: GetGrEntFn = mem::transmute(func_ptr);
+    on_found: impl FnOnce(&group) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
+    let buffer_len = metrics::initial_buflen(NssOperation::GetGrEnt, buffer_len);
+    let getgrent_r = NssFn::<GetGrEntFn>::load(module)?.get();
 
     let mut result: group = mem::zeroed();
     let mut buffer = vec![0u8; buffer_len];
@@ -304,33 +483,46 @@ unsafe fn getgrent_r_impl(
         &mut errno,
     );
 
+    let nss_code = NssReturnCode::from(ret_code);
+
+    // Modules signal the end of enumeration either with NOTFOUND or with
+    // ENOENT alongside a failure code; anything else is a real error
+    let end_of_data = nss_code == NssReturnCode::NotFound
+        || (errno == libc::ENOENT && nss_code != NssReturnCode::Success);
+
     match errno {
-        0 => {} // Success
         libc::ERANGE => {
             // Buffer too small, try with larger buffer
-            return getgrent_r_impl(module, buffer_len * 2);
+            return getgrent_r_with(module, buffer_len * 2, on_found);
         }
+        _ if end_of_data => return Ok(None),
+        0 => metrics::record_buflen(NssOperation::GetGrEnt, buffer_len), // Success
         _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetGrEnt,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+            return Err(NssError::from_errno(errno, NssOperation::GetGrEnt, nss_code, module));
         }
     }
 
-    let nss_code = NssReturnCode::from(ret_code);
     if nss_code != NssReturnCode::Success {
-        return Ok(None);
+        return Err(NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::GetGrEnt,
+            return_code: nss_code,
+            module,
+        });
     }
 
-    parse_group_result(&result, &module)
+    on_found(&result)
 }
 
+/// Enumerates the group entries of one module.
+///
+/// Not `Send` or `Sync`: NSS modules keep the `setgrent` cursor in
+/// thread-local state, so an iterator resumed on another thread would read
+/// (or reset) that thread's enumeration instead of its own.
 pub struct GroupIterator {
     module: NssModule,
     initialized: bool,
+    _thread_bound: PhantomData<*const ()>,
 }
 
 impl GroupIterator {
@@ -339,14 +531,27 @@ impl GroupIterator {
         GroupIterator {
             module,
             initialized: false,
+            _thread_bound: PhantomData,
         }
     }
-}
 
-impl Iterator for GroupIterator {
-    type Item = NssResult<GroupEntry>;
+    /// Rewind to the start: end the current enumeration, if any, so that the
+    /// next `next()` calls `setgrent` again.
+    ///
+    /// This works on an exhausted iterator too. The same thread-local
+    /// constraints apply as for a fresh iterator: the restarted enumeration
+    /// belongs to the calling thread's NSS state.
+    pub fn reset(&mut self) {
+        if self.initialized {
+            unsafe {
+                let _ = endgrent_impl(self.module);
+            }
+            self.initialized = false;
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    /// Advance the enumeration, converting the record with `on_found`.
+    fn next_with<T>(&mut self, on_found: impl FnOnce(&group) -> NssResult<Option<T>>) -> Option<NssResult<T>> {
         unsafe {
             if !self.initialized {
                 if let Err(e) = setgrent_impl(self.module) {
@@ -355,15 +560,20 @@ impl Iterator for GroupIterator {
                 self.initialized = true;
             }
 
-            match getgrent_r_impl(self.module, GROUP_INIT_BUFLEN) {
-                Ok(Some(entry)) => Some(Ok(entry)),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
-            }
+            getgrent_r_with(self.module, GROUP_INIT_BUFLEN, on_found).transpose()
         }
     }
 }
 
+impl Iterator for GroupIterator {
+    type Item = NssResult<GroupEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let module = self.module;
+        self.next_with(|result| unsafe { parse_group_result(result, &module, false) })
+    }
+}
+
 impl Drop for GroupIterator {
     fn drop(&mut self) {
         if self.initialized {
@@ -385,35 +595,812 @@ pub fn itergrp(module: NssModule) -> GroupIterator {
 /// # Errors
 /// Returns `NssError` if NSS operation fails.
 pub fn getgrall(module: Option<NssModule>) -> NssResult<Vec<GroupEntry>> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
+    let mut all_entries = Vec::new();
+    enumerate_chain("group", module, itergrp, |entry| {
+        all_entries.push(entry);
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(all_entries)
+}
+
+/// Number of group entries in the specified NSS module(s).
+///
+/// Enumerates like `getgrall`, including skipping unavailable or missing
+/// modules and summing over the default modules when none is given, but
+/// without decoding any entry.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn count_group(module: Option<NssModule>) -> NssResult<usize> {
+    let mut count = 0;
+    enumerate_chain(
+        "group",
+        module,
+        |mod_enum| {
+            let mut groups = GroupIterator::new(mod_enum);
+            std::iter::from_fn(move || groups.next_with(|_| Ok(Some(()))))
+        },
+        |()| {
+            count += 1;
+            ControlFlow::Continue(())
+        },
+    )?;
+    Ok(count)
+}
+
+/// All group entries keyed by group name.
+///
+/// If a name appears more than once (e.g. defined by two modules), the last
+/// entry in `getgrall` order wins.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getgrall_by_name(module: Option<NssModule>) -> NssResult<HashMap<String, GroupEntry>> {
+    Ok(getgrall(module)?
+        .into_iter()
+        .map(|entry| (entry.gr_name.clone(), entry))
+        .collect())
+}
+
+/// All group entries keyed by GID.
+///
+/// If a GID appears more than once (e.g. two names sharing it, or the same
+/// group from two modules), the last entry in `getgrall` order wins; see
+/// `find_gid_collisions` to detect the former.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getgrall_by_gid(module: Option<NssModule>) -> NssResult<HashMap<gid_t, GroupEntry>> {
+    Ok(getgrall(module)?
+        .into_iter()
+        .map(|entry| (entry.gr_gid, entry))
+        .collect())
+}
+
+/// All group entries together with the passwd entries of their members.
+///
+/// Groups are enumerated once, then every distinct member name is looked up
+/// a single time, spread over `threads` worker threads (at least one). Each
+/// group's users follow `gr_mem` order; members with no passwd entry are left
+/// out.
+///
+/// # Errors
+/// Returns `NssError` if enumeration or a member lookup fails with a hard
+/// error.
+pub fn getgrall_with_members(module: Option<NssModule>, threads: usize) -> NssResult<Vec<(GroupEntry, Vec<PasswdEntry>)>> {
+    resolve_group_members(getgrall(module)?, threads, |name| match getpwnam(name, module) {
+        Ok(entry) => Ok(Some(entry)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    })
+}
+
+fn resolve_group_members<F>(
+    groups: Vec<GroupEntry>,
+    threads: usize,
+    lookup: F,
+) -> NssResult<Vec<(GroupEntry, Vec<PasswdEntry>)>>
+where
+    F: Fn(&str) -> NssResult<Option<PasswdEntry>> + Sync,
+{
+    let mut seen = HashSet::new();
+    let names: Vec<&str> = groups
+        .iter()
+        .flat_map(|entry| &entry.gr_mem)
+        .map(String::as_str)
+        .filter(|name| seen.insert(*name))
+        .collect();
+    let chunk_len = names.len().div_ceil(threads.max(1)).max(1);
+
+    let lookup = &lookup;
+    let resolved = thread::scope(|scope| {
+        let workers: Vec<_> = names
+            .chunks(chunk_len)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|&name| lookup(name).map(|entry| (name, entry)))
+                        .collect::<NssResult<Vec<_>>>()
+                })
+            })
+            .collect();
+
+        let mut resolved = HashMap::new();
+        for worker in workers {
+            for (name, entry) in worker.join().expect("member lookup thread panicked")? {
+                if let Some(entry) = entry {
+                    resolved.insert(name, entry);
+                }
+            }
+        }
+        Ok::<_, NssError>(resolved)
+    })?;
+
+    let members: Vec<Vec<PasswdEntry>> = groups
+        .iter()
+        .map(|entry| {
+            entry
+                .gr_mem
+                .iter()
+                .filter_map(|member| resolved.get(member.as_str()).cloned())
+                .collect()
+        })
+        .collect();
+
+    Ok(groups.into_iter().zip(members).collect())
+}
+
+/// GIDs that `module` maps to more than one group name, in ascending order.
+///
+/// Each GID comes with every name that has it, in enumeration order. Only
+/// one module is checked, since the same group served by FILES and a
+/// directory service is not a collision.
+///
+/// # Errors
+/// Returns `NssError` if enumeration fails.
+pub fn find_gid_collisions(module: NssModule) -> NssResult<Vec<(gid_t, Vec<String>)>> {
+    let entries = getgrall(Some(module))?;
+    Ok(id_collisions(entries.into_iter().map(|e| (e.gr_gid, e.gr_name))))
+}
+
+/// Users from `module` whose primary gid does not resolve to a group.
+///
+/// Groups are looked up through the default module chain, since a user's
+/// primary group is often served by a different module than the user (e.g. a
+/// FILES account in a directory group). Each distinct gid is looked up once.
+/// Users are returned in `getpwall` order.
+///
+/// # Errors
+/// Returns `NssError` if enumeration or a group lookup fails with a hard
+/// error.
+pub fn verify_primary_groups(module: Option<NssModule>) -> NssResult<Vec<PasswdEntry>> {
+    users_missing_primary_group(getpwall(module)?, |gid| gid_exists(gid, None))
+}
+
+fn users_missing_primary_group<F>(users: Vec<PasswdEntry>, mut group_exists: F) -> NssResult<Vec<PasswdEntry>>
+where
+    F: FnMut(gid_t) -> NssResult<bool>,
+{
+    let mut known: HashMap<gid_t, bool> = HashMap::new();
+    let mut missing = Vec::new();
+
+    for user in users {
+        let exists = match known.get(&user.pw_gid) {
+            Some(&exists) => exists,
+            None => {
+                let exists = group_exists(user.pw_gid)?;
+                known.insert(user.pw_gid, exists);
+                exists
+            }
+        };
+        if !exists {
+            missing.push(user);
+        }
+    }
+
+    Ok(missing)
+}
+
+impl PasswdEntry {
+    /// The group named by this user's primary gid, or `None` if no module has it.
+    ///
+    /// The group is looked up through the default module chain, as in
+    /// `verify_primary_groups`.
+    ///
+    /// # Errors
+    /// Returns `NssError` if the group lookup fails with a hard error.
+    pub fn primary_group(&self) -> NssResult<Option<GroupEntry>> {
+        match getgrgid(self.pw_gid, None) {
+            Ok(group) => Ok(Some(group)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Every user from `module`, paired with its primary group.
+///
+/// Groups are resolved with `PasswdEntry::primary_group`. With the `cache`
+/// feature, each distinct gid is looked up once for the whole batch, which
+/// saves a round trip per user when many share a handful of primary groups.
+/// Users are returned in `getpwall` order.
+///
+/// # Errors
+/// Returns `NssError` if enumeration or a group lookup fails with a hard
+/// error.
+pub fn getpwall_with_primary_groups(module: Option<NssModule>) -> NssResult<Vec<(PasswdEntry, Option<GroupEntry>)>> {
+    resolve_primary_groups(getpwall(module)?, |user| user.primary_group())
+}
+
+fn resolve_primary_groups<F>(users: Vec<PasswdEntry>, mut lookup: F) -> NssResult<Vec<(PasswdEntry, Option<GroupEntry>)>>
+where
+    F: FnMut(&PasswdEntry) -> NssResult<Option<GroupEntry>>,
+{
+    #[cfg(feature = "cache")]
+    let mut resolved: HashMap<gid_t, Option<GroupEntry>> = HashMap::new();
+
+    users
+        .into_iter()
+        .map(|user| {
+            #[cfg(feature = "cache")]
+            let group = match resolved.get(&user.pw_gid) {
+                Some(group) => group.clone(),
+                None => {
+                    let group = lookup(&user)?;
+                    resolved.insert(user.pw_gid, group.clone());
+                    group
+                }
+            };
+            #[cfg(not(feature = "cache"))]
+            let group = lookup(&user)?;
+            Ok((user, group))
+        })
+        .collect()
+}
+
+/// Group IDs for `user`, mirroring glibc's `getgrouplist(3)`.
+///
+/// As in glibc, `group` (normally the user's primary gid from its passwd
+/// entry) always comes first, followed by every group listing `user` as a
+/// member. Groups are gathered from all modules, since a user's groups may be
+/// spread over FILES and directory services, and each gid is reported once.
+/// Membership is found by enumerating groups (glibc's fallback when a module has
+/// no `initgroups_dyn`), so this can be slow against large directories.
+///
+/// # Errors
+/// Returns `NssError` if group enumeration fails.
+pub fn getgrouplist_compat(user: &str, group: gid_t) -> NssResult<Vec<gid_t>> {
+    Ok(collect_group_list(user, group, getgrall(None)?))
+}
+
+fn collect_group_list(user: &str, group: gid_t, groups: Vec<GroupEntry>) -> Vec<gid_t> {
+    let mut seen = HashSet::from([group]);
+    let mut gids = vec![group];
+
+    for entry in groups {
+        if entry.gr_mem.iter().any(|member| member == user) && seen.insert(entry.gr_gid) {
+            gids.push(entry.gr_gid);
+        }
+    }
+
+    gids
+}
+
+/// Group IDs for each of `users`, like calling `getgrouplist_compat` for every
+/// one of them but with a single group enumeration.
+///
+/// Each user's list starts with their primary gid from `getpwnam` (omitted if
+/// the user has no passwd entry), followed by every group listing them in
+/// `gr_mem`, without duplicates. Every requested user has a key, with an empty
+/// list if nothing matched.
+///
+/// Membership comes entirely from enumeration, so the result is only as
+/// complete as `getgrall(module)`; a directory service that does not allow
+/// enumeration contributes nothing beyond primary gids. The enumeration costs
+/// the same whether one user is asked for or hundreds.
+///
+/// # Errors
+/// Returns `NssError` if group enumeration or a passwd lookup fails with a
+/// hard error.
+pub fn getgrouplist_many(users: &[&str], module: Option<NssModule>) -> NssResult<HashMap<String, Vec<gid_t>>> {
+    let groups = getgrall(module)?;
+    collect_group_lists(users, groups, |user| match getpwnam(user, module) {
+        Ok(entry) => Ok(Some(entry.pw_gid)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    })
+}
+
+fn collect_group_lists<F>(
+    users: &[&str],
+    groups: Vec<GroupEntry>,
+    mut primary_gid: F,
+) -> NssResult<HashMap<String, Vec<gid_t>>>
+where
+    F: FnMut(&str) -> NssResult<Option<gid_t>>,
+{
+    let mut lists: HashMap<String, Vec<gid_t>> = HashMap::with_capacity(users.len());
+    for &user in users {
+        if !lists.contains_key(user) {
+            let gids = primary_gid(user)?.into_iter().collect();
+            lists.insert(user.to_string(), gids);
+        }
+    }
+
+    for entry in groups {
+        for member in &entry.gr_mem {
+            if let Some(gids) = lists.get_mut(member.as_str()) {
+                if !gids.contains(&entry.gr_gid) {
+                    gids.push(entry.gr_gid);
+                }
+            }
+        }
+    }
+
+    Ok(lists)
+}
+
+/// Groups `user` belongs to, keyed by the module that defines each group.
+///
+/// A group counts when its `gr_mem` lists `user` or when its gid is the
+/// user's primary gid (the primary group rarely lists its own members). The
+/// primary gid comes from `getpwnam(user, None)`; if the user has no passwd
+/// entry only explicit membership counts. Modules without a matching group
+/// are left out of the map.
+///
+/// Every module is fully enumerated, so the cost is one pass over all of its
+/// groups: cheap for FILES, but against SSS or WINBIND it walks the whole
+/// directory, and returns nothing at all if the service has enumeration
+/// disabled (e.g. sssd `enumerate = false`, winbind `winbind enum groups = no`).
+///
+/// # Errors
+/// Returns `NssError` if the passwd lookup or a group enumeration fails.
+pub fn user_groups_by_source(user: &str, modules: &[NssModule]) -> NssResult<HashMap<NssModule, Vec<GroupEntry>>> {
+    let primary_gid = match getpwnam(user, None) {
+        Ok(entry) => Some(entry.pw_gid),
+        Err(e) if e.is_not_found() => None,
+        Err(e) => return Err(e),
     };
 
-    let mut all_entries = Vec::new();
+    groups_by_source(user, primary_gid, modules, |module| getgrall(Some(module)))
+}
+
+fn groups_by_source<F>(
+    user: &str,
+    primary_gid: Option<gid_t>,
+    modules: &[NssModule],
+    mut enumerate: F,
+) -> NssResult<HashMap<NssModule, Vec<GroupEntry>>>
+where
+    F: FnMut(NssModule) -> NssResult<Vec<GroupEntry>>,
+{
+    let mut by_source = HashMap::new();
+
+    for &module in modules {
+        let groups: Vec<GroupEntry> = enumerate(module)?
+            .into_iter()
+            .filter(|entry| Some(entry.gr_gid) == primary_gid || entry.gr_mem.iter().any(|member| member == user))
+            .collect();
+        if !groups.is_empty() {
+            by_source.insert(module, groups);
+        }
+    }
+
+    Ok(by_source)
+}
+
+/// Look up a group in several modules and merge the results into one entry.
+///
+/// Members from every module that defines the group are combined in module
+/// order without duplicates. The gid is taken from the first module that has
+/// the group, and `source` lists each contributing module joined by `+`
+/// (e.g. `"FILES+WINBIND"`); `source_module` is `None` unless only one module
+/// had the group.
+/// Unavailable modules are skipped.
+///
+/// # Errors
+/// Returns `NssError::AllModulesFailed` if no module has the group, or the
+/// first hard error encountered.
+pub fn getgrnam_merged(name: &str, modules: &[NssModule]) -> NssResult<GroupEntry> {
+    merge_groups(modules, |module| unsafe {
+        getgrnam_r_impl(name, module, GROUP_INIT_BUFLEN)
+    })
+}
+
+fn merge_groups<F>(modules: &[NssModule], mut lookup: F) -> NssResult<GroupEntry>
+where
+    F: FnMut(NssModule) -> NssResult<Option<GroupEntry>>,
+{
+    let mut merged: Option<GroupEntry> = None;
+    let mut sources = Vec::new();
+    let mut seen_members = HashSet::new();
+    let mut attempts = Vec::new();
+
+    for &module in modules {
+        match lookup(module) {
+            Ok(Some(entry)) => {
+                sources.push(entry.source);
+                let target = merged.get_or_insert_with(|| GroupEntry {
+                    gr_name: entry.gr_name,
+                    gr_gid: entry.gr_gid,
+                    gr_mem: Vec::new(),
+                    source: String::new(),
+                    source_module: entry.source_module,
+                    invalid_members: 0,
+                });
+                target.invalid_members += entry.invalid_members;
+                for member in entry.gr_mem {
+                    if seen_members.insert(member.clone()) {
+                        target.gr_mem.push(member);
+                    }
+                }
+            }
+            Ok(None) => attempts.push((module, NssReturnCode::NotFound)),
+            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => {
+                attempts.push((module, NssReturnCode::Unavail));
+            }
+            // Skip unavailable modules
+            Err(NssError::LibraryError(_)) => attempts.push((module, NssReturnCode::Unavail)),
+            Err(e) => return Err(e),
+        }
+    }
 
-    for mod_enum in modules {
-        let mut entries = Vec::new();
-        for result in itergrp(mod_enum) {
-            match result {
-                Ok(entry) => entries.push(entry),
-                Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => break,
-                Err(NssError::LibraryError(_)) => {
-                    // Library not available (e.g., winbind/sss not installed), skip this module
-                    break;
+    match merged {
+        Some(mut entry) => {
+            if sources.len() > 1 {
+                entry.source_module = None;
+            }
+            entry.source = sources.join("+");
+            Ok(entry)
+        }
+        None => Err(NssError::AllModulesFailed { operation: NssOperation::GetGrNam, attempts }),
+    }
+}
+
+/// Maximum depth of nested groups followed by `expand_group_members`.
+///
+/// The group passed in is depth 0; groups nested more deeply than this are
+/// not expanded and contribute no members.
+pub const MAX_GROUP_EXPANSION_DEPTH: usize = 8;
+
+/// Resolve the members of a group, following nested groups.
+///
+/// A member name that resolves via `getgrnam` is treated as a nested group and
+/// expanded recursively. It is additionally reported as a user only if it also
+/// resolves via `getpwnam` (e.g. user private groups on FILES). Each group is
+/// expanded once, so membership cycles terminate. Users are returned in
+/// discovery order without duplicates.
+///
+/// Every member name costs at least one group lookup, so this can be slow for
+/// large directory-service groups.
+///
+/// # Errors
+/// Returns `NssError` if `group` is not found or a lookup fails.
+pub fn expand_group_members(group: &str, module: Option<NssModule>) -> NssResult<Vec<String>> {
+    let root = getgrnam(group, module)?;
+    expand_members(
+        root,
+        |name| match getgrnam(name, module) {
+            Ok(entry) => Ok(Some(entry)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        },
+        |name| getpwnam(name, module).is_ok(),
+    )
+}
+
+fn expand_members<G, U>(root: GroupEntry, mut find_group: G, mut is_user: U) -> NssResult<Vec<String>>
+where
+    G: FnMut(&str) -> NssResult<Option<GroupEntry>>,
+    U: FnMut(&str) -> bool,
+{
+    let mut visited = HashSet::from([root.gr_name.clone()]);
+    let mut seen_users = HashSet::new();
+    let mut users = Vec::new();
+    let mut pending = vec![(root, 0)];
+
+    while let Some((entry, depth)) = pending.pop() {
+        for member in entry.gr_mem {
+            let is_group = if visited.contains(&member) {
+                true
+            } else if let Some(nested) = find_group(&member)? {
+                visited.insert(member.clone());
+                if depth < MAX_GROUP_EXPANSION_DEPTH {
+                    pending.push((nested, depth + 1));
                 }
-                Err(e) => return Err(e),
+                true
+            } else {
+                false
+            };
+
+            if (!is_group || is_user(&member)) && seen_users.insert(member.clone()) {
+                users.push(member);
             }
         }
-        all_entries.extend(entries);
     }
 
-    Ok(all_entries)
+    Ok(users)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::nss_common::test_support::lock_global_config;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::nss_common::{clear_source_label, set_source_label};
+    use crate::passwd::RawPasswdFields;
+    use libc::passwd;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    thread_local! {
+        static MOCK_PWENT_POS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn mock_success() -> c_int {
+        NssReturnCode::Success as c_int
+    }
+
+    /// Yields root (gid 0), alice (gid 0) and daemon (gid 1).
+    unsafe extern "C" fn mock_getpwent_r(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        let users: [(&'static CStr, gid_t); 3] = [(c"root", 0), (c"alice", 0), (c"daemon", 1)];
+        let pos = MOCK_PWENT_POS.with(|p| p.replace(p.get() + 1));
+        let Some((name, gid)) = users.get(pos) else {
+            return NssReturnCode::NotFound as c_int;
+        };
+
+        (*result).pw_name = name.as_ptr().cast_mut();
+        (*result).pw_uid = pos as libc::uid_t;
+        (*result).pw_gid = *gid;
+        NssReturnCode::Success as c_int
+    }
+
+    fn mock_passwd_enumeration(module: NssModule) {
+        set_mock_function(module, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(module, NssOperation::GetPwEnt, mock_getpwent_r as *mut libc::c_void);
+        set_mock_function(module, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+    }
+
+    unsafe extern "C" fn mock_getgrnam_not_found(
+        _name: *const c_char,
+        _result: *mut group,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        NssReturnCode::NotFound as c_int
+    }
+
+    unsafe extern "C" fn mock_getgrnam_unsorted(
+        _name: *const c_char,
+        result: *mut group,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        const NAMES: [&CStr; 4] = [c"carol", c"alice", c"bob", c"alice"];
+        assert!(buflen >= (NAMES.len() + 2) * mem::size_of::<*mut c_char>());
+
+        let members = buffer.add(buffer.align_offset(mem::align_of::<*mut c_char>())).cast::<*mut c_char>();
+        for (i, name) in NAMES.iter().enumerate() {
+            *members.add(i) = name.as_ptr().cast_mut();
+        }
+        *members.add(NAMES.len()) = std::ptr::null_mut();
+
+        (*result).gr_name = c"staff".as_ptr().cast_mut();
+        (*result).gr_gid = 50;
+        (*result).gr_mem = members;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_members_sorted_dedups() {
+        set_mock_function(NssModule::Files, NssOperation::GetGrNam, mock_getgrnam_unsorted as *mut libc::c_void);
+
+        let entry = getgrnam("staff", Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.gr_mem, ["carol", "alice", "bob", "alice"]);
+        assert_eq!(entry.members_sorted(), ["alice", "bob", "carol"]);
+        assert_eq!(getgrnam_canonical("staff", Some(NssModule::Files)).unwrap().gr_mem, ["alice", "bob", "carol"]);
+
+        clear_mock_functions();
+    }
+
+    const HUGE_GROUP_MEMBERS: usize = 5000;
+
+    /// A group whose member pointer array fills `buffer`, reporting `ERANGE`
+    /// until the buffer is large enough to hold it.
+    unsafe extern "C" fn mock_getgrnam_huge(
+        _name: *const c_char,
+        result: *mut group,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        let ptr_size = mem::size_of::<*mut c_char>();
+        let offset = buffer.align_offset(ptr_size);
+        if buflen < offset + (HUGE_GROUP_MEMBERS + 1) * ptr_size {
+            *errnop = libc::ERANGE;
+            return NssReturnCode::TryAgain as c_int;
+        }
+
+        let members = buffer.add(offset).cast::<*mut c_char>();
+        for i in 0..HUGE_GROUP_MEMBERS {
+            *members.add(i) = c"member".as_ptr().cast_mut();
+        }
+        *members.add(HUGE_GROUP_MEMBERS) = std::ptr::null_mut();
+
+        (*result).gr_name = c"huge".as_ptr().cast_mut();
+        (*result).gr_gid = 5000;
+        (*result).gr_mem = members;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_huge_group_members_allocated_once() {
+        set_mock_function(NssModule::Files, NssOperation::GetGrNam, mock_getgrnam_huge as *mut libc::c_void);
+
+        let entry = getgrnam("huge", Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.gr_mem.len(), HUGE_GROUP_MEMBERS);
+        // Growing by pushes would have left spare capacity
+        assert_eq!(entry.gr_mem.capacity(), HUGE_GROUP_MEMBERS);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_not_found_names_only_queried_modules() {
+        set_mock_function(NssModule::Sss, NssOperation::GetGrNam, mock_getgrnam_not_found as *mut libc::c_void);
+
+        let error = getgrnam("nogroup-here", Some(NssModule::Sss)).unwrap_err();
+        assert!(error.is_not_found());
+        assert!(matches!(
+            error,
+            NssError::AllModulesFailed { ref attempts, .. } if *attempts == [(NssModule::Sss, NssReturnCode::NotFound)]
+        ));
+        assert!(!error.to_string().contains("FILES"));
+
+        clear_mock_functions();
+    }
+
+    fn mock_group(name: &str, members: &[&str]) -> GroupEntry {
+        GroupEntry {
+            gr_name: name.to_string(),
+            gr_gid: 5000,
+            gr_mem: members.iter().map(|m| m.to_string()).collect(),
+            source: "WINBIND".to_string(),
+            source_module: Some(NssModule::Winbind),
+            invalid_members: 0,
+        }
+    }
+
+    fn mock_user(name: &str, gid: gid_t) -> PasswdEntry {
+        PasswdEntry {
+            pw_name: name.to_string(),
+            pw_uid: 1000,
+            pw_gid: gid,
+            pw_gecos: String::new(),
+            pw_dir: format!("/home/{}", name),
+            pw_shell: "/bin/sh".to_string(),
+            source: "WINBIND".to_string(),
+            source_module: Some(NssModule::Winbind),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_group_members_looks_up_each_name_once() {
+        let groups = vec![
+            mock_group("staff", &["alice", "ghost", "bob"]),
+            mock_group("wheel", &["bob", "alice"]),
+            mock_group("empty", &[]),
+        ];
+        let calls = AtomicUsize::new(0);
+        let lookup = |name: &str| {
+            calls.fetch_add(1, AtomicOrdering::Relaxed);
+            Ok((name != "ghost").then(|| mock_user(name, 5000)))
+        };
+
+        let resolved = resolve_group_members(groups, 2, lookup).unwrap();
+        assert_eq!(calls.load(AtomicOrdering::Relaxed), 3);
+
+        let users = |index: usize| -> Vec<&str> { resolved[index].1.iter().map(|u| u.pw_name.as_str()).collect() };
+        assert_eq!(resolved[0].0.gr_name, "staff");
+        assert_eq!(users(0), ["alice", "bob"]);
+        assert_eq!(users(1), ["bob", "alice"]);
+        assert!(users(2).is_empty());
+
+        // Zero threads still resolves, on a single worker
+        let single = resolve_group_members(vec![mock_group("staff", &["alice"])], 0, lookup).unwrap();
+        assert_eq!(single[0].1.len(), 1);
+    }
+
+    #[test]
+    fn test_resolve_group_members_propagates_hard_error() {
+        let result = resolve_group_members(vec![mock_group("staff", &["alice"])], 4, |_| {
+            Err(NssError::NssOperationFailed {
+                errno: libc::EIO as u32,
+                operation: NssOperation::GetPwNam,
+                return_code: NssReturnCode::TryAgain,
+                module: NssModule::Winbind,
+            })
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_users_missing_primary_group() {
+        let users = vec![
+            mock_user("alice", 100),
+            mock_user("orphan", 4242),
+            mock_user("bob", 100),
+            mock_user("stray", 4242),
+        ];
+        let lookups = Cell::new(0);
+
+        let missing = users_missing_primary_group(users, |gid| {
+            lookups.set(lookups.get() + 1);
+            Ok(gid == 100)
+        })
+        .unwrap();
+
+        let names: Vec<&str> = missing.iter().map(|u| u.pw_name.as_str()).collect();
+        assert_eq!(names, ["orphan", "stray"]);
+        // One lookup per distinct gid
+        assert_eq!(lookups.get(), 2);
+    }
+
+    #[test]
+    fn test_resolve_primary_groups_pairs_users() {
+        let users = vec![
+            mock_user("alice", 100),
+            mock_user("orphan", 4242),
+            mock_user("bob", 100),
+        ];
+        let lookups = Cell::new(0);
+
+        let resolved = resolve_primary_groups(users, |user| {
+            lookups.set(lookups.get() + 1);
+            Ok((user.pw_gid == 100).then(|| GroupEntry { gr_gid: 100, ..mock_group("staff", &[]) }))
+        })
+        .unwrap();
+
+        let pairs: Vec<(&str, Option<&str>)> = resolved
+            .iter()
+            .map(|(user, group)| (user.pw_name.as_str(), group.as_ref().map(|g| g.gr_name.as_str())))
+            .collect();
+        assert_eq!(pairs, [("alice", Some("staff")), ("orphan", None), ("bob", Some("staff"))]);
+        // alice and bob share gid 100, which is only looked up once when cached
+        #[cfg(feature = "cache")]
+        assert_eq!(lookups.get(), 2);
+        #[cfg(not(feature = "cache"))]
+        assert_eq!(lookups.get(), 3);
+    }
+
+    #[test]
+    fn test_collect_group_lists_for_many_users() {
+        let group = |name: &str, gid: gid_t, members: &[&str]| GroupEntry { gr_gid: gid, ..mock_group(name, members) };
+        let groups = vec![
+            group("users", 100, &["alice", "bob"]),
+            group("wheel", 10, &["alice"]),
+            group("staff", 50, &["bob", "carol"]),
+            // Primary group that also lists its member
+            group("bob", 1001, &["bob"]),
+        ];
+
+        let lists = collect_group_lists(&["alice", "bob", "nobody"], groups, |user| {
+            Ok(match user {
+                "alice" => Some(1000),
+                "bob" => Some(1001),
+                _ => None,
+            })
+        })
+        .unwrap();
+
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists["alice"], [1000, 100, 10]);
+        assert_eq!(lists["bob"], [1001, 100, 50]);
+        assert!(lists["nobody"].is_empty());
+    }
+
+    fn expand_mock(root: &str, groups: &[GroupEntry], users: &[&str]) -> Vec<String> {
+        let groups: HashMap<String, GroupEntry> =
+            groups.iter().map(|g| (g.gr_name.clone(), g.clone())).collect();
+        expand_members(
+            groups[root].clone(),
+            |name| Ok(groups.get(name).cloned()),
+            |name| users.contains(&name),
+        )
+        .unwrap()
+    }
 
     #[test]
     fn test_group_entry_creation() {
@@ -422,6 +1409,8 @@ mod tests {
             gr_gid: 1000,
             gr_mem: vec!["user1".to_string(), "user2".to_string()],
             source: "files".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
         };
 
         assert_eq!(entry.gr_name, "testgroup");
@@ -438,6 +1427,8 @@ mod tests {
             gr_gid: 2000,
             gr_mem: vec![],
             source: "files".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
         };
 
         assert_eq!(entry.gr_name, "emptygroup");
@@ -461,6 +1452,204 @@ mod tests {
         assert!(!iterator.initialized);
     }
 
+    #[test]
+    fn test_invalid_member_strict_and_lossy() {
+        let invalid = CString::new(vec![b'b', 0xff, b'd']).unwrap();
+        let mut members = [
+            c"alice".as_ptr().cast_mut(),
+            invalid.as_ptr().cast_mut(),
+            c"carol".as_ptr().cast_mut(),
+            std::ptr::null_mut(),
+        ];
+        let mut raw: group = unsafe { mem::zeroed() };
+        raw.gr_name = c"staff".as_ptr().cast_mut();
+        raw.gr_gid = 50;
+        raw.gr_mem = members.as_mut_ptr();
+
+        let strict = unsafe { parse_group_result(&raw, &NssModule::Files, false) };
+        assert!(matches!(strict, Err(NssError::InvalidUtf8)));
+
+        let lossy = unsafe { parse_group_result(&raw, &NssModule::Files, true) }
+            .unwrap()
+            .unwrap();
+        assert_eq!(lossy.gr_mem, ["alice", "b\u{FFFD}d", "carol"]);
+        assert_eq!(lossy.invalid_members, 1);
+    }
+
+    #[test]
+    fn test_source_module_matches_lookup_module() {
+        let _config = lock_global_config();
+        let mut raw: group = unsafe { mem::zeroed() };
+        raw.gr_name = c"staff".as_ptr().cast_mut();
+        raw.gr_gid = 50;
+
+        for module in [NssModule::Files, NssModule::Sss, NssModule::Winbind] {
+            let entry = unsafe { parse_group_result(&raw, &module, false) }.unwrap().unwrap();
+            assert_eq!(entry.source_module, Some(module));
+            assert_eq!(entry.source, module.upper_name());
+        }
+
+        set_source_label(NssModule::Sss, "ldap");
+        let entry = unsafe { parse_group_result(&raw, &NssModule::Sss, false) }.unwrap().unwrap();
+        assert_eq!(entry.source, "LDAP");
+        assert_eq!(entry.source_module, Some(NssModule::Sss));
+        clear_source_label(NssModule::Sss);
+    }
+
+    #[test]
+    fn test_group_entry_ordering() {
+        let mut groups = [
+            mock_group("wheel", &[]),
+            GroupEntry { gr_gid: 0, ..mock_group("root", &[]) },
+            mock_group("audio", &["alice"]),
+        ];
+        groups.sort();
+
+        let names: Vec<&str> = groups.iter().map(|g| g.gr_name.as_str()).collect();
+        assert_eq!(names, ["root", "audio", "wheel"]);
+        assert_eq!(mock_group("audio", &["alice"]), mock_group("audio", &["bob"]));
+    }
+
+    #[test]
+    fn test_group_list_primary_first_and_deduped() {
+        let groups = vec![
+            GroupEntry { gr_gid: 10, ..mock_group("wheel", &["alice"]) },
+            GroupEntry { gr_gid: 100, ..mock_group("users", &["alice", "bob"]) },
+            GroupEntry { gr_gid: 20, ..mock_group("dialout", &["bob"]) },
+            // Same group from a second module
+            GroupEntry { gr_gid: 10, ..mock_group("wheel", &["alice"]) },
+        ];
+
+        assert_eq!(collect_group_list("alice", 100, groups), [100, 10]);
+    }
+
+    #[test]
+    fn test_groups_by_source_spans_modules() {
+        let by_source = groups_by_source(
+            "alice",
+            Some(100),
+            &[NssModule::Files, NssModule::Sss, NssModule::Winbind],
+            |module| {
+                Ok(match module {
+                    NssModule::Files => vec![
+                        GroupEntry { gr_gid: 100, ..mock_group("users", &[]) },
+                        GroupEntry { gr_gid: 10, ..mock_group("wheel", &["alice"]) },
+                        GroupEntry { gr_gid: 20, ..mock_group("dialout", &["bob"]) },
+                    ],
+                    NssModule::Sss => vec![mock_group("sssgroup", &["bob"])],
+                    NssModule::Winbind => vec![mock_group("domain users", &["alice"])],
+                })
+            },
+        )
+        .unwrap();
+
+        let names = |module| -> Vec<&str> { by_source[&module].iter().map(|g| g.gr_name.as_str()).collect() };
+        assert_eq!(names(NssModule::Files), ["users", "wheel"]);
+        assert_eq!(names(NssModule::Winbind), ["domain users"]);
+        assert!(!by_source.contains_key(&NssModule::Sss));
+    }
+
+    #[test]
+    fn test_merge_groups_unions_members() {
+        let merged = merge_groups(&[NssModule::Files, NssModule::Sss, NssModule::Winbind], |module| {
+            Ok(match module {
+                NssModule::Files => Some(GroupEntry {
+                    gr_gid: 100,
+                    source: "FILES".to_string(),
+                    source_module: Some(NssModule::Files),
+                    ..mock_group("staff", &["alice", "bob"])
+                }),
+                NssModule::Sss => None,
+                NssModule::Winbind => Some(mock_group("staff", &["bob", "carol", "alice"])),
+            })
+        })
+        .unwrap();
+
+        assert_eq!(merged.gr_name, "staff");
+        assert_eq!(merged.gr_gid, 100);
+        assert_eq!(merged.gr_mem, ["alice", "bob", "carol"]);
+        assert_eq!(merged.source, "FILES+WINBIND");
+        assert_eq!(merged.source_module, None);
+
+        let single = merge_groups(&[NssModule::Files, NssModule::Winbind], |module| {
+            Ok((module == NssModule::Winbind).then(|| mock_group("staff", &["carol"])))
+        })
+        .unwrap();
+        assert_eq!(single.source, "WINBIND");
+        assert_eq!(single.source_module, Some(NssModule::Winbind));
+    }
+
+    #[test]
+    fn test_merge_groups_not_found() {
+        let result = merge_groups(&[NssModule::Files, NssModule::Winbind], |_| Ok(None));
+        assert!(result.unwrap_err().is_not_found());
+    }
+
+    #[test]
+    fn test_expand_nested_groups() {
+        let groups = [
+            mock_group("A", &["B", "alice"]),
+            mock_group("B", &["bob", "alice"]),
+        ];
+
+        let mut members = expand_mock("A", &groups, &["alice", "bob"]);
+        members.sort();
+        assert_eq!(members, ["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_expand_group_cycles_terminate() {
+        let groups = [
+            mock_group("A", &["A", "B"]),
+            mock_group("B", &["A", "carol"]),
+        ];
+
+        assert_eq!(expand_mock("A", &groups, &["carol"]), ["carol"]);
+    }
+
+    #[test]
+    fn test_expand_keeps_user_private_groups() {
+        // "dave" is both a user and a same-named (empty) group
+        let groups = [mock_group("staff", &["dave"]), mock_group("dave", &[])];
+
+        assert_eq!(expand_mock("staff", &groups, &["dave"]), ["dave"]);
+    }
+
+    #[test]
+    fn test_expand_depth_is_capped() {
+        let groups: Vec<GroupEntry> = (0..=MAX_GROUP_EXPANSION_DEPTH + 1)
+            .map(|i| {
+                let next = format!("G{}", i + 1);
+                let user = format!("user{}", i);
+                mock_group(&format!("G{}", i), &[next.as_str(), user.as_str()])
+            })
+            .collect();
+        let users: Vec<String> = (0..=MAX_GROUP_EXPANSION_DEPTH + 1).map(|i| format!("user{}", i)).collect();
+        let user_refs: Vec<&str> = users.iter().map(String::as_str).collect();
+
+        let members = expand_mock("G0", &groups, &user_refs);
+        assert_eq!(members.len(), MAX_GROUP_EXPANSION_DEPTH + 1);
+        assert!(!members.contains(&format!("user{}", MAX_GROUP_EXPANSION_DEPTH + 1)));
+    }
+
+    #[test]
+    fn test_all_members_includes_primary_group_users() {
+        mock_passwd_enumeration(NssModule::Files);
+        let entry = GroupEntry {
+            gr_name: "root".to_string(),
+            gr_gid: 0,
+            gr_mem: vec!["alice".to_string(), "bob".to_string()],
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
+        };
+
+        let members = entry.all_members(Some(NssModule::Files)).unwrap();
+        assert_eq!(members, ["alice", "bob", "root"]);
+
+        clear_mock_functions();
+    }
+
     // Note: Most NSS function tests would require actual NSS libraries to be present
     // and would be better suited for integration tests rather than unit tests
 }
\ No newline at end of file
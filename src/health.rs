@@ -0,0 +1,125 @@
+use libc::uid_t;
+use std::time::{Duration, Instant};
+
+use crate::nss_common::get_nss_function;
+use crate::{getpwuid, NssModule, NssOperation};
+
+/// UID resolved by `health_check`; root exists on every system.
+pub const HEALTH_CHECK_DEFAULT_UID: uid_t = 0;
+
+/// Result of an end-to-end health check against a single NSS module.
+#[derive(Debug, Clone)]
+pub struct ModuleHealth {
+    pub module: NssModule,
+    /// The module library loaded and exports `getpwuid_r`.
+    pub available: bool,
+    /// The probe UID was resolved by the module.
+    pub resolved: bool,
+    /// Time spent on the probe lookup (zero if the module is unavailable).
+    pub latency: Duration,
+    /// Description of the load or lookup failure, if any.
+    pub error: Option<String>,
+}
+
+/// Check that each module can actually answer queries by resolving uid 0.
+///
+/// This goes further than loading the library: a module such as winbind may
+/// load fine but be unable to reach its directory service.
+#[must_use]
+pub fn health_check(modules: &[NssModule]) -> Vec<ModuleHealth> {
+    health_check_uid(modules, HEALTH_CHECK_DEFAULT_UID)
+}
+
+/// Like `health_check`, but probes each module with the given UID.
+#[must_use]
+pub fn health_check_uid(modules: &[NssModule], uid: uid_t) -> Vec<ModuleHealth> {
+    modules.iter().map(|&module| check_module(module, uid)).collect()
+}
+
+fn check_module(module: NssModule, uid: uid_t) -> ModuleHealth {
+    if let Err(e) = unsafe { get_nss_function(NssOperation::GetPwUid, module) } {
+        return ModuleHealth {
+            module,
+            available: false,
+            resolved: false,
+            latency: Duration::ZERO,
+            error: Some(e.to_string()),
+        };
+    }
+
+    let start = Instant::now();
+    let result = getpwuid(uid, Some(module));
+    let latency = start.elapsed();
+
+    ModuleHealth {
+        module,
+        available: true,
+        resolved: result.is_ok(),
+        latency,
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::NssReturnCode;
+    use libc::{c_char, c_int, passwd};
+
+    unsafe extern "C" fn mock_getpwuid_root(
+        uid: uid_t,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        if uid != 0 {
+            return NssReturnCode::NotFound as c_int;
+        }
+        (*result).pw_name = c"root".as_ptr().cast_mut();
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwuid_unavail(
+        _uid: uid_t,
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        NssReturnCode::Unavail as c_int
+    }
+
+    #[test]
+    fn test_health_check_reports_per_module() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_root as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwUid, mock_getpwuid_unavail as *mut libc::c_void);
+
+        let report = health_check(&[NssModule::Files, NssModule::Sss]);
+        assert_eq!(report.len(), 2);
+
+        assert_eq!(report[0].module, NssModule::Files);
+        assert!(report[0].available);
+        assert!(report[0].resolved);
+        assert!(report[0].error.is_none());
+
+        assert_eq!(report[1].module, NssModule::Sss);
+        assert!(report[1].available);
+        assert!(!report[1].resolved);
+        assert!(report[1].error.is_some());
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_health_check_custom_uid() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_root as *mut libc::c_void);
+
+        let report = health_check_uid(&[NssModule::Files], 4242);
+        assert!(report[0].available);
+        assert!(!report[0].resolved);
+
+        clear_mock_functions();
+    }
+}
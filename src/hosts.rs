@@ -0,0 +1,233 @@
+use libc::{c_char, c_int, c_void, hostent, socklen_t, AF_INET, AF_INET6};
+use std::ffi::CStr;
+use std::mem;
+use std::net::IpAddr;
+
+use crate::{NssError, NssResult, NssModule, NssOperation, NssReturnCode};
+use crate::metrics;
+use crate::nss_common::{NssFn, NssSymbol};
+use crate::nsswitch::{lookup_chain, FallbackPolicy};
+
+const HOST_INIT_BUFLEN: usize = 1024;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HostEntry {
+    pub h_name: String,
+    pub h_aliases: Vec<String>,
+    pub h_addr_list: Vec<IpAddr>,
+    pub source: String,
+}
+
+/// Read a NULL-terminated array of C pointers.
+unsafe fn null_terminated(list: *mut *mut c_char) -> impl Iterator<Item = *mut c_char> {
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        if list.is_null() {
+            return None;
+        }
+        let item = *list.offset(i);
+        if item.is_null() {
+            return None;
+        }
+        i += 1;
+        Some(item)
+    })
+}
+
+unsafe fn parse_host_result(
+    result: *const hostent,
+    module: &NssModule,
+) -> NssResult<Option<HostEntry>> {
+    if result.is_null() {
+        return Ok(None);
+    }
+
+    let host_ref = &*result;
+
+    if host_ref.h_name.is_null() {
+        return Ok(None);
+    }
+
+    let h_name = CStr::from_ptr(host_ref.h_name)
+        .to_str()
+        .map_err(|_| NssError::InvalidUtf8)?
+        .to_string();
+
+    let mut h_aliases = Vec::new();
+    for alias in null_terminated(host_ref.h_aliases) {
+        let alias = CStr::from_ptr(alias)
+            .to_str()
+            .map_err(|_| NssError::InvalidUtf8)?
+            .to_string();
+        h_aliases.push(alias);
+    }
+
+    let h_addr_list = null_terminated(host_ref.h_addr_list)
+        .filter_map(|addr| match (host_ref.h_addrtype, host_ref.h_length) {
+            (AF_INET, 4) => Some(IpAddr::from(*addr.cast::<[u8; 4]>())),
+            (AF_INET6, 16) => Some(IpAddr::from(*addr.cast::<[u8; 16]>())),
+            _ => None,
+        })
+        .collect();
+
+    Ok(Some(HostEntry {
+        h_name,
+        h_aliases,
+        h_addr_list,
+        source: module.source_label(),
+    }))
+}
+
+struct GetHostByAddrFn;
+
+unsafe impl NssSymbol for GetHostByAddrFn {
+    type Fn = unsafe extern "C" fn(
+        addr: *const c_void,
+        len: socklen_t,
+        af: c_int,
+        result: *mut hostent,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+        h_errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetHostByAddr;
+}
+
+unsafe fn gethostbyaddr_r_impl(
+    addr: IpAddr,
+    module: NssModule,
+    buffer_len: usize,
+) -> NssResult<Option<HostEntry>> {
+    let buffer_len = metrics::initial_buflen(NssOperation::GetHostByAddr, buffer_len);
+    let gethostbyaddr_r = NssFn::<GetHostByAddrFn>::load(module)?.get();
+
+    // Raw network-order bytes, sized to match the address family
+    let (addr_bytes, family) = match addr {
+        IpAddr::V4(ip) => (ip.octets().to_vec(), AF_INET),
+        IpAddr::V6(ip) => (ip.octets().to_vec(), AF_INET6),
+    };
+
+    let mut result: hostent = mem::zeroed();
+    let mut buffer = vec![0u8; buffer_len];
+    let mut errno: c_int = 0;
+    let mut h_errno: c_int = 0;
+
+    let ret_code = gethostbyaddr_r(
+        addr_bytes.as_ptr().cast::<c_void>(),
+        addr_bytes.len() as socklen_t,
+        family,
+        &mut result,
+        buffer.as_mut_ptr().cast::<c_char>(),
+        buffer_len,
+        &mut errno,
+        &mut h_errno,
+    );
+
+    match errno {
+        0 => metrics::record_buflen(NssOperation::GetHostByAddr, buffer_len), // Success
+        libc::ERANGE => {
+            // Buffer too small, try with larger buffer
+            return gethostbyaddr_r_impl(addr, module, buffer_len * 2);
+        }
+        _ => {
+            return Err(NssError::from_errno(errno, NssOperation::GetHostByAddr, NssReturnCode::from(ret_code), module));
+        }
+    }
+
+    let nss_code = NssReturnCode::from(ret_code);
+    if nss_code == NssReturnCode::NotFound {
+        return Ok(None);
+    }
+
+    if nss_code != NssReturnCode::Success {
+        return Err(NssError::NssOperationFailed {
+            errno: errno.unsigned_abs(),
+            operation: NssOperation::GetHostByAddr,
+            return_code: nss_code,
+            module,
+        });
+    }
+
+    parse_host_result(&result, &module)
+}
+
+/// Get host entry by address (reverse lookup).
+///
+/// Both IPv4 and IPv6 addresses are supported; the address family passed to
+/// the module follows the variant of `addr`.
+///
+/// # Errors
+/// Returns `NssError` if the address is not found or NSS operation fails.
+pub fn gethostbyaddr(addr: IpAddr, module: Option<NssModule>) -> NssResult<HostEntry> {
+    lookup_chain(NssOperation::GetHostByAddr, module, FallbackPolicy::default(), |mod_enum| unsafe {
+        gethostbyaddr_r_impl(addr, mod_enum, HOST_INIT_BUFLEN)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    const NAME: &CStr = c"localhost";
+    const ALIAS: &CStr = c"localhost.localdomain";
+
+    /// Answers 127.0.0.1 with "localhost", building the entry inside `buffer`
+    /// the way a real module does.
+    unsafe extern "C" fn mock_gethostbyaddr_localhost(
+        addr: *const c_void,
+        len: socklen_t,
+        af: c_int,
+        result: *mut hostent,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        _errnop: *mut c_int,
+        _h_errnop: *mut c_int,
+    ) -> c_int {
+        if af != AF_INET || len != 4 || *addr.cast::<[u8; 4]>() != [127, 0, 0, 1] {
+            return NssReturnCode::NotFound as c_int;
+        }
+        assert!(buflen >= 64);
+
+        // Layout: [addr_list: 2 ptrs][aliases: 2 ptrs][addr: 4 bytes][NAME][ALIAS]
+        let buffer = buffer.add(buffer.align_offset(mem::align_of::<*mut c_char>()));
+        let ptrs = buffer.cast::<*mut c_char>();
+        let addr_bytes = buffer.add(4 * mem::size_of::<*mut c_char>());
+        let name = addr_bytes.add(4);
+        let alias = name.add(NAME.to_bytes_with_nul().len());
+
+        std::ptr::copy_nonoverlapping([127u8, 0, 0, 1].as_ptr(), addr_bytes.cast::<u8>(), 4);
+        std::ptr::copy_nonoverlapping(NAME.as_ptr(), name, NAME.to_bytes_with_nul().len());
+        std::ptr::copy_nonoverlapping(ALIAS.as_ptr(), alias, ALIAS.to_bytes_with_nul().len());
+        *ptrs = addr_bytes;
+        *ptrs.add(1) = std::ptr::null_mut();
+        *ptrs.add(2) = alias;
+        *ptrs.add(3) = std::ptr::null_mut();
+
+        (*result).h_name = name;
+        (*result).h_aliases = ptrs.add(2);
+        (*result).h_addrtype = AF_INET;
+        (*result).h_length = 4;
+        (*result).h_addr_list = ptrs;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_gethostbyaddr_parses_reverse_result() {
+        set_mock_function(NssModule::Files, NssOperation::GetHostByAddr, mock_gethostbyaddr_localhost as *mut c_void);
+
+        let entry = gethostbyaddr(IpAddr::V4(Ipv4Addr::LOCALHOST), Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.h_name, "localhost");
+        assert_eq!(entry.h_aliases, ["localhost.localdomain"]);
+        assert_eq!(entry.h_addr_list, [IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+        assert_eq!(entry.source, "FILES");
+
+        let miss = gethostbyaddr(IpAddr::V6(Ipv6Addr::LOCALHOST), Some(NssModule::Files));
+        assert!(miss.unwrap_err().is_not_found());
+
+        clear_mock_functions();
+    }
+}
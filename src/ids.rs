@@ -0,0 +1,152 @@
+//! Distinct types for user and group IDs.
+//!
+//! `uid_t` and `gid_t` are both `u32`, so passing a gid where a uid is
+//! expected compiles. `Uid` and `Gid` do not convert into each other; the
+//! raw-integer functions stay available alongside them.
+
+use libc::{gid_t, uid_t};
+use std::fmt;
+
+use crate::{getgrgid, getpwuid, GroupEntry, NssModule, NssResult, PasswdEntry};
+
+/// A user ID.
+///
+/// ```compile_fail
+/// use truenas_rust_nss::{Gid, Uid};
+///
+/// fn owner(_uid: Uid) {}
+/// owner(Gid::from(100));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Uid(pub uid_t);
+
+/// A group ID.
+///
+/// ```compile_fail
+/// use truenas_rust_nss::{Gid, Uid};
+///
+/// let _ = Gid::lookup(Uid::from(0), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+pub struct Gid(pub gid_t);
+
+impl Uid {
+    /// Get the password entry for this uid; see `getpwuid`.
+    ///
+    /// # Errors
+    /// Returns `NssError` if the user is not found or NSS operation fails.
+    pub fn lookup(self, module: Option<NssModule>) -> NssResult<PasswdEntry> {
+        getpwuid(self.0, module)
+    }
+}
+
+impl Gid {
+    /// Get the group entry for this gid; see `getgrgid`.
+    ///
+    /// # Errors
+    /// Returns `NssError` if the group is not found or NSS operation fails.
+    pub fn lookup(self, module: Option<NssModule>) -> NssResult<GroupEntry> {
+        getgrgid(self.0, module)
+    }
+}
+
+impl From<uid_t> for Uid {
+    fn from(uid: uid_t) -> Self {
+        Uid(uid)
+    }
+}
+
+impl From<Uid> for uid_t {
+    fn from(uid: Uid) -> Self {
+        uid.0
+    }
+}
+
+impl From<gid_t> for Gid {
+    fn from(gid: gid_t) -> Self {
+        Gid(gid)
+    }
+}
+
+impl From<Gid> for gid_t {
+    fn from(gid: Gid) -> Self {
+        gid.0
+    }
+}
+
+impl fmt::Display for Uid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for Gid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PasswdEntry {
+    /// `pw_uid` as a `Uid`.
+    #[must_use]
+    pub fn uid(&self) -> Uid {
+        Uid(self.pw_uid)
+    }
+
+    /// `pw_gid` as a `Gid`.
+    #[must_use]
+    pub fn gid(&self) -> Gid {
+        Gid(self.pw_gid)
+    }
+}
+
+impl GroupEntry {
+    /// `gr_gid` as a `Gid`.
+    #[must_use]
+    pub fn gid(&self) -> Gid {
+        Gid(self.gr_gid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::{NssOperation, NssReturnCode};
+    use libc::{c_char, c_int, passwd};
+
+    unsafe extern "C" fn mock_getpwuid_daemon(
+        uid: uid_t,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        if uid != 1 {
+            return NssReturnCode::NotFound as c_int;
+        }
+        (*result).pw_name = c"daemon".as_ptr().cast_mut();
+        (*result).pw_uid = 1;
+        (*result).pw_gid = 2;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_uid_lookup_round_trip() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_daemon as *mut libc::c_void);
+
+        let entry = Uid::from(1).lookup(Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.pw_name, "daemon");
+        assert_eq!(entry.uid(), Uid(1));
+        assert_eq!(entry.gid(), Gid(2));
+        assert_eq!(uid_t::from(entry.uid()), entry.pw_uid);
+        assert_eq!(entry.uid().to_string(), "1");
+
+        // 2 is daemon's gid, not a uid the mock knows
+        assert!(Uid::from(2).lookup(Some(NssModule::Files)).unwrap_err().is_not_found());
+
+        clear_mock_functions();
+    }
+}
@@ -0,0 +1,75 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{GroupEntry, PasswdEntry};
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    // Every field is a string, integer, list or the module enum, all of
+    // which map to JSON
+    serde_json::to_value(value).expect("entry fields are always representable as JSON")
+}
+
+impl PasswdEntry {
+    /// The entry as a JSON object, for embedding in a larger document
+    /// without serializing to a string and parsing it back.
+    ///
+    /// Keys are the field names (`pw_name`, `pw_uid`, ...). As with the other
+    /// serde formats, the raw bytes behind the `*_os` accessors are left out.
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        to_value(self)
+    }
+}
+
+impl GroupEntry {
+    /// The entry as a JSON object; see `PasswdEntry::to_value`.
+    #[must_use]
+    pub fn to_value(&self) -> Value {
+        to_value(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::passwd::RawPasswdFields;
+    use crate::NssModule;
+
+    #[test]
+    fn test_passwd_to_value() {
+        let entry = PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1001,
+            pw_gecos: "Test User".to_string(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: "/bin/bash".to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        };
+
+        let value = entry.to_value();
+        assert_eq!(value["pw_uid"], 1000);
+        assert_eq!(value["pw_name"], "testuser");
+        assert!(value.get("raw").is_none());
+    }
+
+    #[test]
+    fn test_group_to_value() {
+        let entry = GroupEntry {
+            gr_name: "staff".to_string(),
+            gr_gid: 50,
+            gr_mem: vec!["alice".to_string(), "bob".to_string()],
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            invalid_members: 0,
+        };
+
+        let value = entry.to_value();
+        assert_eq!(value["gr_gid"], 50);
+        assert_eq!(value["gr_mem"], serde_json::json!(["alice", "bob"]));
+    }
+}
@@ -3,14 +3,44 @@ pub mod error;
 pub mod nss_common;
 pub mod passwd;
 pub mod group;
+mod fingerprint;
+mod ids;
+mod line;
+pub mod health;
+pub mod hosts;
+pub mod metrics;
+pub mod nsswitch;
+mod options;
+pub mod shells;
+pub mod snapshot;
+mod sss;
+
+#[cfg(feature = "bincode")]
+pub mod binary;
+
+#[cfg(feature = "serde")]
+pub mod json;
+
+#[cfg(feature = "users-compat")]
+pub mod users_compat;
 
 #[cfg(feature = "python")]
 pub mod python_bindings;
 
-pub use error::{NssError, NssResult};
-pub use nss_common::{NssModule, NssOperation, NssReturnCode};
-pub use passwd::{PasswdEntry, PasswdIterator, getpwnam, getpwuid, getpwall, iterpw};
-pub use group::{GroupEntry, GroupIterator, getgrnam, getgrgid, getgrall, itergrp};
+pub use error::{NssError, NssResult, explain};
+pub use ids::{Gid, Uid};
+pub use nss_common::{NssModule, NssOperation, NssReturnCode, NssFn, NssSymbol, module_capabilities, set_module_path, clear_module_path, set_module_search_paths, register_module_handle, enable_strict_paths, disable_strict_paths, loaded_module_path, module_soname, is_cached, warm_up, end_all_enumerations, set_symbol_template, clear_symbol_template, set_source_label, clear_source_label};
+pub use options::LookupOptions;
+pub use passwd::{PasswdEntry, RawPasswdFields, PasswdIterator, getpwnam, getpwuid, getpwnam_opts, getpwuid_opts, getpwnam_self, getpwuid_self, getpwuid_fields, getpwnam_fields, home_dir, shell, Fields, getpwall, getpwall_with_capacity, getpwall_page, getpwall_sorted, getpwall_by_name, getpwall_by_uid, getpwall_best_effort, count_passwd, for_each_passwd, merge_sorted_passwd, getpwnam_best_effort, FieldError, getpwnam_race, getpwnam_all_sources, which_modules_have_user, getpwnam_with_policy, getpwnam_deadline, getpwnam_via_lib, getpwnam_raw, getpwnam_reuse, getpwnam_nth, PwLookupScratch, getpwnam_prefix, getpwnam_prefix_ignore_case, split_domain_user, DEFAULT_WINBIND_SEPARATOR, iterpw, iterpw_all_tagged, iterpw_lazy, LazyPasswdEntry, LazyPasswdIterator, uid_exists, verify_name_uid, find_uid_collisions, SortKey};
+pub use group::{GroupEntry, GroupIterator, getgrnam, getgrgid, getgrnam_opts, getgrgid_opts, getgrnam_canonical, getgrgid_self, getgrall, getgrall_by_name, getgrall_by_gid, getgrall_with_members, count_group, gid_exists, find_gid_collisions, verify_primary_groups, getpwall_with_primary_groups, getgrnam_merged, getgrouplist_compat, getgrouplist_many, user_groups_by_source, itergrp, expand_group_members};
+pub use health::{ModuleHealth, health_check, health_check_uid};
+pub use hosts::{HostEntry, gethostbyaddr};
+pub use metrics::{MetricsSnapshot, metrics_snapshot, enable_adaptive_buffers, disable_adaptive_buffers};
+#[cfg(feature = "metrics")]
+pub use metrics::metrics_prometheus;
+pub use snapshot::{PasswdSnapshot, SnapshotDiff};
+pub use sss::getpwnam_ex;
+pub use nsswitch::{default_chain, default_modules, effective_passwd_chain, effective_group_chain, disable_module, enable_module, set_default_modules, clear_default_modules, set_files_authoritative, clear_files_authoritative, is_local_service_name, parse_nsswitch_chain, parse_nsswitch_services, FallbackPolicy, NsswitchAction, NsswitchService};
 
 #[cfg(feature = "python")]
 use pyo3::prelude::*;
@@ -0,0 +1,137 @@
+//! Parsing of `/etc/passwd` and `/etc/group` style lines into entries.
+//!
+//! Input may come from untrusted backups, so every malformed line is reported
+//! as `NssError::MalformedLine` rather than panicking. Parsed entries were not
+//! returned by a module, so `source` is empty and `source_module` is `None`.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::passwd::RawPasswdFields;
+use crate::{GroupEntry, NssError, PasswdEntry};
+
+/// Split `line` into exactly `N` colon-separated fields.
+fn fields<'a, const N: usize>(line: &'a str, kind: &str) -> Result<[&'a str; N], NssError> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    if line.contains('\0') {
+        return Err(NssError::MalformedLine(format!("{kind} line contains a NUL byte")));
+    }
+
+    let parts: Vec<&str> = line.split(':').collect();
+    parts
+        .try_into()
+        .map_err(|parts: Vec<&str>| NssError::MalformedLine(format!("{kind} line has {} fields, expected {N}", parts.len())))
+}
+
+fn parse_id(value: &str, what: &str) -> Result<u32, NssError> {
+    value
+        .parse()
+        .map_err(|_| NssError::MalformedLine(format!("{what} {value:?} is not a number between 0 and {}", u32::MAX)))
+}
+
+/// Parses a `passwd(5)` line: `name:password:uid:gid:gecos:dir:shell`.
+///
+/// The password field is ignored.
+impl FromStr for PasswdEntry {
+    type Err = NssError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let [name, _, uid, gid, gecos, dir, shell] = fields::<7>(line, "passwd")?;
+        if name.is_empty() {
+            return Err(NssError::MalformedLine("passwd line has an empty name".to_string()));
+        }
+
+        Ok(PasswdEntry {
+            pw_name: name.to_string(),
+            pw_uid: parse_id(uid, "uid")?,
+            pw_gid: parse_id(gid, "gid")?,
+            pw_gecos: gecos.to_string(),
+            pw_dir: dir.to_string(),
+            pw_shell: shell.to_string(),
+            source: String::new(),
+            source_module: None,
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        })
+    }
+}
+
+/// Parses a `group(5)` line: `name:password:gid:member1,member2`.
+///
+/// The password field is ignored; an empty member list gives no members.
+impl FromStr for GroupEntry {
+    type Err = NssError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let [name, _, gid, members] = fields::<4>(line, "group")?;
+        if name.is_empty() {
+            return Err(NssError::MalformedLine("group line has an empty name".to_string()));
+        }
+
+        Ok(GroupEntry {
+            gr_name: name.to_string(),
+            gr_gid: parse_id(gid, "gid")?,
+            gr_mem: members.split(',').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+            source: String::new(),
+            source_module: None,
+            invalid_members: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_parse_passwd_line() {
+        let entry: PasswdEntry = "alice:x:1000:100:Alice,,,:/home/alice:/bin/sh\n".parse().unwrap();
+        assert_eq!(entry.pw_name, "alice");
+        assert_eq!(entry.pw_uid, 1000);
+        assert_eq!(entry.pw_gid, 100);
+        assert_eq!(entry.pw_gecos, "Alice,,,");
+        assert_eq!(entry.pw_dir, "/home/alice");
+        assert_eq!(entry.pw_shell, "/bin/sh");
+        assert!(entry.source_module.is_none());
+
+        let group: GroupEntry = "staff:x:50:alice,bob".parse().unwrap();
+        assert_eq!(group.gr_gid, 50);
+        assert_eq!(group.gr_mem, ["alice", "bob"]);
+        assert!("empty:x:51:".parse::<GroupEntry>().unwrap().gr_mem.is_empty());
+    }
+
+    #[test]
+    fn test_forty_digit_uid_is_rejected() {
+        let line = format!("alice:x:{}:100::/home/alice:/bin/sh", "9".repeat(40));
+        assert!(matches!(line.parse::<PasswdEntry>(), Err(NssError::MalformedLine(_))));
+    }
+
+    #[test]
+    fn test_malformed_lines_are_rejected() {
+        for line in ["", "alice:x:1000", "alice:x:1000:100::/home/alice:/bin/sh:extra", ":x:0:0:::", "a\0:x:0:0:::", "alice:x:-1:0:::"] {
+            assert!(matches!(line.parse::<PasswdEntry>(), Err(NssError::MalformedLine(_))), "{line:?}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_arbitrary_bytes_never_panic(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let line = String::from_utf8_lossy(&bytes);
+            let _ = line.parse::<PasswdEntry>();
+            let _ = line.parse::<GroupEntry>();
+        }
+
+        #[test]
+        fn prop_numeric_fields_parse_or_error(uid in "[0-9]{0,45}", gid in "-?[0-9]{0,45}") {
+            let line = format!("user:x:{uid}:{gid}:gecos:/home/user:/bin/sh");
+            match line.parse::<PasswdEntry>() {
+                Ok(entry) => {
+                    prop_assert_eq!(Ok(entry.pw_uid), uid.parse::<u32>());
+                    prop_assert_eq!(Ok(entry.pw_gid), gid.parse::<u32>());
+                }
+                Err(e) => prop_assert!(matches!(e, NssError::MalformedLine(_))),
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::nss_common::{NssModule, NssOperation, ALL_MODULES, ALL_OPERATIONS, MODULE_COUNT, OPERATION_COUNT};
+
+/// Largest buffer each operation has needed, indexed by `NssOperation::as_index`.
+static MAX_BUFLEN: [AtomicUsize; OPERATION_COUNT] =
+    [const { AtomicUsize::new(0) }; OPERATION_COUNT];
+
+/// NSS calls made, indexed by `NssModule::as_index` then `NssOperation::as_index`.
+static LOOKUPS: [[AtomicUsize; OPERATION_COUNT]; MODULE_COUNT] =
+    [const { [const { AtomicUsize::new(0) }; OPERATION_COUNT] }; MODULE_COUNT];
+
+static ADAPTIVE_BUFFERS: AtomicBool = AtomicBool::new(false);
+
+/// Start each lookup at the largest buffer its operation has needed so far,
+/// process-wide.
+///
+/// Without this, every call starts at the initial size and doubles on
+/// `ERANGE` until the record fits, repeating that work for each lookup of a
+/// large entry (e.g. a group with many members). With it, only the first
+/// large lookup per operation pays for the retries; the cost is that later
+/// lookups of small entries also allocate the larger buffer.
+pub fn enable_adaptive_buffers() {
+    ADAPTIVE_BUFFERS.store(true, Ordering::Relaxed);
+}
+
+/// Go back to starting every lookup at the initial buffer size.
+pub fn disable_adaptive_buffers() {
+    ADAPTIVE_BUFFERS.store(false, Ordering::Relaxed);
+}
+
+/// Buffer size to start an `operation` call with, given the caller's `buflen`.
+pub(crate) fn initial_buflen(operation: NssOperation, buflen: usize) -> usize {
+    if ADAPTIVE_BUFFERS.load(Ordering::Relaxed) {
+        buflen.max(MAX_BUFLEN[operation.as_index()].load(Ordering::Relaxed))
+    } else {
+        buflen
+    }
+}
+
+/// Record that an NSS function for `operation` is about to be called on `module`.
+pub(crate) fn record_lookup(operation: NssOperation, module: NssModule) {
+    LOOKUPS[module.as_index()][operation.as_index()].fetch_add(1, Ordering::Relaxed);
+}
+
+fn lookups(module: NssModule, operation: NssOperation) -> usize {
+    LOOKUPS[module.as_index()][operation.as_index()].load(Ordering::Relaxed)
+}
+
+/// Record that an NSS call for `operation` completed without `ERANGE` using a
+/// buffer of `buflen` bytes.
+pub(crate) fn record_buflen(operation: NssOperation, buflen: usize) {
+    MAX_BUFLEN[operation.as_index()].fetch_max(buflen, Ordering::Relaxed);
+}
+
+/// Point-in-time copy of the process-wide lookup metrics.
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    /// Largest buffer, in bytes, that each operation needed to complete.
+    ///
+    /// Every operation is present; 0 means it has not been called yet. A value
+    /// equal to the initial buffer size means no call ever hit `ERANGE`.
+    pub max_buflen: HashMap<NssOperation, usize>,
+    /// Number of NSS function calls per module and operation.
+    ///
+    /// Every pair is present. Each enumeration step counts as one call, and
+    /// calls that fail (including failures to load the module) are counted.
+    pub lookups: HashMap<(NssModule, NssOperation), usize>,
+}
+
+/// Capture the current metrics.
+#[must_use]
+pub fn metrics_snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        max_buflen: ALL_OPERATIONS
+            .iter()
+            .map(|&operation| (operation, MAX_BUFLEN[operation.as_index()].load(Ordering::Relaxed)))
+            .collect(),
+        lookups: ALL_MODULES
+            .iter()
+            .flat_map(|&module| ALL_OPERATIONS.iter().map(move |&operation| (module, operation)))
+            .map(|(module, operation)| ((module, operation), lookups(module, operation)))
+            .collect(),
+    }
+}
+
+/// Label value for `operation`: its NSS function suffix without `_r`.
+#[cfg(feature = "metrics")]
+fn op_label(operation: NssOperation) -> &'static str {
+    let name = operation.function_name();
+    name.strip_suffix("_r").unwrap_or(name)
+}
+
+/// Render the current metrics in Prometheus text exposition format.
+///
+/// Emits `tnss_lookups_total{module,op}` (a counter of NSS calls) and
+/// `tnss_max_buflen_bytes{op}` (a gauge of the largest buffer needed), e.g.
+/// `tnss_lookups_total{module="files",op="getpwnam"} 42`. Series are always
+/// written in the same order, including those still at 0.
+#[cfg(feature = "metrics")]
+#[must_use]
+pub fn metrics_prometheus() -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "# HELP tnss_lookups_total NSS function calls by module and operation.");
+    let _ = writeln!(out, "# TYPE tnss_lookups_total counter");
+    for &module in &ALL_MODULES {
+        for &operation in ALL_OPERATIONS {
+            let _ = writeln!(
+                out,
+                "tnss_lookups_total{{module=\"{}\",op=\"{}\"}} {}",
+                module.name(),
+                op_label(operation),
+                lookups(module, operation)
+            );
+        }
+    }
+
+    let _ = writeln!(out, "# HELP tnss_max_buflen_bytes Largest buffer each operation has needed.");
+    let _ = writeln!(out, "# TYPE tnss_max_buflen_bytes gauge");
+    for &operation in ALL_OPERATIONS {
+        let _ = writeln!(
+            out,
+            "tnss_max_buflen_bytes{{op=\"{}\"}} {}",
+            op_label(operation),
+            MAX_BUFLEN[operation.as_index()].load(Ordering::Relaxed)
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::lock_global_config;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::{getpwnam, NssModule, NssReturnCode};
+    use libc::{c_char, c_int, passwd};
+
+    const MOCK_REQUIRED_BUFLEN: usize = 4096;
+
+    unsafe extern "C" fn mock_getpwnam_large(
+        _name: *const c_char,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        if buflen < MOCK_REQUIRED_BUFLEN {
+            *errnop = libc::ERANGE;
+            return NssReturnCode::TryAgain as c_int;
+        }
+
+        (*result).pw_name = c"bigUser".as_ptr().cast_mut();
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_max_buflen_records_grown_buffer() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_large as *mut libc::c_void);
+
+        let entry = getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.pw_name, "bigUser");
+
+        let snapshot = metrics_snapshot();
+        assert!(snapshot.max_buflen[&NssOperation::GetPwNam] >= MOCK_REQUIRED_BUFLEN);
+        assert_eq!(snapshot.max_buflen.len(), ALL_OPERATIONS.len());
+
+        clear_mock_functions();
+    }
+
+    thread_local! {
+        static FIRST_BUFLEN: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    /// Like `mock_getpwnam_large`, remembering the size of the first attempt.
+    unsafe extern "C" fn mock_getpwnam_large_recording(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        FIRST_BUFLEN.with(|first| {
+            if first.get() == 0 {
+                first.set(buflen);
+            }
+        });
+        mock_getpwnam_large(name, result, buffer, buflen, errnop)
+    }
+
+    #[test]
+    fn test_adaptive_buffers_start_at_recorded_size() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_large_recording as *mut libc::c_void);
+
+        // Off by default: every call starts at the initial size
+        getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        FIRST_BUFLEN.with(|first| first.set(0));
+        getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        assert!(FIRST_BUFLEN.with(std::cell::Cell::get) < MOCK_REQUIRED_BUFLEN);
+
+        enable_adaptive_buffers();
+        FIRST_BUFLEN.with(|first| first.set(0));
+        getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        assert!(FIRST_BUFLEN.with(std::cell::Cell::get) >= MOCK_REQUIRED_BUFLEN);
+
+        disable_adaptive_buffers();
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_snapshot_counts_lookups() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_large as *mut libc::c_void);
+
+        getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        let snapshot = metrics_snapshot();
+        assert!(snapshot.lookups[&(NssModule::Files, NssOperation::GetPwNam)] >= 1);
+        assert_eq!(snapshot.lookups.len(), ALL_MODULES.len() * ALL_OPERATIONS.len());
+
+        clear_mock_functions();
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_prometheus_output_after_lookup() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_large as *mut libc::c_void);
+
+        getpwnam("bigUser", Some(NssModule::Files)).unwrap();
+        let text = metrics_prometheus();
+        assert!(text.contains("# TYPE tnss_lookups_total counter\n"));
+
+        let count: usize = text
+            .lines()
+            .find_map(|line| line.strip_prefix("tnss_lookups_total{module=\"files\",op=\"getpwnam\"} "))
+            .expect("getpwnam series missing")
+            .parse()
+            .unwrap();
+        assert!(count >= 1);
+        assert!(text.contains("tnss_lookups_total{module=\"winbind\",op=\"gethostbyaddr\"} "));
+        assert!(text.lines().any(|line| line.starts_with("tnss_max_buflen_bytes{op=\"getpwnam\"} ")));
+
+        clear_mock_functions();
+    }
+}
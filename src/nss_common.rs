@@ -1,7 +1,11 @@
 use libc::{c_int, dlopen, dlsym, RTLD_LAZY};
-use std::ffi::CString;
-use std::sync::{OnceLock, Mutex};
 use std::collections::HashMap;
+use std::ffi::{CStr, CString, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{OnceLock, PoisonError, RwLock};
 
 pub const NSS_MODULES_DIR: &str = "/usr/lib/x86_64-linux-gnu";
 pub const FILES_NSS_PATH: &str = "/usr/lib/x86_64-linux-gnu/libnss_files.so.2";
@@ -17,6 +21,20 @@ pub enum NssReturnCode {
     Return = 2,
 }
 
+impl NssReturnCode {
+    /// Short lowercase description of the status, for messages.
+    #[must_use]
+    pub fn description(&self) -> &'static str {
+        match self {
+            NssReturnCode::TryAgain => "temporarily unavailable",
+            NssReturnCode::Unavail => "service unavailable",
+            NssReturnCode::NotFound => "not found",
+            NssReturnCode::Success => "succeeded",
+            NssReturnCode::Return => "stopped the lookup",
+        }
+    }
+}
+
 impl From<c_int> for NssReturnCode {
     fn from(code: c_int) -> Self {
         match code {
@@ -30,14 +48,289 @@ impl From<c_int> for NssReturnCode {
     }
 }
 
+/// NSS modules supported by this library.
+///
+/// The explicit discriminants are the stable numeric ids exposed through
+/// `as_u8` and `TryFrom<u8>`; never renumber existing variants.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
 pub enum NssModule {
-    Files,
-    Sss,
-    Winbind,
+    Files = 0,
+    Sss = 1,
+    Winbind = 2,
+}
+
+pub(crate) const ALL_MODULES: [NssModule; 3] = [NssModule::Files, NssModule::Sss, NssModule::Winbind];
+
+/// Number of modules, indexed by `NssModule::as_index`.
+pub(crate) const MODULE_COUNT: usize = ALL_MODULES.len();
+
+/// Per-module library path overrides set via `set_module_path`.
+static MODULE_PATH_OVERRIDES: OnceLock<RwLock<HashMap<NssModule, PathBuf>>> = OnceLock::new();
+
+fn module_path_overrides() -> &'static RwLock<HashMap<NssModule, PathBuf>> {
+    MODULE_PATH_OVERRIDES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Pin the shared library used for a single module.
+///
+/// The override takes precedence over the default location for that module
+/// only, and is what `NssModule::resolved_path` reports; other modules are
+/// unaffected. Libraries are loaded once per process,
+/// so this must be called before the first lookup against `module`.
+pub fn set_module_path(module: NssModule, path: PathBuf) {
+    module_path_overrides()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(module, path);
+}
+
+/// Remove a path override set via `set_module_path`.
+pub fn clear_module_path(module: NssModule) {
+    module_path_overrides()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&module);
+}
+
+/// Symbol template used unless `set_symbol_template` overrides it.
+pub const DEFAULT_SYMBOL_TEMPLATE: &str = "_nss_{name}_{fn}";
+
+/// Per-module symbol templates set via `set_symbol_template`.
+static SYMBOL_TEMPLATE_OVERRIDES: OnceLock<RwLock<HashMap<NssModule, String>>> = OnceLock::new();
+
+fn symbol_template_overrides() -> &'static RwLock<HashMap<NssModule, String>> {
+    SYMBOL_TEMPLATE_OVERRIDES.get_or_init(|| RwLock::new(HashMap::new()))
+}
+
+/// Use a nonstandard symbol naming scheme for one module.
+///
+/// `template` may contain `{name}` (the module name, e.g. `winbind`) and `{fn}`
+/// (the function, e.g. `getpwnam_r`); the default is `DEFAULT_SYMBOL_TEMPLATE`.
+/// Symbols are resolved when the library is first loaded, so this must be
+/// called before the first lookup against `module`.
+pub fn set_symbol_template(module: NssModule, template: &str) {
+    symbol_template_overrides()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(module, template.to_string());
+}
+
+/// Remove a symbol template set via `set_symbol_template`.
+pub fn clear_symbol_template(module: NssModule) {
+    symbol_template_overrides()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&module);
+}
+
+/// Per-module `source` labels set via `set_source_label`, already uppercased.
+static SOURCE_LABELS: OnceLock<RwLock<HashMap<NssModule, String>>> = OnceLock::new();
+
+/// Whether `SOURCE_LABELS` has any entry, so that stamping an entry with
+/// the default label does not take the lock.
+static HAS_SOURCE_LABELS: AtomicBool = AtomicBool::new(false);
+
+fn update_source_labels(update: impl FnOnce(&mut HashMap<NssModule, String>)) {
+    let mut labels = SOURCE_LABELS
+        .get_or_init(|| RwLock::new(HashMap::new()))
+        .write()
+        .unwrap_or_else(PoisonError::into_inner);
+    update(&mut labels);
+    HAS_SOURCE_LABELS.store(!labels.is_empty(), Ordering::Release);
+}
+
+fn source_label_override(module: NssModule) -> Option<String> {
+    if !HAS_SOURCE_LABELS.load(Ordering::Acquire) {
+        return None;
+    }
+    SOURCE_LABELS
+        .get()?
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&module)
+        .cloned()
+}
+
+/// Stamp entries from `module` with `label` (uppercased) as their `source`.
+///
+/// For a module slot pointed at a different library with `set_module_path`
+/// and `set_symbol_template`, e.g. an LDAP module loaded in place of SSS, so
+/// entries report where they really came from. `source_module` still names
+/// the slot that was queried.
+pub fn set_source_label(module: NssModule, label: &str) {
+    update_source_labels(|labels| {
+        labels.insert(module, label.to_uppercase());
+    });
+}
+
+/// Remove a label set via `set_source_label`.
+pub fn clear_source_label(module: NssModule) {
+    update_source_labels(|labels| {
+        labels.remove(&module);
+    });
+}
+
+static STRICT_PATHS: OnceLock<RwLock<Option<Vec<PathBuf>>>> = OnceLock::new();
+
+fn set_strict_paths(allowed_dirs: Option<Vec<PathBuf>>) {
+    *STRICT_PATHS
+        .get_or_init(|| RwLock::new(None))
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = allowed_dirs;
+}
+
+fn strict_paths() -> Option<Vec<PathBuf>> {
+    STRICT_PATHS.get()?.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Only load module libraries that pass security checks, process-wide.
+///
+/// Before each `dlopen`, the library path is canonicalized (resolving
+/// symlinks) and must lie under one of `allowed_dirs`. The library and the
+/// directory containing it must both be owned by root and writable by
+/// neither group nor others. The canonical path is what gets loaded. A
+/// library failing any check is refused with `NssError::LibraryError` rather
+/// than passed over. Modules that are already loaded are not rechecked, and
+/// handles passed to `register_module_handle` are never checked.
+pub fn enable_strict_paths(allowed_dirs: Vec<PathBuf>) {
+    set_strict_paths(Some(allowed_dirs));
+}
+
+/// Go back to loading module libraries without the strict path checks.
+pub fn disable_strict_paths() {
+    set_strict_paths(None);
+}
+
+/// Why the canonical library path `path` fails the strict path checks, if it does.
+fn strict_path_violation(path: &Path, allowed_dirs: &[PathBuf]) -> Option<String> {
+    let allowed = allowed_dirs.iter().any(|dir| {
+        let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+        path.starts_with(dir)
+    });
+    if !allowed {
+        return Some("it is not under an allowed directory".to_string());
+    }
+
+    if let Some(reason) = unsafe_ownership(path, "it") {
+        return Some(reason);
+    }
+    match path.parent() {
+        Some(dir) => unsafe_ownership(dir, "its directory"),
+        None => None,
+    }
+}
+
+/// Why `path`, called `what` in the message, could be altered by someone
+/// other than root, if it could.
+fn unsafe_ownership(path: &Path, what: &str) -> Option<String> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return Some(format!("{what} cannot be inspected: {e}")),
+    };
+    // Mode first, so the reason does not depend on who runs the check
+    if metadata.mode() & 0o002 != 0 {
+        return Some(format!("{what} is world-writable"));
+    }
+    if metadata.mode() & 0o020 != 0 {
+        return Some(format!("{what} is group-writable"));
+    }
+    if metadata.uid() != 0 {
+        return Some(format!("{what} is owned by uid {}, not root", metadata.uid()));
+    }
+    None
+}
+
+fn format_symbol(template: &str, module_name: &str, function: &str) -> String {
+    template.replace("{name}", module_name).replace("{fn}", function)
+}
+
+/// Ordered list of directories searched for module libraries.
+static MODULE_SEARCH_PATHS: OnceLock<RwLock<Vec<PathBuf>>> = OnceLock::new();
+
+fn module_search_paths() -> &'static RwLock<Vec<PathBuf>> {
+    MODULE_SEARCH_PATHS.get_or_init(|| RwLock::new(vec![PathBuf::from(NSS_MODULES_DIR)]))
+}
+
+/// Set the directories searched, in order, for module libraries.
+///
+/// The first directory from which the module's library can be `dlopen`ed is
+/// used; `loaded_module_path` reports which one that was. Defaults to
+/// `NSS_MODULES_DIR`. A `set_module_path` override still takes precedence,
+/// and modules that are already loaded are not reloaded.
+pub fn set_module_search_paths(paths: Vec<PathBuf>) {
+    *module_search_paths()
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = paths;
+}
+
+/// Path the module's library was actually loaded from, if it has been loaded.
+#[must_use]
+pub fn loaded_module_path(module: NssModule) -> Option<PathBuf> {
+    NSS_LIBRARIES[module.as_index()]
+        .get()
+        .map(|library| library.path.clone())
+}
+
+/// Whether the module's library has been loaded and its functions cached.
+#[must_use]
+pub fn is_cached(module: NssModule) -> bool {
+    NSS_LIBRARIES[module.as_index()].get().is_some()
+}
+
+/// Load the module and perform one trivial lookup, so that benchmarks and
+/// tests measure steady-state calls rather than `dlopen` and the module's own
+/// first-use setup.
+///
+/// The probe is a lookup of uid 0; the module not knowing root is fine.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if the library cannot be loaded, or the
+/// lookup's error if the module fails for any reason other than not found.
+pub fn warm_up(module: NssModule) -> Result<(), crate::NssError> {
+    unsafe { loaded_library(module)? };
+
+    match crate::passwd::getpwuid(0, Some(module)) {
+        Err(e) if !e.is_not_found() => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Call `endpwent` and `endgrent` on every loaded module so that modules
+/// release enumeration resources.
+///
+/// This only ends enumerations that the calling thread started: module
+/// enumeration state (and the test mocks) is per-thread, so a shutdown handler
+/// running on another thread does not reach them. Call it from each thread
+/// that enumerates, e.g. before that thread exits.
+///
+/// Modules that were never loaded are left alone, as are functions a module
+/// does not export, so this is a no-op when no enumeration was started.
+/// Every module is ended even if an earlier one fails.
+///
+/// # Errors
+/// Returns the first error reported by an end function.
+pub fn end_all_enumerations() -> Result<(), crate::NssError> {
+    let mut first_error = None;
+    for &module in ALL_MODULES.iter().filter(|&&m| is_cached(m)) {
+        let results = unsafe { [crate::passwd::endpwent_impl(module), crate::group::endgrent_impl(module)] };
+        for result in results {
+            match result {
+                Ok(()) | Err(crate::NssError::LibraryError(_)) => {}
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+    }
+
+    first_error.map_or(Ok(()), Err)
 }
 
 impl NssModule {
+    /// Default path of the shared library backing this module, under
+    /// `NSS_MODULES_DIR`; see `resolved_path` for the one lookups load.
     #[must_use]
     pub fn path(&self) -> &'static str {
         match self {
@@ -47,6 +340,60 @@ impl NssModule {
         }
     }
 
+    /// Path of the shared library lookups load for this module.
+    ///
+    /// Returns the override from `set_module_path` if one is set, otherwise the
+    /// library in the first directory from `set_module_search_paths` (by
+    /// default `NSS_MODULES_DIR`, giving `path`).
+    #[must_use]
+    pub fn resolved_path(&self) -> PathBuf {
+        let search_paths = module_search_paths()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.candidate_paths(&search_paths)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| PathBuf::from(self.library_name()))
+    }
+
+    /// Exported symbol implementing `function` in this module's library.
+    ///
+    /// Follows the template from `set_symbol_template`, by default
+    /// `_nss_<name>_<function>`.
+    #[must_use]
+    pub fn symbol_name(&self, function: &str) -> String {
+        let overrides = symbol_template_overrides()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let template = overrides.get(self).map_or(DEFAULT_SYMBOL_TEMPLATE, String::as_str);
+        format_symbol(template, self.name(), function)
+    }
+
+    /// File name of the shared library backing this module.
+    #[must_use]
+    pub fn library_name(&self) -> &'static str {
+        match self {
+            NssModule::Files => "libnss_files.so.2",
+            NssModule::Sss => "libnss_sss.so.2",
+            NssModule::Winbind => "libnss_winbind.so.2",
+        }
+    }
+
+    /// Library paths to try, in order, given the configured search directories.
+    fn candidate_paths(&self, search_paths: &[PathBuf]) -> Vec<PathBuf> {
+        let overrides = module_path_overrides()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(path) = overrides.get(self) {
+            return vec![path.clone()];
+        }
+
+        search_paths
+            .iter()
+            .map(|dir| dir.join(self.library_name()))
+            .collect()
+    }
+
     #[must_use]
     pub fn name(&self) -> &'static str {
         match self {
@@ -64,68 +411,131 @@ impl NssModule {
             NssModule::Winbind => "WINBIND",
         }
     }
+
+    /// `source` stamped on entries returned by this module: the label from
+    /// `set_source_label` if any, otherwise `upper_name`.
+    #[must_use]
+    pub fn source_label(&self) -> String {
+        source_label_override(*self).unwrap_or_else(|| self.upper_name().to_string())
+    }
+
+    /// Stable numeric id of the module, for FFI and wire protocols.
+    #[must_use]
+    pub fn as_u8(&self) -> u8 {
+        *self as u8
+    }
+
+    /// Whether the module can enumerate passwd entries (`iterpw`/`getpwall`).
+    ///
+    /// Checks that `setpwent`, `getpwent_r` and `endpwent` are all exported, so
+    /// callers can hide "list all users" for modules that lack them. Note that a
+    /// module may export the symbols yet still return nothing when enumeration
+    /// is disabled in its own configuration (e.g. sssd `enumerate = false`).
+    ///
+    /// # Errors
+    /// Returns `NssError::LibraryError` if the library cannot be loaded.
+    pub fn supports_enumeration(&self) -> Result<bool, crate::NssError> {
+        let capabilities = module_capabilities(*self)?;
+        Ok([NssOperation::SetPwEnt, NssOperation::GetPwEnt, NssOperation::EndPwEnt]
+            .iter()
+            .all(|operation| capabilities.get(operation) == Some(&true)))
+    }
+
+    pub(crate) const fn as_index(self) -> usize {
+        self as usize
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum NssOperation {
-    GetGrNam,
-    GetGrGid,
-    SetGrEnt,
-    EndGrEnt,
-    GetGrEnt,
-    GetPwNam,
-    GetPwUid,
-    GetPwEnt,
-    SetPwEnt,
-    EndPwEnt,
+impl TryFrom<u8> for NssModule {
+    type Error = crate::NssError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        ALL_MODULES
+            .iter()
+            .copied()
+            .find(|module| module.as_u8() == value)
+            .ok_or(crate::NssError::UnknownModule(value))
+    }
+}
+
+/// Declares `NssOperation` along with its symbol names and `ALL_OPERATIONS`,
+/// so that adding an operation is a single line in the invocation below.
+macro_rules! nss_operations {
+    ($($variant:ident => $function:literal,)+) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum NssOperation {
+            $($variant,)+
+        }
+
+        impl NssOperation {
+            #[must_use]
+            pub fn function_name(&self) -> &'static str {
+                match self {
+                    $(NssOperation::$variant => $function,)+
+                }
+            }
+
+            /// Operation whose NSS function suffix is `name` (e.g. "getpwnam_r"),
+            /// the inverse of `function_name`.
+            #[must_use]
+            pub fn from_function_name(name: &str) -> Option<NssOperation> {
+                match name {
+                    $($function => Some(NssOperation::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+
+        /// Every operation, in declaration order.
+        pub(crate) const ALL_OPERATIONS: &[NssOperation] = &[$(NssOperation::$variant,)+];
+    };
+}
+
+nss_operations! {
+    GetGrNam => "getgrnam_r",
+    GetGrGid => "getgrgid_r",
+    SetGrEnt => "setgrent",
+    EndGrEnt => "endgrent",
+    GetGrEnt => "getgrent_r",
+    GetPwNam => "getpwnam_r",
+    GetPwUid => "getpwuid_r",
+    GetPwEnt => "getpwent_r",
+    SetPwEnt => "setpwent",
+    EndPwEnt => "endpwent",
+    GetHostByAddr => "gethostbyaddr_r",
 }
 
+/// Number of operations, and so the size of each module's function table.
+pub(crate) const OPERATION_COUNT: usize = ALL_OPERATIONS.len();
+
 impl NssOperation {
+    /// Every operation this library knows, in declaration order.
     #[must_use]
-    pub fn function_name(&self) -> &'static str {
-        match self {
-            NssOperation::GetGrNam => "getgrnam_r",
-            NssOperation::GetGrGid => "getgrgid_r",
-            NssOperation::SetGrEnt => "setgrent",
-            NssOperation::EndGrEnt => "endgrent",
-            NssOperation::GetGrEnt => "getgrent_r",
-            NssOperation::GetPwNam => "getpwnam_r",
-            NssOperation::GetPwUid => "getpwuid_r",
-            NssOperation::GetPwEnt => "getpwent_r",
-            NssOperation::SetPwEnt => "setpwent",
-            NssOperation::EndPwEnt => "endpwent",
-        }
+    pub fn all() -> &'static [NssOperation] {
+        ALL_OPERATIONS
+    }
+
+    /// Slot of this operation in a module's function table.
+    ///
+    /// Variants are numbered in declaration order, so this is also the
+    /// operation's position in `ALL_OPERATIONS`.
+    pub(crate) const fn as_index(self) -> usize {
+        self as usize
     }
 
-    const fn as_index(self) -> usize {
+    /// nsswitch.conf database this operation reads ("passwd", "group" or
+    /// "hosts").
+    pub(crate) const fn database(self) -> &'static str {
         match self {
-            NssOperation::GetGrNam => 0,
-            NssOperation::GetGrGid => 1,
-            NssOperation::SetGrEnt => 2,
-            NssOperation::EndGrEnt => 3,
-            NssOperation::GetGrEnt => 4,
-            NssOperation::GetPwNam => 5,
-            NssOperation::GetPwUid => 6,
-            NssOperation::GetPwEnt => 7,
-            NssOperation::SetPwEnt => 8,
-            NssOperation::EndPwEnt => 9,
+            NssOperation::GetPwNam | NssOperation::GetPwUid | NssOperation::GetPwEnt
+            | NssOperation::SetPwEnt | NssOperation::EndPwEnt => "passwd",
+            NssOperation::GetGrNam | NssOperation::GetGrGid | NssOperation::GetGrEnt
+            | NssOperation::SetGrEnt | NssOperation::EndGrEnt => "group",
+            NssOperation::GetHostByAddr => "hosts",
         }
     }
 }
 
-const ALL_OPERATIONS: [NssOperation; 10] = [
-    NssOperation::GetGrNam,
-    NssOperation::GetGrGid,
-    NssOperation::SetGrEnt,
-    NssOperation::EndGrEnt,
-    NssOperation::GetGrEnt,
-    NssOperation::GetPwNam,
-    NssOperation::GetPwUid,
-    NssOperation::GetPwEnt,
-    NssOperation::SetPwEnt,
-    NssOperation::EndPwEnt,
-];
-
 /// Cached NSS library with all function pointers loaded upfront
 ///
 /// Safety: Raw function pointers are safe to share between threads as long as
@@ -134,11 +544,19 @@ unsafe impl Send for NssLibrary {}
 unsafe impl Sync for NssLibrary {}
 
 struct NssLibrary {
-    functions: [*mut libc::c_void; 10],
+    /// `dlopen` handle, kept for resolving symbols outside `NssOperation`.
+    handle: *mut libc::c_void,
+    functions: [*mut libc::c_void; OPERATION_COUNT],
+    /// Path the library was loaded from.
+    path: PathBuf,
 }
 
-/// Global cache of loaded NSS libraries (max 3 entries)
-static NSS_LIBRARIES: OnceLock<Mutex<HashMap<NssModule, NssLibrary>>> = OnceLock::new();
+/// Global cache of loaded NSS libraries, one slot per module.
+///
+/// Each slot is written at most once and never mutated or cleared afterwards,
+/// so function pointers handed out by `get_nss_function` remain valid for the
+/// lifetime of the process without holding any lock.
+static NSS_LIBRARIES: [OnceLock<NssLibrary>; 3] = [OnceLock::new(), OnceLock::new(), OnceLock::new()];
 
 /// Gets a function pointer from an NSS module library.
 ///
@@ -154,26 +572,21 @@ static NSS_LIBRARIES: OnceLock<Mutex<HashMap<NssModule, NssLibrary>>> = OnceLock
 /// # Errors
 /// Returns `NssError::LibraryError` if the library cannot be loaded or the function is not found.
 /// Returns `NssError::InvalidUtf8` if string conversion fails.
-///
-/// # Panics
-/// Panics if the internal library cache mutex is poisoned, which indicates that
-/// another thread panicked while loading NSS libraries. This represents an
-/// unrecoverable system-level failure and the application should terminate.
 pub unsafe fn get_nss_function(
     operation: NssOperation,
     module: NssModule,
 ) -> Result<*mut libc::c_void, crate::NssError> {
-    let libraries = NSS_LIBRARIES.get_or_init(|| Mutex::new(HashMap::new()));
-    let mut guard = libraries.lock().unwrap();
+    crate::metrics::record_lookup(operation, module);
 
-    // Load all functions for this module if not already loaded
-    if let std::collections::hash_map::Entry::Vacant(e) = guard.entry(module) {
-        let lib = load_all_functions_for_module(module)?;
-        e.insert(lib);
+    #[cfg(test)]
+    if let Some(func_ptr) = test_support::mock_function(operation, module) {
+        return Ok(func_ptr);
     }
 
+    let library = loaded_library(module)?;
+
     // Return the specific function pointer
-    let func_ptr = guard[&module].functions[operation.as_index()];
+    let func_ptr = library.functions[operation.as_index()];
     if func_ptr.is_null() {
         return Err(crate::NssError::LibraryError(
             format!("Function {} not found in {}", operation.function_name(), module.name())
@@ -183,26 +596,182 @@ pub unsafe fn get_nss_function(
     Ok(func_ptr)
 }
 
-/// Load a library and all its NSS function pointers upfront.
+/// An NSS function's C signature, tied to the operation that provides it.
 ///
-/// Note: Library handles are intentionally never closed with `dlclose()` as this
-/// is standard practice for NSS modules and system libraries.
-unsafe fn load_all_functions_for_module(module: NssModule) -> Result<NssLibrary, crate::NssError> {
-    // Load the library once
-    let lib_path = CString::new(module.path())
+/// Implemented by marker types next to the code that calls each function, so
+/// that `NssFn` can resolve and type the pointer in one place.
+///
+/// # Safety
+/// `Fn` must be a function pointer type matching the C signature of
+/// `OPERATION`'s function exactly.
+pub unsafe trait NssSymbol {
+    type Fn: Copy;
+    const OPERATION: NssOperation;
+}
+
+/// A function resolved from an NSS module, typed by its signature `S`.
+///
+/// Replaces calling `get_nss_function` and transmuting the untyped pointer
+/// at every call site.
+pub struct NssFn<S: NssSymbol> {
+    func: S::Fn,
+}
+
+impl<S: NssSymbol> Clone for NssFn<S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: NssSymbol> Copy for NssFn<S> {}
+
+impl<S: NssSymbol> NssFn<S> {
+    /// Resolve `S`'s function from `module`; see `get_nss_function`.
+    ///
+    /// # Safety
+    /// As for `get_nss_function`: loading a module runs its initialization
+    /// code, and the function must be called according to the NSS API.
+    ///
+    /// # Errors
+    /// Returns `NssError::LibraryError` if the library cannot be loaded or the function is not found.
+    pub unsafe fn load(module: NssModule) -> Result<Self, crate::NssError> {
+        Ok(Self::from_raw(get_nss_function(S::OPERATION, module)?))
+    }
+
+    /// Type a pointer resolved some other way, e.g. by `library_symbol`.
+    ///
+    /// # Safety
+    /// `func_ptr` must be non-null and point to a function with signature `S`.
+    pub(crate) unsafe fn from_raw(func_ptr: *mut libc::c_void) -> Self {
+        const { assert!(std::mem::size_of::<S::Fn>() == std::mem::size_of::<*mut libc::c_void>()) };
+
+        NssFn { func: std::mem::transmute_copy(&func_ptr) }
+    }
+
+    /// The typed function pointer, to call with the module's arguments.
+    #[must_use]
+    pub fn get(self) -> S::Fn {
+        self.func
+    }
+}
+
+/// Resolve an arbitrary `_nss_<module>_<symbol_suffix>` symbol from a module.
+///
+/// This is an escape hatch for module-specific entry points that have no
+/// `NssOperation`, such as winbind's `getusersids`. A template set with
+/// `set_symbol_template` applies, with `symbol_suffix` as `{fn}`. The library
+/// is loaded (or taken from the cache) exactly as for `get_nss_function`.
+///
+/// # Safety
+/// The returned pointer is untyped. The caller must transmute it to the exact
+/// C signature the module exports for that symbol and uphold that function's
+/// contract (argument validity, buffer sizes, errno handling). A wrong
+/// signature is undefined behavior. The pointer stays valid for the life of
+/// the process, since libraries are never unloaded.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if the library cannot be loaded or the symbol is not found.
+/// Returns `NssError::InvalidUtf8` if `symbol_suffix` contains a NUL byte.
+pub unsafe fn raw_symbol(module: NssModule, symbol_suffix: &str) -> Result<*mut libc::c_void, crate::NssError> {
+    let library = loaded_library(module)?;
+
+    let symbol = module.symbol_name(symbol_suffix);
+    let symbol_c = CString::new(symbol.as_str())
         .map_err(|_| crate::NssError::InvalidUtf8)?;
 
-    let handle = dlopen(lib_path.as_ptr(), RTLD_LAZY);
-    if handle.is_null() {
+    let func_ptr = dlsym(library.handle, symbol_c.as_ptr());
+    if func_ptr.is_null() {
         return Err(crate::NssError::LibraryError(
-            format!("Failed to load library: {}", module.path())
+            format!("Symbol {} not found in {}", symbol, module.name())
         ));
     }
 
-    // Load all 10 function pointers
-    let mut functions = [std::ptr::null_mut(); 10];
-    for &operation in &ALL_OPERATIONS {
-        let func_name = format!("_nss_{}_{}", module.name(), operation.function_name());
+    Ok(func_ptr)
+}
+
+/// Resolve `symbol` from the library file at `path`, outside the per-module
+/// cache.
+///
+/// The file is vetted under `enable_strict_paths` as module libraries are.
+/// It is never unloaded either; opening the same file again gets the copy
+/// that is already loaded.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if the file cannot be loaded or lacks the symbol.
+pub(crate) unsafe fn library_symbol(path: &Path, symbol: &str) -> Result<*mut libc::c_void, crate::NssError> {
+    let (handle, path) = open_first(std::slice::from_ref(&path.to_path_buf()))?;
+
+    let symbol_c = CString::new(symbol)
+        .map_err(|_| crate::NssError::InvalidUtf8)?;
+    let func_ptr = dlsym(handle, symbol_c.as_ptr());
+    if func_ptr.is_null() {
+        return Err(crate::NssError::LibraryError(
+            format!("Symbol {} not found in {}", symbol, path.display())
+        ));
+    }
+
+    Ok(func_ptr)
+}
+
+/// Report which NSS operations a module actually implements.
+///
+/// Loads the module library (if not already cached) and returns, for every
+/// `NssOperation`, whether the corresponding `_nss_<module>_<function>` symbol
+/// resolved. Some modules omit enumeration or group functions entirely.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if the library cannot be loaded.
+pub fn module_capabilities(module: NssModule) -> Result<HashMap<NssOperation, bool>, crate::NssError> {
+    let library = unsafe { loaded_library(module)? };
+
+    Ok(ALL_OPERATIONS
+        .iter()
+        .map(|&operation| (operation, !library.functions[operation.as_index()].is_null()))
+        .collect())
+}
+
+/// Return the cached library for a module, loading it on first use.
+unsafe fn loaded_library(module: NssModule) -> Result<&'static NssLibrary, crate::NssError> {
+    let slot = &NSS_LIBRARIES[module.as_index()];
+
+    // Load all functions for this module if not already loaded. Failures are
+    // not cached so that a module installed later can still be picked up.
+    if let Some(library) = slot.get() {
+        return Ok(library);
+    }
+
+    let library = load_all_functions_for_module(module)?;
+    // If another thread won the race its table is identical; the extra
+    // dlopen only bumps the library's reference count.
+    let _ = slot.set(library);
+    Ok(slot.get().expect("NSS library slot initialized above"))
+}
+
+/// Load a library and all its NSS function pointers upfront.
+///
+/// Note: Library handles are intentionally never closed with `dlclose()` as this
+/// is standard practice for NSS modules and system libraries.
+unsafe fn load_all_functions_for_module(module: NssModule) -> Result<NssLibrary, crate::NssError> {
+    // Load the library once, from the first candidate path that works
+    let candidates = {
+        let search_paths = module_search_paths()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        module.candidate_paths(&search_paths)
+    };
+    let (handle, path) = open_first(&candidates)?;
+    library_from_handle(module, handle, path)
+}
+
+/// Resolve every operation's function pointer from an open `handle`.
+unsafe fn library_from_handle(
+    module: NssModule,
+    handle: *mut libc::c_void,
+    path: PathBuf,
+) -> Result<NssLibrary, crate::NssError> {
+    let mut functions = [std::ptr::null_mut(); OPERATION_COUNT];
+    for &operation in ALL_OPERATIONS {
+        let func_name = module.symbol_name(operation.function_name());
         let func_name_c = CString::new(func_name)
             .map_err(|_| crate::NssError::InvalidUtf8)?;
 
@@ -211,12 +780,243 @@ unsafe fn load_all_functions_for_module(module: NssModule) -> Result<NssLibrary,
         functions[operation.as_index()] = func_ptr;
     }
 
-    Ok(NssLibrary { functions })
+    Ok(NssLibrary { handle, functions, path })
+}
+
+/// Use a library the caller already `dlopen`ed for `module`, instead of
+/// having this crate open it.
+///
+/// For embedders that hold their own handle (e.g. to call non-NSS functions)
+/// and want lookups to share it. Function pointers are resolved from `handle`
+/// right away, and `loaded_module_path` reports the file it refers to.
+///
+/// # Safety
+/// `handle` must come from `dlopen` of the module's NSS library, and must
+/// stay open (no `dlclose`) for the rest of the process, since cached
+/// function pointers are used without holding any lock. The library has
+/// already been loaded, so `enable_strict_paths` does not vet it; the caller
+/// is responsible for where it came from.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if `handle` is null, exports none of the
+/// module's NSS functions, or the module is already loaded.
+pub unsafe fn register_module_handle(module: NssModule, handle: *mut libc::c_void) -> Result<(), crate::NssError> {
+    if handle.is_null() {
+        return Err(crate::NssError::LibraryError(format!("Null handle for {}", module.name())));
+    }
+
+    let library = library_from_handle(module, handle, handle_path(handle))?;
+    if library.functions.iter().all(|f| f.is_null()) {
+        return Err(crate::NssError::LibraryError(
+            format!("Handle exports no NSS functions for {}", module.name())
+        ));
+    }
+
+    NSS_LIBRARIES[module.as_index()].set(library).map_err(|_| {
+        crate::NssError::LibraryError(format!("Module {} is already loaded", module.name()))
+    })
+}
+
+/// Leading fields of glibc's `struct link_map`, which are public ABI.
+#[repr(C)]
+struct LinkMap {
+    l_addr: usize,
+    l_name: *const libc::c_char,
+    l_ld: *const ElfDyn,
+}
+
+/// `ElfW(Dyn)`: a tag and a value or address, each pointer-sized.
+#[repr(C)]
+struct ElfDyn {
+    d_tag: isize,
+    d_val: usize,
+}
+
+const DT_NULL: isize = 0;
+const DT_STRTAB: isize = 5;
+const DT_SONAME: isize = 14;
+
+/// The link map glibc keeps for a `dlopen` handle.
+unsafe fn link_map<'a>(handle: *mut libc::c_void) -> Option<&'a LinkMap> {
+    let mut map: *const LinkMap = std::ptr::null();
+    if libc::dlinfo(handle, libc::RTLD_DI_LINKMAP, (&raw mut map).cast()) != 0 {
+        return None;
+    }
+    map.as_ref()
+}
+
+/// File a `dlopen` handle refers to, or an empty path if glibc cannot say.
+///
+/// Taken from the handle's link map rather than `dladdr` on a symbol: glibc
+/// now implements the FILES functions in libc itself, so symbols resolved
+/// through libnss_files.so.2 can live in another object.
+unsafe fn handle_path(handle: *mut libc::c_void) -> PathBuf {
+    match link_map(handle) {
+        Some(map) if !map.l_name.is_null() => PathBuf::from(OsStr::from_bytes(CStr::from_ptr(map.l_name).to_bytes())),
+        _ => PathBuf::new(),
+    }
+}
+
+/// `DT_SONAME` of the object behind a `dlopen` handle, read from its dynamic
+/// section in memory.
+unsafe fn handle_soname(handle: *mut libc::c_void) -> Option<String> {
+    let map = link_map(handle)?;
+    if map.l_ld.is_null() {
+        return None;
+    }
+
+    let (mut strtab, mut soname) = (None, None);
+    let mut entry = map.l_ld;
+    while (*entry).d_tag != DT_NULL {
+        match (*entry).d_tag {
+            DT_STRTAB => strtab = Some((*entry).d_val),
+            DT_SONAME => soname = Some((*entry).d_val),
+            _ => {}
+        }
+        entry = entry.add(1);
+    }
+
+    // glibc relocates the link map's dynamic entries in place (`D_PTR`),
+    // except on targets whose dynamic section is read-only
+    // (`DL_RO_DYN_SECTION`: MIPS and RISC-V), where DT_STRTAB is still an
+    // offset from the load address
+    let strtab = if cfg!(any(
+        target_arch = "mips",
+        target_arch = "mips64",
+        target_arch = "riscv32",
+        target_arch = "riscv64"
+    )) {
+        strtab? + map.l_addr
+    } else {
+        strtab?
+    };
+    let name = CStr::from_ptr((strtab + soname?) as *const libc::c_char);
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// Soname of the module's library, e.g. `libnss_files.so.2`, loading it
+/// first if needed.
+///
+/// Meant for diagnostics: unlike `loaded_module_path`, which may be a
+/// symlink or a versioned file name, this is the ABI name the library was
+/// built with.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if the library cannot be loaded or has
+/// no soname.
+pub fn module_soname(module: NssModule) -> Result<String, crate::NssError> {
+    let library = unsafe { loaded_library(module)? };
+
+    unsafe { handle_soname(library.handle) }
+        .ok_or_else(|| crate::NssError::LibraryError(format!("No soname recorded in {}", library.path.display())))
+}
+
+/// `dlopen` the first of `candidates` that loads, returning its handle and path.
+///
+/// Under `enable_strict_paths`, each candidate that exists is vetted first
+/// and loaded by its canonical path.
+unsafe fn open_first(candidates: &[PathBuf]) -> Result<(*mut libc::c_void, PathBuf), crate::NssError> {
+    let strict = strict_paths();
+    for path in candidates {
+        let path = match &strict {
+            Some(allowed_dirs) => {
+                // Missing candidates are skipped as in non-strict mode
+                let Ok(canonical) = std::fs::canonicalize(path) else { continue };
+                if let Some(reason) = strict_path_violation(&canonical, allowed_dirs) {
+                    return Err(crate::NssError::LibraryError(
+                        format!("Refusing to load {}: {}", canonical.display(), reason)
+                    ));
+                }
+                canonical
+            }
+            None => path.clone(),
+        };
+
+        let lib_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| crate::NssError::InvalidUtf8)?;
+
+        let handle = dlopen(lib_path.as_ptr(), RTLD_LAZY);
+        if !handle.is_null() {
+            return Ok((handle, path));
+        }
+    }
+
+    let tried: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+    Err(crate::NssError::LibraryError(
+        format!("Failed to load library: {}", tried.join(", "))
+    ))
+}
+
+/// Per-thread overrides of NSS function pointers for unit tests, and the lock
+/// for tests that change process-wide configuration.
+///
+/// Mocks are thread-local so that concurrently running tests cannot observe
+/// each other's doubles, and the real library cache is never touched.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::{clear_module_path, clear_source_label, clear_symbol_template, disable_strict_paths, NssModule, NssOperation, ALL_MODULES};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    thread_local! {
+        static MOCK_FUNCTIONS: RefCell<HashMap<(NssModule, NssOperation), *mut libc::c_void>> =
+            RefCell::new(HashMap::new());
+    }
+
+    /// Route `operation` on `module` to `func` for the current thread.
+    pub(crate) fn set_mock_function(module: NssModule, operation: NssOperation, func: *mut libc::c_void) {
+        MOCK_FUNCTIONS.with(|mocks| mocks.borrow_mut().insert((module, operation), func));
+    }
+
+    /// Remove all mocks registered on the current thread.
+    pub(crate) fn clear_mock_functions() {
+        MOCK_FUNCTIONS.with(|mocks| mocks.borrow_mut().clear());
+    }
+
+    pub(super) fn mock_function(operation: NssOperation, module: NssModule) -> Option<*mut libc::c_void> {
+        MOCK_FUNCTIONS.with(|mocks| mocks.borrow().get(&(module, operation)).copied())
+    }
+
+    static GLOBAL_CONFIG: Mutex<()> = Mutex::new(());
+
+    /// Held by a test for as long as it changes, or depends on, process-wide
+    /// configuration; see `lock_global_config`.
+    pub(crate) struct GlobalConfigGuard {
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    /// Serialize against other tests using process-wide configuration.
+    ///
+    /// Unlike mocks, settings such as `set_module_path` are shared by every
+    /// thread, so tests that touch them cannot run in parallel. Everything
+    /// is reset to its default when the guard is dropped, including when the
+    /// test panics.
+    pub(crate) fn lock_global_config() -> GlobalConfigGuard {
+        GlobalConfigGuard { _lock: GLOBAL_CONFIG.lock().unwrap_or_else(PoisonError::into_inner) }
+    }
+
+    impl Drop for GlobalConfigGuard {
+        fn drop(&mut self) {
+            for module in ALL_MODULES {
+                clear_module_path(module);
+                clear_source_label(module);
+                clear_symbol_template(module);
+                crate::nsswitch::enable_module(module);
+            }
+            crate::metrics::disable_adaptive_buffers();
+            crate::nsswitch::clear_default_modules();
+            crate::nsswitch::clear_files_authoritative();
+            disable_strict_paths();
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::test_support::lock_global_config;
+    use std::path::Path;
 
     #[test]
     fn test_nss_return_code_from_int() {
@@ -230,9 +1030,80 @@ mod tests {
 
     #[test]
     fn test_nss_module_paths() {
+        let _config = lock_global_config();
+
         assert_eq!(NssModule::Files.path(), FILES_NSS_PATH);
         assert_eq!(NssModule::Sss.path(), SSS_NSS_PATH);
         assert_eq!(NssModule::Winbind.path(), WINBIND_NSS_PATH);
+        for module in ALL_MODULES {
+            assert_eq!(module.resolved_path(), Path::new(module.path()));
+        }
+    }
+
+    #[test]
+    fn test_module_path_override_is_per_module() {
+        let _config = lock_global_config();
+        let custom = PathBuf::from("/opt/samba/lib/libnss_winbind.so.2");
+        set_module_path(NssModule::Winbind, custom.clone());
+
+        assert_eq!(NssModule::Winbind.resolved_path(), custom);
+        assert_eq!(NssModule::Winbind.path(), WINBIND_NSS_PATH);
+        assert_eq!(NssModule::Files.resolved_path(), Path::new(FILES_NSS_PATH));
+        assert_eq!(NssModule::Sss.resolved_path(), Path::new(SSS_NSS_PATH));
+
+        clear_module_path(NssModule::Winbind);
+        assert_eq!(NssModule::Winbind.resolved_path(), Path::new(WINBIND_NSS_PATH));
+    }
+
+    #[test]
+    fn test_candidate_paths_follow_search_order() {
+        let _config = lock_global_config();
+        let dirs = vec![PathBuf::from("/nonexistent/nss"), PathBuf::from("/lib/x86_64-linux-gnu")];
+        assert_eq!(
+            NssModule::Sss.candidate_paths(&dirs),
+            vec![
+                PathBuf::from("/nonexistent/nss/libnss_sss.so.2"),
+                PathBuf::from("/lib/x86_64-linux-gnu/libnss_sss.so.2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_first_reports_all_candidates() {
+        let candidates = vec![
+            PathBuf::from("/nonexistent/a/libnss_files.so.2"),
+            PathBuf::from("/nonexistent/b/libnss_files.so.2"),
+        ];
+        match unsafe { open_first(&candidates) } {
+            Err(crate::NssError::LibraryError(msg)) => {
+                assert!(msg.contains("/nonexistent/a/"));
+                assert!(msg.contains("/nonexistent/b/"));
+            }
+            other => panic!("Expected LibraryError, got {:?}", other.map(|(_, p)| p)),
+        }
+    }
+
+    #[test]
+    fn test_symbol_template_formatting() {
+        assert_eq!(
+            format_symbol(DEFAULT_SYMBOL_TEMPLATE, "files", NssOperation::GetPwNam.function_name()),
+            "_nss_files_getpwnam_r"
+        );
+        assert_eq!(
+            format_symbol("_vendor_{name}_nss_{fn}", "ldap", NssOperation::GetGrGid.function_name()),
+            "_vendor_ldap_nss_getgrgid_r"
+        );
+    }
+
+    #[test]
+    fn test_symbol_template_override_is_per_module() {
+        let _config = lock_global_config();
+        set_symbol_template(NssModule::Winbind, "{fn}@{name}");
+        assert_eq!(NssModule::Winbind.symbol_name("setpwent"), "setpwent@winbind");
+        assert_eq!(NssModule::Sss.symbol_name("setpwent"), "_nss_sss_setpwent");
+
+        clear_symbol_template(NssModule::Winbind);
+        assert_eq!(NssModule::Winbind.symbol_name("setpwent"), "_nss_winbind_setpwent");
     }
 
     #[test]
@@ -249,6 +1120,33 @@ mod tests {
         assert_eq!(NssModule::Winbind.upper_name(), "WINBIND");
     }
 
+    #[test]
+    fn test_nss_module_indices_unique() {
+        for (i, module) in ALL_MODULES.iter().enumerate() {
+            assert_eq!(module.as_index(), i);
+        }
+        assert_eq!(NSS_LIBRARIES.len(), ALL_MODULES.len());
+    }
+
+    #[test]
+    fn test_nss_module_u8_round_trip() {
+        assert_eq!(NssModule::Files.as_u8(), 0);
+        assert_eq!(NssModule::Sss.as_u8(), 1);
+        assert_eq!(NssModule::Winbind.as_u8(), 2);
+
+        for module in ALL_MODULES {
+            assert_eq!(NssModule::try_from(module.as_u8()).unwrap(), module);
+        }
+    }
+
+    #[test]
+    fn test_nss_module_unknown_discriminant() {
+        assert!(matches!(
+            NssModule::try_from(200),
+            Err(crate::NssError::UnknownModule(200))
+        ));
+    }
+
     #[test]
     fn test_nss_operation_function_names() {
         assert_eq!(NssOperation::GetGrNam.function_name(), "getgrnam_r");
@@ -261,6 +1159,78 @@ mod tests {
         assert_eq!(NssOperation::GetPwEnt.function_name(), "getpwent_r");
         assert_eq!(NssOperation::SetPwEnt.function_name(), "setpwent");
         assert_eq!(NssOperation::EndPwEnt.function_name(), "endpwent");
+        assert_eq!(NssOperation::GetHostByAddr.function_name(), "gethostbyaddr_r");
+    }
+
+    #[test]
+    fn test_nss_operation_function_name_round_trip() {
+        assert_eq!(NssOperation::all(), ALL_OPERATIONS);
+        for &operation in NssOperation::all() {
+            assert_eq!(NssOperation::from_function_name(operation.function_name()), Some(operation));
+        }
+        assert_eq!(NssOperation::from_function_name("getpwnam"), None);
+        assert_eq!(NssOperation::from_function_name("_nss_files_getpwnam_r"), None);
+    }
+
+    #[test]
+    fn test_end_all_enumerations_without_enumeration() {
+        assert!(end_all_enumerations().is_ok());
+        // Idempotent
+        assert!(end_all_enumerations().is_ok());
+    }
+
+    #[test]
+    fn test_strict_paths_reject_world_writable_library() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let _config = lock_global_config();
+        let dir = std::env::temp_dir().join(format!("tnss-strict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let library = dir.join("libnss_files.so.2");
+        std::fs::write(&library, b"not really a library").unwrap();
+        std::fs::set_permissions(&library, std::fs::Permissions::from_mode(0o666)).unwrap();
+
+        enable_strict_paths(vec![dir.clone()]);
+        let err = unsafe { open_first(std::slice::from_ref(&library)) }.unwrap_err();
+        assert!(err.to_string().contains("Refusing to load"), "{err}");
+        assert!(err.to_string().contains("it is world-writable"), "{err}");
+
+        let canonical = std::fs::canonicalize(&library).unwrap();
+        assert!(strict_path_violation(&canonical, &[PathBuf::from(NSS_MODULES_DIR)])
+            .unwrap()
+            .contains("not under an allowed directory"));
+
+        std::fs::set_permissions(&library, std::fs::Permissions::from_mode(0o664)).unwrap();
+        let reason = strict_path_violation(&canonical, std::slice::from_ref(&dir)).unwrap();
+        assert!(reason.contains("it is group-writable"), "{reason}");
+
+        std::fs::set_permissions(&library, std::fs::Permissions::from_mode(0o644)).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o775)).unwrap();
+        let reason = strict_path_violation(&canonical, std::slice::from_ref(&dir)).unwrap();
+        assert!(reason.contains("its directory is group-writable"), "{reason}");
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        // Only ownership can still fail, when tests do not run as root
+        if let Some(reason) = strict_path_violation(&canonical, std::slice::from_ref(&dir)) {
+            assert!(reason.contains("not root"), "{reason}");
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_register_module_handle_rejects_null() {
+        let err = unsafe { register_module_handle(NssModule::Winbind, std::ptr::null_mut()) }.unwrap_err();
+        assert!(matches!(err, crate::NssError::LibraryError(_)));
+    }
+
+    #[test]
+    fn test_operation_indices_contiguous() {
+        assert_eq!(ALL_OPERATIONS.len(), OPERATION_COUNT);
+        for (index, operation) in ALL_OPERATIONS.iter().enumerate() {
+            assert_eq!(operation.as_index(), index, "{:?} is out of place", operation);
+        }
     }
 
     #[test]
@@ -0,0 +1,813 @@
+use crate::nss_common::{NssModule, NssOperation, NssReturnCode, ALL_MODULES};
+use crate::{NssError, NssResult};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::sync::{OnceLock, PoisonError, RwLock};
+
+/// Location of the system NSS configuration.
+pub const NSSWITCH_CONF_PATH: &str = "/etc/nsswitch.conf";
+
+/// Modules queried, in order, when no nsswitch.conf order is available.
+pub(crate) const DEFAULT_MODULES: [NssModule; 3] = [NssModule::Files, NssModule::Sss, NssModule::Winbind];
+
+/// Statuses an action bracket can name, for expanding `!STATUS=ACTION`.
+const ACTION_STATUSES: [NssReturnCode; 4] = [
+    NssReturnCode::Success,
+    NssReturnCode::NotFound,
+    NssReturnCode::Unavail,
+    NssReturnCode::TryAgain,
+];
+
+/// What the resolver does after a service reports a status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NsswitchAction {
+    /// Stop the chain with this service's result.
+    Return,
+    /// Go on to the next service.
+    Continue,
+}
+
+/// A service from an nsswitch.conf line, with the action bracket after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NsswitchService {
+    pub module: NssModule,
+    /// `STATUS=ACTION` overrides, in the order written.
+    pub actions: Vec<(NssReturnCode, NsswitchAction)>,
+}
+
+impl NsswitchService {
+    /// Service with glibc's default actions.
+    #[must_use]
+    pub fn new(module: NssModule) -> Self {
+        NsswitchService { module, actions: Vec::new() }
+    }
+
+    /// Action to take after this service reports `status`.
+    ///
+    /// The last override for `status` wins; without one, glibc's defaults
+    /// apply: `return` on SUCCESS and `continue` on anything else.
+    #[must_use]
+    pub fn action(&self, status: NssReturnCode) -> NsswitchAction {
+        self.actions
+            .iter()
+            .rev()
+            .find(|(code, _)| *code == status)
+            .map_or(
+                if status == NssReturnCode::Success {
+                    NsswitchAction::Return
+                } else {
+                    NsswitchAction::Continue
+                },
+                |&(_, action)| action,
+            )
+    }
+}
+
+#[cfg(feature = "nsswitch")]
+fn system_config() -> Option<&'static str> {
+    static SYSTEM_CONFIG: OnceLock<Option<String>> = OnceLock::new();
+    SYSTEM_CONFIG
+        .get_or_init(|| {
+            // Unit tests mock modules and must not depend on the host's configuration
+            if cfg!(test) {
+                return None;
+            }
+            std::fs::read_to_string(NSSWITCH_CONF_PATH).ok()
+        })
+        .as_deref()
+}
+
+/// Modules to query for `database` ("passwd", "group", ...) when the caller
+/// does not name one: the modules of `default_chain`, in order.
+#[must_use]
+pub fn default_modules(database: &str) -> Vec<NssModule> {
+    default_chain(database)
+        .into_iter()
+        .map(|service| service.module)
+        .collect()
+}
+
+/// Modules that lookups without a module consult for users, in order.
+///
+/// This is the fully resolved view, for diagnostics: the nsswitch.conf order
+/// (or built-in default), replaced by `set_default_modules` if set, minus
+/// anything turned off with `disable_module`.
+#[must_use]
+pub fn effective_passwd_chain() -> Vec<NssModule> {
+    default_modules("passwd")
+}
+
+/// Modules that lookups without a module consult for groups, in order; see
+/// `effective_passwd_chain`.
+#[must_use]
+pub fn effective_group_chain() -> Vec<NssModule> {
+    default_modules("group")
+}
+
+/// Services, with their actions, that lookups follow for `database` when the
+/// caller does not name a module.
+///
+/// With the `nsswitch` feature this is the order configured in
+/// `NSSWITCH_CONF_PATH`, which is read once per process, so `passwd` and
+/// `group` may differ, and the action brackets are honored: `passwd: files
+/// [NOTFOUND=return] sss` never asks SSS about a user FILES does not know.
+/// If the file cannot be read or lists none of our modules for `database`,
+/// or without the feature, the built-in FILES, SSS, WINBIND order is used
+/// with glibc's default actions.
+///
+/// A list set with `set_default_modules` replaces all of this, for every
+/// database. Modules turned off with `disable_module` are left out either way.
+#[must_use]
+pub fn default_chain(database: &str) -> Vec<NsswitchService> {
+    let mut chain = match default_modules_override() {
+        Some(modules) => modules.into_iter().map(NsswitchService::new).collect(),
+        None => configured_chain(database),
+    };
+    chain.retain(|service| !is_module_disabled(service.module));
+    chain
+}
+
+#[cfg(feature = "nsswitch")]
+fn configured_chain(database: &str) -> Vec<NsswitchService> {
+    chain_from_config(system_config(), database)
+}
+
+#[cfg(not(feature = "nsswitch"))]
+fn configured_chain(_database: &str) -> Vec<NsswitchService> {
+    DEFAULT_MODULES.map(NsswitchService::new).to_vec()
+}
+
+fn disabled_modules() -> &'static RwLock<HashSet<NssModule>> {
+    static DISABLED_MODULES: OnceLock<RwLock<HashSet<NssModule>>> = OnceLock::new();
+    DISABLED_MODULES.get_or_init(|| RwLock::new(HashSet::new()))
+}
+
+fn update_disabled_modules(update: impl FnOnce(&mut HashSet<NssModule>)) {
+    update(&mut disabled_modules().write().unwrap_or_else(PoisonError::into_inner));
+}
+
+fn is_module_disabled(module: NssModule) -> bool {
+    disabled_modules()
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains(&module)
+}
+
+fn default_modules_storage() -> &'static RwLock<Option<Vec<NssModule>>> {
+    static DEFAULT_MODULES_OVERRIDE: OnceLock<RwLock<Option<Vec<NssModule>>>> = OnceLock::new();
+    DEFAULT_MODULES_OVERRIDE.get_or_init(|| RwLock::new(None))
+}
+
+fn store_default_modules(modules: Option<Vec<NssModule>>) {
+    *default_modules_storage().write().unwrap_or_else(PoisonError::into_inner) = modules;
+}
+
+fn default_modules_override() -> Option<Vec<NssModule>> {
+    default_modules_storage().read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// Query `modules`, in order and with glibc's default actions, for every
+/// database whenever the caller does not name a module, process-wide.
+///
+/// This replaces the nsswitch.conf or built-in order, e.g. for test setups
+/// that only ship some modules. `disable_module` still applies on top.
+///
+/// # Errors
+/// Returns `NssError::InvalidModuleList`, leaving the current order in
+/// place, if `modules` is empty or names a module twice.
+pub fn set_default_modules(modules: Vec<NssModule>) -> NssResult<()> {
+    if modules.is_empty() {
+        return Err(NssError::InvalidModuleList("no modules given".to_string()));
+    }
+    if let Some(duplicate) = modules.iter().enumerate().find_map(|(i, m)| modules[..i].contains(m).then_some(m)) {
+        return Err(NssError::InvalidModuleList(format!("{} is listed twice", duplicate.upper_name())));
+    }
+    store_default_modules(Some(modules));
+    Ok(())
+}
+
+/// Undo `set_default_modules`, going back to the configured order.
+pub fn clear_default_modules() {
+    store_default_modules(None);
+}
+
+/// Leave `module` out of every default module list, process-wide.
+///
+/// Useful where a module is installed but can never answer, such as winbind
+/// on a host that is not domain-joined: lookups without an explicit module
+/// then skip it instead of paying for a failed call. Lookups naming the
+/// module explicitly are unaffected.
+pub fn disable_module(module: NssModule) {
+    update_disabled_modules(|disabled| {
+        disabled.insert(module);
+    });
+}
+
+/// Undo `disable_module`.
+pub fn enable_module(module: NssModule) {
+    update_disabled_modules(|disabled| {
+        disabled.remove(&module);
+    });
+}
+
+/// Predicate passed to `set_files_authoritative`.
+type LocalNamePredicate = fn(&str) -> bool;
+
+fn files_authoritative_storage() -> &'static RwLock<Option<LocalNamePredicate>> {
+    static FILES_AUTHORITATIVE: OnceLock<RwLock<Option<LocalNamePredicate>>> = OnceLock::new();
+    FILES_AUTHORITATIVE.get_or_init(|| RwLock::new(None))
+}
+
+fn store_files_authoritative(is_local: Option<LocalNamePredicate>) {
+    *files_authoritative_storage().write().unwrap_or_else(PoisonError::into_inner) = is_local;
+}
+
+fn files_authoritative() -> Option<LocalNamePredicate> {
+    *files_authoritative_storage().read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Trust FILES when it does not know a name `is_local` accepts, process-wide.
+///
+/// Off by default. When on, a user lookup by name through the default chain
+/// (`getpwnam` and its `_opts` variants, `getpwnam_deadline` and
+/// `getpwnam_reuse`) stops after a FILES `NotFound` for such names, as if the
+/// chain read `files [NOTFOUND=return]`, instead of going on to ask SSS and
+/// winbind. This saves the remote round trips for names that can only be
+/// local, such as service accounts; see `is_local_service_name` for a
+/// ready-made pattern. Names `is_local` rejects use the chain unchanged.
+/// Lookups that ask each module on purpose, such as
+/// `which_modules_have_user` or the enumerating `getpwnam_prefix`, are not
+/// affected.
+pub fn set_files_authoritative(is_local: fn(&str) -> bool) {
+    store_files_authoritative(Some(is_local));
+}
+
+/// Undo `set_files_authoritative`.
+pub fn clear_files_authoritative() {
+    store_files_authoritative(None);
+}
+
+/// Whether `name` matches `^[a-z_][a-z0-9_-]*$`, the shape of local service
+/// accounts, for use with `set_files_authoritative`.
+///
+/// Only a fit where directory users always have a domain prefix or
+/// uppercase letters: with winbind's `use default domain`, plain directory
+/// names such as `jsmith` match too.
+#[must_use]
+pub fn is_local_service_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+fn modules_from_config(config: Option<&str>, database: &str) -> Vec<NssModule> {
+    chain_from_config(config, database)
+        .into_iter()
+        .map(|service| service.module)
+        .collect()
+}
+
+#[cfg(any(feature = "nsswitch", test))]
+fn chain_from_config(config: Option<&str>, database: &str) -> Vec<NsswitchService> {
+    let chain = config
+        .map(|config| parse_nsswitch_chain(config, database))
+        .unwrap_or_default();
+    if chain.is_empty() {
+        DEFAULT_MODULES.map(NsswitchService::new).to_vec()
+    } else {
+        chain
+    }
+}
+
+/// How a lookup reacts when a module fails with a hard error, i.e. anything
+/// other than not found or unavailable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FallbackPolicy {
+    /// Return the error at once without asking the remaining modules.
+    #[default]
+    StopOnError,
+    /// Go on as the failing service's action bracket says for its status
+    /// (TRYAGAIN for errors without one), as glibc does. If no later module
+    /// has the entry, the first hard error is returned, since "not found"
+    /// could be wrong.
+    ContinueOnError,
+}
+
+/// Run a single-entry `operation` through its database's chain, or only
+/// through `module` if one is given.
+///
+/// `lookup` returns `Ok(None)` when a module has no entry. A module that is
+/// unavailable or not installed counts as UNAVAIL; what happens on any other
+/// error is up to `policy`.
+pub(crate) fn lookup_chain<T, F>(
+    operation: NssOperation,
+    module: Option<NssModule>,
+    policy: FallbackPolicy,
+    lookup: F,
+) -> NssResult<T>
+where
+    F: FnMut(NssModule) -> NssResult<Option<T>>,
+{
+    let chain = match module {
+        Some(m) => vec![NsswitchService::new(m)],
+        None => default_chain(operation.database()),
+    };
+    follow_chain(operation, &chain, policy, lookup)
+}
+
+/// `lookup_chain` for a by-name lookup of `name`, applying
+/// `set_files_authoritative` to the default chain.
+pub(crate) fn lookup_name_chain<T, F>(
+    operation: NssOperation,
+    name: &str,
+    module: Option<NssModule>,
+    policy: FallbackPolicy,
+    lookup: F,
+) -> NssResult<T>
+where
+    F: FnMut(NssModule) -> NssResult<Option<T>>,
+{
+    if module.is_some() || !files_authoritative().is_some_and(|is_local| is_local(name)) {
+        return lookup_chain(operation, module, policy, lookup);
+    }
+
+    let mut chain = default_chain(operation.database());
+    for service in chain.iter_mut().filter(|service| service.module == NssModule::Files) {
+        service.actions.push((NssReturnCode::NotFound, NsswitchAction::Return));
+    }
+    follow_chain(operation, &chain, policy, lookup)
+}
+
+/// Enumerate `database` on `module`, or on each of its default modules in
+/// turn, passing every entry to `visit` until it returns `Break`.
+///
+/// `open` starts the enumeration of one module. A module that is unavailable
+/// or not installed just ends its own enumeration; any other error stops the
+/// whole walk and is returned.
+pub(crate) fn enumerate_chain<T, I, O, V>(
+    database: &str,
+    module: Option<NssModule>,
+    mut open: O,
+    mut visit: V,
+) -> NssResult<()>
+where
+    I: Iterator<Item = NssResult<T>>,
+    O: FnMut(NssModule) -> I,
+    V: FnMut(T) -> ControlFlow<()>,
+{
+    for mod_enum in enumerated_modules(database, module) {
+        if enumerate_module(open(mod_enum), &mut visit)?.is_break() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// `enumerate_chain`, but a hard error only ends the failing module's
+/// enumeration: it is collected with that module and the walk goes on.
+pub(crate) fn enumerate_chain_best_effort<T, I, O, V>(
+    database: &str,
+    module: Option<NssModule>,
+    mut open: O,
+    mut visit: V,
+) -> Vec<(NssModule, NssError)>
+where
+    I: Iterator<Item = NssResult<T>>,
+    O: FnMut(NssModule) -> I,
+    V: FnMut(T) -> ControlFlow<()>,
+{
+    let mut errors = Vec::new();
+    for mod_enum in enumerated_modules(database, module) {
+        match enumerate_module(open(mod_enum), &mut visit) {
+            Ok(ControlFlow::Break(())) => break,
+            Ok(ControlFlow::Continue(())) => {}
+            Err(e) => errors.push((mod_enum, e)),
+        }
+    }
+    errors
+}
+
+fn enumerated_modules(database: &str, module: Option<NssModule>) -> Vec<NssModule> {
+    match module {
+        Some(m) => vec![m],
+        None => default_modules(database),
+    }
+}
+
+/// Pass one module's `entries` to `visit`, returning `Break` if `visit` asked
+/// to stop the whole walk.
+fn enumerate_module<T, I, V>(entries: I, visit: &mut V) -> NssResult<ControlFlow<()>>
+where
+    I: Iterator<Item = NssResult<T>>,
+    V: FnMut(T) -> ControlFlow<()>,
+{
+    for result in entries {
+        match result {
+            Ok(entry) => {
+                if visit(entry).is_break() {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => break,
+            // Library not available (e.g., winbind/sss not installed), skip this module
+            Err(NssError::LibraryError(_)) => break,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
+
+/// Query `chain` in order, applying each service's action brackets.
+///
+/// A hit under `SUCCESS=continue` is kept while the remaining services are
+/// asked; a later hit replaces it, as in glibc, but unlike glibc a later miss
+/// does not discard it.
+fn follow_chain<T, F>(
+    operation: NssOperation,
+    chain: &[NsswitchService],
+    policy: FallbackPolicy,
+    mut lookup: F,
+) -> NssResult<T>
+where
+    F: FnMut(NssModule) -> NssResult<Option<T>>,
+{
+    let mut attempts = Vec::new();
+    let mut first_error = None;
+    let mut found = None;
+    for service in chain {
+        let status = match lookup(service.module) {
+            Ok(Some(entry)) => {
+                found = Some(entry);
+                NssReturnCode::Success
+            }
+            Ok(None) => NssReturnCode::NotFound,
+            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => NssReturnCode::Unavail,
+            // Library not installed
+            Err(NssError::LibraryError(_)) => NssReturnCode::Unavail,
+            Err(e) if policy == FallbackPolicy::StopOnError => return Err(e),
+            Err(e) => {
+                let status = match e {
+                    NssError::NssOperationFailed { return_code, .. } => return_code,
+                    _ => NssReturnCode::TryAgain,
+                };
+                first_error.get_or_insert(e);
+                status
+            }
+        };
+
+        attempts.push((service.module, status));
+        if service.action(status) == NsswitchAction::Return {
+            break;
+        }
+    }
+
+    if let Some(entry) = found {
+        return Ok(entry);
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Err(NssError::AllModulesFailed { operation, attempts }),
+    }
+}
+
+/// Ordered modules configured for `database` in nsswitch.conf-style `config`.
+///
+/// Action brackets such as `[NOTFOUND=return]` are skipped, as are services
+/// this library has no module for (e.g. `systemd`). Returns an empty list if
+/// `database` has no entry. Only the first line for `database` is used.
+#[must_use]
+pub fn parse_nsswitch_services(config: &str, database: &str) -> Vec<NssModule> {
+    parse_nsswitch_chain(config, database)
+        .into_iter()
+        .map(|service| service.module)
+        .collect()
+}
+
+/// Ordered services configured for `database`, each with the actions from
+/// the bracket that follows it.
+///
+/// Status and action names are case-insensitive and `!STATUS=ACTION` applies
+/// to every other status. Actions this library does not model (`merge`) are
+/// ignored, as are brackets after services it has no module for.
+#[must_use]
+pub fn parse_nsswitch_chain(config: &str, database: &str) -> Vec<NsswitchService> {
+    for line in config.lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        let Some((name, services)) = line.split_once(':') else {
+            continue;
+        };
+        if name.trim() == database {
+            return parse_service_list(services);
+        }
+    }
+
+    Vec::new()
+}
+
+fn parse_service_list(services: &str) -> Vec<NsswitchService> {
+    let mut chain: Vec<NsswitchService> = Vec::new();
+    // Whether the last service seen is in `chain`, so a bracket belongs to it
+    let mut last_known = false;
+    let mut rest = services;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+
+        // An unterminated bracket swallows the rest of the line, as glibc does
+        if let Some(action) = rest.strip_prefix('[') {
+            let (contents, tail) = action.split_once(']').unwrap_or((action, ""));
+            if let (true, Some(service)) = (last_known, chain.last_mut()) {
+                service.actions.extend(parse_actions(contents));
+            }
+            rest = tail;
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '[')
+            .unwrap_or(rest.len());
+        let (service, tail) = rest.split_at(end);
+        let module = ALL_MODULES.iter().find(|m| m.name() == service);
+        if let Some(&module) = module {
+            chain.push(NsswitchService::new(module));
+        }
+        last_known = module.is_some();
+        rest = tail;
+    }
+
+    chain
+}
+
+/// Parse the inside of an action bracket, e.g. `NOTFOUND=return !UNAVAIL = continue`.
+fn parse_actions(contents: &str) -> Vec<(NssReturnCode, NsswitchAction)> {
+    // Spaces are allowed around '=', so rejoin those before splitting items
+    let mut normalized = String::new();
+    for token in contents.split_whitespace() {
+        if !normalized.is_empty() && !normalized.ends_with('=') && !token.starts_with('=') {
+            normalized.push(' ');
+        }
+        normalized.push_str(token);
+    }
+
+    let mut actions = Vec::new();
+    for item in normalized.split(' ') {
+        let Some((status, action)) = item.split_once('=') else {
+            continue;
+        };
+        let action = match action.to_ascii_lowercase().as_str() {
+            "return" => NsswitchAction::Return,
+            "continue" => NsswitchAction::Continue,
+            _ => continue,
+        };
+        let (negated, status) = match status.strip_prefix('!') {
+            Some(status) => (true, status),
+            None => (false, status),
+        };
+        let status = match status.to_ascii_uppercase().as_str() {
+            "SUCCESS" => NssReturnCode::Success,
+            "NOTFOUND" => NssReturnCode::NotFound,
+            "UNAVAIL" => NssReturnCode::Unavail,
+            "TRYAGAIN" => NssReturnCode::TryAgain,
+            _ => continue,
+        };
+
+        if negated {
+            actions.extend(ACTION_STATUSES.iter().filter(|&&s| s != status).map(|&s| (s, action)));
+        } else {
+            actions.push((status, action));
+        }
+    }
+
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::lock_global_config;
+
+    const CONFIG: &str = "\
+# /etc/nsswitch.conf
+passwd: files [NOTFOUND=return] sss
+group:          files winbind   # local groups first
+shadow: files
+hosts: files dns
+";
+
+    #[test]
+    fn test_action_brackets_are_skipped() {
+        assert_eq!(
+            parse_nsswitch_services(CONFIG, "passwd"),
+            vec![NssModule::Files, NssModule::Sss]
+        );
+    }
+
+    #[test]
+    fn test_comments_and_whitespace() {
+        assert_eq!(
+            parse_nsswitch_services(CONFIG, "group"),
+            vec![NssModule::Files, NssModule::Winbind]
+        );
+    }
+
+    #[test]
+    fn test_unknown_services_and_databases() {
+        assert_eq!(parse_nsswitch_services(CONFIG, "hosts"), vec![NssModule::Files]);
+        assert!(parse_nsswitch_services(CONFIG, "netgroup").is_empty());
+    }
+
+    #[test]
+    fn test_databases_use_their_own_order() {
+        let config = "passwd: winbind files\ngroup: files sss\n";
+        assert_eq!(
+            modules_from_config(Some(config), "passwd"),
+            vec![NssModule::Winbind, NssModule::Files]
+        );
+        assert_eq!(
+            modules_from_config(Some(config), "group"),
+            vec![NssModule::Files, NssModule::Sss]
+        );
+    }
+
+    #[test]
+    fn test_default_order_when_config_unusable() {
+        assert_eq!(modules_from_config(None, "passwd"), DEFAULT_MODULES);
+        assert_eq!(modules_from_config(Some("passwd: systemd"), "passwd"), DEFAULT_MODULES);
+        assert_eq!(modules_from_config(Some("group: files"), "passwd"), DEFAULT_MODULES);
+    }
+
+    #[test]
+    fn test_bracket_spacing_and_adjacency() {
+        let config = "passwd: files[ !UNAVAIL = return ]sss [SUCCESS=merge] winbind";
+        assert_eq!(
+            parse_nsswitch_services(config, "passwd"),
+            vec![NssModule::Files, NssModule::Sss, NssModule::Winbind]
+        );
+
+        let unterminated = "passwd: files [NOTFOUND=return sss";
+        assert_eq!(parse_nsswitch_services(unterminated, "passwd"), vec![NssModule::Files]);
+    }
+
+    #[test]
+    fn test_chain_parses_actions() {
+        let chain = parse_nsswitch_chain(CONFIG, "passwd");
+        assert_eq!(chain[0].actions, [(NssReturnCode::NotFound, NsswitchAction::Return)]);
+        assert_eq!(chain[0].action(NssReturnCode::NotFound), NsswitchAction::Return);
+        assert_eq!(chain[0].action(NssReturnCode::Unavail), NsswitchAction::Continue);
+        assert_eq!(chain[1], NsswitchService::new(NssModule::Sss));
+
+        let config = "passwd: files [ !UNAVAIL = return success=CONTINUE SUCCESS=merge ]";
+        let files = &parse_nsswitch_chain(config, "passwd")[0];
+        assert_eq!(files.action(NssReturnCode::Unavail), NsswitchAction::Continue);
+        assert_eq!(files.action(NssReturnCode::NotFound), NsswitchAction::Return);
+        assert_eq!(files.action(NssReturnCode::Success), NsswitchAction::Continue);
+
+        // The bracket belongs to systemd, which is dropped, not to files
+        let config = "passwd: files systemd [NOTFOUND=return] sss";
+        assert!(parse_nsswitch_chain(config, "passwd")[0].actions.is_empty());
+    }
+
+    /// Simulate a lookup in which every module misses, recording which
+    /// modules were asked.
+    fn queried_modules(config: &str) -> (Vec<NssModule>, NssResult<()>) {
+        let mut queried = Vec::new();
+        let result = follow_chain(NssOperation::GetPwNam, &chain_from_config(Some(config), "passwd"), FallbackPolicy::default(), |module| {
+            queried.push(module);
+            if module == NssModule::Files {
+                Ok(None)
+            } else {
+                Err(NssError::LibraryError("not installed".to_string()))
+            }
+        });
+        (queried, result)
+    }
+
+    #[test]
+    fn test_notfound_return_stops_chain() {
+        let (queried, result) = queried_modules("passwd: files [NOTFOUND=return] sss");
+        assert_eq!(queried, [NssModule::Files]);
+        assert!(matches!(
+            result,
+            Err(NssError::AllModulesFailed { ref attempts, .. })
+                if *attempts == [(NssModule::Files, NssReturnCode::NotFound)]
+        ));
+
+        let (queried, _) = queried_modules("passwd: files sss");
+        assert_eq!(queried, [NssModule::Files, NssModule::Sss]);
+    }
+
+    #[test]
+    fn test_unavail_actions() {
+        let (queried, _) = queried_modules("passwd: sss [UNAVAIL=continue] winbind files");
+        assert_eq!(queried, [NssModule::Sss, NssModule::Winbind, NssModule::Files]);
+
+        let (queried, result) = queried_modules("passwd: sss [UNAVAIL=return] files");
+        assert_eq!(queried, [NssModule::Sss]);
+        assert!(result.unwrap_err().is_not_found());
+    }
+
+    #[test]
+    fn test_success_continue_asks_next_service() {
+        let mut queried = Vec::new();
+        let chain = parse_nsswitch_chain("passwd: files [SUCCESS=continue] sss winbind", "passwd");
+        let result = follow_chain(NssOperation::GetPwNam, &chain, FallbackPolicy::default(), |module| {
+            queried.push(module);
+            Ok(match module {
+                NssModule::Files => Some("files"),
+                NssModule::Sss => None,
+                NssModule::Winbind => Some("winbind"),
+            })
+        });
+        assert_eq!(queried, [NssModule::Files, NssModule::Sss, NssModule::Winbind]);
+        assert_eq!(result.unwrap(), "winbind");
+
+        let chain = parse_nsswitch_chain("passwd: files [SUCCESS=continue] sss", "passwd");
+        let result = follow_chain(NssOperation::GetPwNam, &chain, FallbackPolicy::default(), |module| {
+            Ok((module == NssModule::Files).then_some("files"))
+        });
+        assert_eq!(result.unwrap(), "files");
+    }
+
+    #[test]
+    fn test_hard_error_follows_action_bracket() {
+        let denied = || NssError::PermissionDenied { operation: NssOperation::GetPwNam, module: NssModule::Files };
+        let chain = parse_nsswitch_chain("passwd: files [!SUCCESS=return] sss", "passwd");
+
+        let mut queried = Vec::new();
+        let result: NssResult<()> = follow_chain(NssOperation::GetPwNam, &chain, FallbackPolicy::ContinueOnError, |module| {
+            queried.push(module);
+            Err(denied())
+        });
+        assert_eq!(queried, [NssModule::Files]);
+        assert!(matches!(result, Err(NssError::PermissionDenied { module: NssModule::Files, .. })));
+
+        let chain = parse_nsswitch_chain("passwd: files sss", "passwd");
+        let result = follow_chain(NssOperation::GetPwNam, &chain, FallbackPolicy::ContinueOnError, |module| match module {
+            NssModule::Files => Err(denied()),
+            _ => Ok(Some(module)),
+        });
+        assert_eq!(result.unwrap(), NssModule::Sss);
+
+        let result = follow_chain(NssOperation::GetPwNam, &chain, FallbackPolicy::default(), |module| match module {
+            NssModule::Files => Err(denied()),
+            _ => Ok(Some(module)),
+        });
+        assert!(matches!(result, Err(NssError::PermissionDenied { .. })));
+    }
+
+    #[test]
+    fn test_explicit_module_ignores_config() {
+        let mut queried = Vec::new();
+        let result: NssResult<()> = lookup_chain(NssOperation::GetPwNam, Some(NssModule::Winbind), FallbackPolicy::default(), |module| {
+            queried.push(module);
+            Ok(None)
+        });
+        assert_eq!(queried, [NssModule::Winbind]);
+        assert!(result.unwrap_err().is_not_found());
+    }
+
+    #[test]
+    fn test_effective_chain_drops_disabled_winbind() {
+        let _config = lock_global_config();
+        set_default_modules(vec![NssModule::Files, NssModule::Sss, NssModule::Winbind]).unwrap();
+        assert_eq!(effective_passwd_chain(), [NssModule::Files, NssModule::Sss, NssModule::Winbind]);
+
+        disable_module(NssModule::Winbind);
+        assert_eq!(effective_passwd_chain(), [NssModule::Files, NssModule::Sss]);
+        assert_eq!(effective_group_chain(), [NssModule::Files, NssModule::Sss]);
+
+        enable_module(NssModule::Winbind);
+        clear_default_modules();
+    }
+
+    #[test]
+    fn test_set_default_modules_overrides_order() {
+        let _config = lock_global_config();
+        set_default_modules(vec![NssModule::Winbind, NssModule::Files]).unwrap();
+        assert_eq!(default_modules("passwd"), [NssModule::Winbind, NssModule::Files]);
+        assert_eq!(default_modules("group"), [NssModule::Winbind, NssModule::Files]);
+
+        disable_module(NssModule::Winbind);
+        assert_eq!(default_modules("passwd"), [NssModule::Files]);
+        enable_module(NssModule::Winbind);
+
+        clear_default_modules();
+        assert_eq!(default_modules("passwd"), DEFAULT_MODULES);
+    }
+
+    #[test]
+    fn test_set_default_modules_rejects_bad_lists() {
+        let _config = lock_global_config();
+        assert!(matches!(set_default_modules(Vec::new()), Err(NssError::InvalidModuleList(_))));
+
+        let err = set_default_modules(vec![NssModule::Files, NssModule::Sss, NssModule::Files]).unwrap_err();
+        assert!(err.to_string().contains("FILES is listed twice"));
+        assert_eq!(default_modules("passwd"), DEFAULT_MODULES);
+    }
+}
@@ -0,0 +1,163 @@
+//! Tuning knobs for single-entry lookups, bundled so that each new knob does
+//! not need another function variant.
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::nsswitch::FallbackPolicy;
+use crate::passwd::Fields;
+use crate::{NssError, NssModule, NssOperation, NssResult, NssReturnCode};
+
+/// Options for `getpwnam_opts` and its siblings.
+///
+/// Built from `LookupOptions::new()` (the settings plain `getpwnam` and
+/// friends use) by chaining the setters:
+///
+/// ```
+/// use std::time::Duration;
+/// use truenas_rust_nss::LookupOptions;
+///
+/// let opts = LookupOptions::new().buflen(16 * 1024).retries(2).timeout(Duration::from_secs(5));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LookupOptions {
+    pub(crate) buflen: Option<usize>,
+    pub(crate) retries: u32,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) fields: Fields,
+    pub(crate) lossy: bool,
+    pub(crate) policy: FallbackPolicy,
+}
+
+impl Default for LookupOptions {
+    fn default() -> Self {
+        LookupOptions {
+            buflen: None,
+            retries: 0,
+            timeout: None,
+            fields: Fields::ALL,
+            lossy: false,
+            policy: FallbackPolicy::default(),
+        }
+    }
+}
+
+impl LookupOptions {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffer size to start each module call with, instead of the
+    /// database's default. It still doubles on `ERANGE`, and adaptive
+    /// buffers can still raise it.
+    #[must_use]
+    pub fn buflen(mut self, buflen: usize) -> Self {
+        self.buflen = Some(buflen);
+        self
+    }
+
+    /// Ask a module again, up to `retries` more times, when it answers
+    /// `TryAgain`. No retries by default.
+    #[must_use]
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Stop waiting for a module after `timeout`, including its retries.
+    ///
+    /// The call runs on a worker thread, since it cannot be cancelled; one
+    /// that overruns is left to finish there and its answer is discarded.
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Decode only these string fields of passwd entries; see `Fields`.
+    /// Group lookups ignore this.
+    #[must_use]
+    pub fn fields(mut self, fields: Fields) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    /// Replace bytes that are not valid UTF-8 with U+FFFD instead of failing
+    /// with `NssError::InvalidUtf8`. Off by default.
+    ///
+    /// The original bytes of a passwd field decoded this way stay available
+    /// from its `*_os` accessor; group members decoded this way are counted
+    /// in `GroupEntry::invalid_members`.
+    #[must_use]
+    pub fn lossy(mut self, lossy: bool) -> Self {
+        self.lossy = lossy;
+        self
+    }
+
+    /// What a hard error from one module does to the rest of the chain;
+    /// `FallbackPolicy::StopOnError` by default.
+    #[must_use]
+    pub fn policy(mut self, policy: FallbackPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Run one module's `call` on the caller's thread, applying the retry
+    /// count. For lookups without a timeout.
+    pub(crate) fn retry<T>(&self, call: impl FnMut() -> NssResult<T>) -> NssResult<T> {
+        retry_try_again(self.retries, call)
+    }
+
+    /// Run one module's `call` on a worker thread, applying the retry count
+    /// and waiting at most `timeout` for the result.
+    pub(crate) fn run_within<T: Send + 'static>(
+        &self,
+        operation: NssOperation,
+        module: NssModule,
+        timeout: Duration,
+        call: impl FnMut() -> NssResult<T> + Send + 'static,
+    ) -> NssResult<T> {
+        let retries = self.retries;
+        call_within(operation, module, timeout, move || retry_try_again(retries, call))
+    }
+}
+
+/// Call `call`, asking again up to `retries` more times while it answers
+/// `TryAgain`.
+fn retry_try_again<T>(retries: u32, mut call: impl FnMut() -> NssResult<T>) -> NssResult<T> {
+    if retries == 0 {
+        return call();
+    }
+
+    let mut left = retries;
+    loop {
+        match call() {
+            Err(NssError::NssOperationFailed { return_code: NssReturnCode::TryAgain, .. }) if left > 0 => {
+                left -= 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run `call` on a worker thread, waiting at most `timeout` for its result.
+///
+/// Anything `call` needs from the caller's thread-local NSS state, such as
+/// the resolved function, must be captured before calling this.
+pub(crate) fn call_within<T: Send + 'static>(
+    operation: NssOperation,
+    module: NssModule,
+    timeout: Duration,
+    call: impl FnOnce() -> NssResult<T> + Send + 'static,
+) -> NssResult<T> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        // The receiver is gone if the caller already timed out
+        let _ = tx.send(call());
+    });
+
+    rx.recv_timeout(timeout)
+        .unwrap_or(Err(NssError::Timeout { operation, module }))
+}
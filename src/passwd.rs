@@ -1,13 +1,36 @@
 use libc::{c_char, c_int, gid_t, uid_t, passwd};
-use std::ffi::{CStr, CString};
+use std::cell::{Cell, OnceCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::marker::PhantomData;
 use std::mem;
+use std::ops::{BitOr, ControlFlow};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::{NssError, NssResult, NssModule, NssOperation, NssReturnCode};
-use crate::nss_common::get_nss_function;
+use crate::metrics;
+use crate::nss_common::{library_symbol, NssFn, NssSymbol, ALL_MODULES};
+use crate::nsswitch::{default_modules, enumerate_chain, enumerate_chain_best_effort, lookup_chain, lookup_name_chain, FallbackPolicy};
+use crate::options::{call_within, LookupOptions};
 
 const PASSWD_INIT_BUFLEN: usize = 1024;
 
+/// A passwd database entry.
+///
+/// String fields must be valid UTF-8 unless the lookup opted into
+/// `LookupOptions::lossy`; use the `*_os` accessors when the exact bytes
+/// returned by the NSS module matter.
+///
+/// **Equality is identity, not content:** `==` compares only `pw_uid` and
+/// `pw_name` (see the `PartialEq` impl). Compare fields explicitly to detect a
+/// changed shell, home directory or source.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PasswdEntry {
     pub pw_name: String,
     pub pw_uid: uid_t,
@@ -15,13 +38,188 @@ pub struct PasswdEntry {
     pub pw_gecos: String,
     pub pw_dir: String,
     pub pw_shell: String,
+    /// Display label of `source_module` (see `NssModule::source_label`),
+    /// derived from it when the entry is built; for `getpwnam_via_lib`, the
+    /// uppercased module name.
     pub source: String,
+    /// Module that produced the entry; this, not `source`, is what to match
+    /// on. `None` for entries that did not come from a configured module,
+    /// such as those from `getpwnam_via_lib` or converted from other crates'
+    /// types.
+    pub source_module: Option<NssModule>,
+    /// Attributes beyond the POSIX fields, such as SSS's user principal name.
+    /// Only filled in by `getpwnam_ex` for SSS entries; empty otherwise.
+    pub extra: HashMap<String, String>,
+    /// Original bytes of fields that were decoded lossily; see
+    /// `RawPasswdFields`. Use `RawPasswdFields::default()` when building an
+    /// entry by hand.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub raw: RawPasswdFields,
+}
+
+/// Raw bytes of fields that were not valid UTF-8.
+///
+/// Only populated by lossy decoding, and only for fields whose `String` had
+/// bytes replaced, so entries that decode cleanly carry no extra allocation.
+#[derive(Debug, Clone, Default)]
+pub struct RawPasswdFields {
+    pub name: Option<OsString>,
+    pub gecos: Option<OsString>,
+    pub dir: Option<OsString>,
+    pub shell: Option<OsString>,
+}
+
+impl PasswdEntry {
+    /// Username as returned by the NSS module, without UTF-8 validation.
+    #[must_use]
+    pub fn name_os(&self) -> &OsStr {
+        self.raw.name.as_deref().unwrap_or_else(|| OsStr::new(&self.pw_name))
+    }
+
+    /// GECOS field as returned by the NSS module, without UTF-8 validation.
+    #[must_use]
+    pub fn gecos_os(&self) -> &OsStr {
+        self.raw.gecos.as_deref().unwrap_or_else(|| OsStr::new(&self.pw_gecos))
+    }
+
+    /// Home directory as returned by the NSS module, without UTF-8 validation.
+    #[must_use]
+    pub fn dir_os(&self) -> &OsStr {
+        self.raw.dir.as_deref().unwrap_or_else(|| OsStr::new(&self.pw_dir))
+    }
+
+    /// Login shell as returned by the NSS module, without UTF-8 validation.
+    #[must_use]
+    pub fn shell_os(&self) -> &OsStr {
+        self.raw.shell.as_deref().unwrap_or_else(|| OsStr::new(&self.pw_shell))
+    }
+
+    /// Domain part of `pw_name` for winbind-style `DOMAIN<sep>user` names, or
+    /// `None` for a plain username; see `split_domain_user`.
+    #[must_use]
+    pub fn domain(&self, sep: char) -> Option<&str> {
+        split_domain_user(&self.pw_name, sep).0
+    }
+}
+
+/// Entries compare by `pw_uid`, then `pw_name`.
+///
+/// **Only those two fields participate.** `pw_gecos`, `pw_dir`, `pw_shell`,
+/// `pw_gid` and `source` are ignored, so two entries whose other fields differ
+/// still compare equal. This is deliberate: the same account returned by two
+/// modules is one account, and a `BTreeSet` keeps only the first one inserted.
+impl PartialEq for PasswdEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PasswdEntry {}
+
+impl PartialOrd for PasswdEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PasswdEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.pw_uid
+            .cmp(&other.pw_uid)
+            .then_with(|| self.pw_name.cmp(&other.pw_name))
+    }
 }
 
+/// Decode a C string field.
+///
+/// Invalid UTF-8 is an error unless `lossy` is set, in which case invalid
+/// sequences become U+FFFD and the raw bytes are returned as well.
+unsafe fn decode_field(ptr: *const c_char, lossy: bool) -> NssResult<(String, Option<OsString>)> {
+    if ptr.is_null() {
+        return Ok((String::new(), None));
+    }
+
+    let bytes = CStr::from_ptr(ptr).to_bytes();
+    if lossy {
+        return Ok(decode_bytes(bytes));
+    }
+    let field = std::str::from_utf8(bytes).map_err(|_| NssError::InvalidUtf8)?;
+    Ok((field.to_string(), None))
+}
+
+/// Decode raw field bytes lossily, as `decode_field` does when asked to.
+pub(crate) fn decode_bytes(bytes: &[u8]) -> (String, Option<OsString>) {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => (s.to_string(), None),
+        Err(_) => (
+            String::from_utf8_lossy(bytes).into_owned(),
+            Some(OsStr::from_bytes(bytes).to_os_string()),
+        ),
+    }
+}
+
+/// Which string fields of a `PasswdEntry` a lookup decodes.
+///
+/// Combine with `|`, e.g. `Fields::NAME | Fields::SHELL`. Fields left out come
+/// back as empty strings, saving their allocation. `pw_uid`, `pw_gid` and the
+/// source are always filled in, since they cost nothing to copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fields(u8);
+
+impl Fields {
+    pub const NAME: Fields = Fields(1);
+    pub const GECOS: Fields = Fields(1 << 1);
+    pub const DIR: Fields = Fields(1 << 2);
+    pub const SHELL: Fields = Fields(1 << 3);
+    pub const ALL: Fields = Fields(0b1111);
+
+    /// Whether every field in `other` is also in `self`.
+    #[must_use]
+    pub const fn contains(self, other: Fields) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for Fields {
+    type Output = Fields;
+
+    fn bitor(self, rhs: Fields) -> Fields {
+        Fields(self.0 | rhs.0)
+    }
+}
 
 unsafe fn parse_passwd_result(
     result: *const passwd,
     module: &NssModule,
+) -> NssResult<Option<PasswdEntry>> {
+    parse_passwd_fields(result, module, Fields::ALL, false)
+}
+
+/// `parse_passwd_fields` for a single-entry lookup, where an entry with an
+/// empty name counts as not found.
+///
+/// A nameless passwd entry is invalid, and callers matching on `pw_name`
+/// mishandle it. Enumeration keeps such entries, since there `Ok(None)`
+/// would end the iteration.
+unsafe fn parse_lookup_fields(
+    result: *const passwd,
+    module: &NssModule,
+    fields: Fields,
+    lossy: bool,
+) -> NssResult<Option<PasswdEntry>> {
+    if !result.is_null() && !(*result).pw_name.is_null() && *(*result).pw_name == 0 {
+        return Ok(None);
+    }
+    parse_passwd_fields(result, module, fields, lossy)
+}
+
+/// Build a `PasswdEntry` from `result`, decoding the string fields in
+/// `fields` strictly or, with `lossy`, as `decode_field` describes.
+unsafe fn parse_passwd_fields(
+    result: *const passwd,
+    module: &NssModule,
+    fields: Fields,
+    lossy: bool,
 ) -> NssResult<Option<PasswdEntry>> {
     if result.is_null() {
         return Ok(None);
@@ -33,37 +231,17 @@ unsafe fn parse_passwd_result(
         return Ok(None);
     }
 
-    let pw_name = CStr::from_ptr(passwd_ref.pw_name)
-        .to_str()
-        .map_err(|_| NssError::InvalidUtf8)?
-        .to_string();
-
-    let pw_gecos = if passwd_ref.pw_gecos.is_null() {
-        String::new()
-    } else {
-        CStr::from_ptr(passwd_ref.pw_gecos)
-            .to_str()
-            .map_err(|_| NssError::InvalidUtf8)?
-            .to_string()
-    };
-
-    let pw_dir = if passwd_ref.pw_dir.is_null() {
-        String::new()
-    } else {
-        CStr::from_ptr(passwd_ref.pw_dir)
-            .to_str()
-            .map_err(|_| NssError::InvalidUtf8)?
-            .to_string()
-    };
-
-    let pw_shell = if passwd_ref.pw_shell.is_null() {
-        String::new()
-    } else {
-        CStr::from_ptr(passwd_ref.pw_shell)
-            .to_str()
-            .map_err(|_| NssError::InvalidUtf8)?
-            .to_string()
+    let decode = |field: Fields, ptr: *const c_char| {
+        if fields.contains(field) {
+            decode_field(ptr, lossy)
+        } else {
+            Ok((String::new(), None))
+        }
     };
+    let (pw_name, raw_name) = decode(Fields::NAME, passwd_ref.pw_name)?;
+    let (pw_gecos, raw_gecos) = decode(Fields::GECOS, passwd_ref.pw_gecos)?;
+    let (pw_dir, raw_dir) = decode(Fields::DIR, passwd_ref.pw_dir)?;
+    let (pw_shell, raw_shell) = decode(Fields::SHELL, passwd_ref.pw_shell)?;
 
     Ok(Some(PasswdEntry {
         pw_name,
@@ -72,52 +250,86 @@ unsafe fn parse_passwd_result(
         pw_gecos,
         pw_dir,
         pw_shell,
-        source: module.upper_name().to_string(),
+        source: module.source_label(),
+        source_module: Some(*module),
+        extra: HashMap::new(),
+        raw: RawPasswdFields {
+            name: raw_name,
+            gecos: raw_gecos,
+            dir: raw_dir,
+            shell: raw_shell,
+        },
     }))
 }
 
-type GetPwNameFn = unsafe extern "C" fn(
-    name: *const c_char,
-    result: *mut passwd,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
+struct GetPwNameFn;
+
+unsafe impl NssSymbol for GetPwNameFn {
+    type Fn = unsafe extern "C" fn(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetPwNam;
+}
 
 unsafe fn getpwnam_r_impl(
     name: &str,
     module: NssModule,
     buffer_len: usize,
 ) -> NssResult<Option<PasswdEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetPwNam, module)?;
-    let getpwnam_r: GetPwNameFn = mem::transmute(func_ptr);
+    let mut buffer = vec![0u8; buffer_len];
+    getpwnam_r_into(name, module, &mut buffer, Fields::ALL)
+}
+
+/// `getpwnam_r` using `buffer` as the record buffer, doubling it in place on
+/// `ERANGE` so the caller keeps the larger allocation. Only the string fields
+/// in `fields` are decoded, strictly.
+unsafe fn getpwnam_r_into(
+    name: &str,
+    module: NssModule,
+    buffer: &mut Vec<u8>,
+    fields: Fields,
+) -> NssResult<Option<PasswdEntry>> {
+    let wanted = metrics::initial_buflen(NssOperation::GetPwNam, buffer.len());
+    if buffer.len() < wanted {
+        buffer.resize(wanted, 0);
+    }
+    getpwnam_r_call(NssFn::load(module)?, name, module, buffer, fields, false)
+}
 
+/// The body of `getpwnam_r_into`, calling an already resolved `getpwnam_r`.
+unsafe fn getpwnam_r_call(
+    getpwnam_r: NssFn<GetPwNameFn>,
+    name: &str,
+    module: NssModule,
+    buffer: &mut Vec<u8>,
+    fields: Fields,
+    lossy: bool,
+) -> NssResult<Option<PasswdEntry>> {
     let name_c = CString::new(name).map_err(|_| NssError::InvalidUtf8)?;
     let mut result: passwd = mem::zeroed();
-    let mut buffer = vec![0u8; buffer_len];
     let mut errno: c_int = 0;
 
-    let ret_code = getpwnam_r(
+    let ret_code = getpwnam_r.get()(
         name_c.as_ptr(),
         &mut result,
         buffer.as_mut_ptr().cast::<c_char>(),
-        buffer_len,
+        buffer.len(),
         &mut errno,
     );
 
     match errno {
-        0 => {} // Success
+        0 => metrics::record_buflen(NssOperation::GetPwNam, buffer.len()), // Success
         libc::ERANGE => {
             // Buffer too small, try with larger buffer
-            return getpwnam_r_impl(name, module, buffer_len * 2);
+            buffer.resize(buffer.len() * 2, 0);
+            return getpwnam_r_call(getpwnam_r, name, module, buffer, fields, lossy);
         }
         _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetPwNam,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+            return Err(NssError::from_errno(errno, NssOperation::GetPwNam, NssReturnCode::from(ret_code), module));
         }
     }
 
@@ -135,30 +347,47 @@ unsafe fn getpwnam_r_impl(
         });
     }
 
-    parse_passwd_result(&result, &module)
+    parse_lookup_fields(&result, &module, fields, lossy)
 }
 
-type GetPwUidFn = unsafe extern "C" fn(
-    uid: uid_t,
-    result: *mut passwd,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
+struct GetPwUidFn;
+
+unsafe impl NssSymbol for GetPwUidFn {
+    type Fn = unsafe extern "C" fn(
+        uid: uid_t,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetPwUid;
+}
 
-unsafe fn getpwuid_r_impl(
+/// Call `getpwuid_r`, handing a found record to `on_found` while the buffer
+/// backing it is still alive.
+unsafe fn getpwuid_r_with<T>(
     uid: uid_t,
     module: NssModule,
     buffer_len: usize,
-) -> NssResult<Option<PasswdEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetPwUid, module)?;
-    let getpwuid_r: GetPwUidFn = mem::transmute(func_ptr);
+    on_found: impl FnOnce(&passwd) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
+    let buffer_len = metrics::initial_buflen(NssOperation::GetPwUid, buffer_len);
+    getpwuid_r_call(NssFn::load(module)?, uid, module, buffer_len, on_found)
+}
 
+/// The body of `getpwuid_r_with`, calling an already resolved `getpwuid_r`.
+unsafe fn getpwuid_r_call<T>(
+    getpwuid_r: NssFn<GetPwUidFn>,
+    uid: uid_t,
+    module: NssModule,
+    buffer_len: usize,
+    on_found: impl FnOnce(&passwd) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
     let mut result: passwd = mem::zeroed();
     let mut buffer = vec![0u8; buffer_len];
     let mut errno: c_int = 0;
 
-    let ret_code = getpwuid_r(
+    let ret_code = getpwuid_r.get()(
         uid,
         &mut result,
         buffer.as_mut_ptr().cast::<c_char>(),
@@ -167,18 +396,13 @@ unsafe fn getpwuid_r_impl(
     );
 
     match errno {
-        0 => {} // Success
+        0 => metrics::record_buflen(NssOperation::GetPwUid, buffer_len), // Success
         libc::ERANGE => {
             // Buffer too small, try with larger buffer
-            return getpwuid_r_impl(uid, module, buffer_len * 2);
+            return getpwuid_r_call(getpwuid_r, uid, module, buffer_len * 2, on_found);
         }
         _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetPwUid,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+            return Err(NssError::from_errno(errno, NssOperation::GetPwUid, NssReturnCode::from(ret_code), module));
         }
     }
 
@@ -196,275 +420,2700 @@ unsafe fn getpwuid_r_impl(
         });
     }
 
-    parse_passwd_result(&result, &module)
+    on_found(&result)
+}
+
+/// Winbind's default separator between domain and user (`winbind separator`
+/// in smb.conf).
+pub const DEFAULT_WINBIND_SEPARATOR: char = '\\';
+
+/// Split a winbind-style `DOMAIN<sep>user` name into its domain and user.
+///
+/// Splits at the first `sep`, since domain names cannot contain it. A name
+/// without `sep` is returned as the user with no domain.
+#[must_use]
+pub fn split_domain_user(name: &str, sep: char) -> (Option<&str>, &str) {
+    match name.split_once(sep) {
+        Some((domain, user)) => (Some(domain), user),
+        None => (None, name),
+    }
 }
 
 /// Get password entry by username.
 ///
+/// As with every single-entry lookup, an entry with an empty name is treated
+/// as not found. A hard error from one module is returned at once, without
+/// asking the remaining modules; see `getpwnam_with_policy`.
+///
 /// # Errors
 /// Returns `NssError` if the user is not found or NSS operation fails.
 pub fn getpwnam(name: &str, module: Option<NssModule>) -> NssResult<PasswdEntry> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
-    };
-
-    for mod_enum in modules {
-        match unsafe { getpwnam_r_impl(name, mod_enum, PASSWD_INIT_BUFLEN) } {
-            Ok(Some(entry)) => return Ok(entry),
-            Ok(None) => continue,
-            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => continue,
-            Err(NssError::LibraryError(_)) => continue, // Skip unavailable modules
-            Err(e) => return Err(e),
-        }
-    }
-
-    Err(NssError::NssOperationFailed {
-        errno: 0,
-        operation: NssOperation::GetPwNam,
-        return_code: NssReturnCode::NotFound,
-        module: NssModule::Files, // Placeholder
-    })
+    getpwnam_with_policy(name, module, FallbackPolicy::default())
 }
 
-/// Get password entry by user ID.
+/// Get password entry by username, choosing what a hard error from one
+/// module does to the rest of the fallback.
+///
+/// `getpwnam` uses `FallbackPolicy::StopOnError`, failing as soon as a module
+/// reports anything other than not found or unavailable. Pass
+/// `ContinueOnError` to go on to the remaining modules instead.
 ///
 /// # Errors
 /// Returns `NssError` if the user is not found or NSS operation fails.
-pub fn getpwuid(uid: uid_t, module: Option<NssModule>) -> NssResult<PasswdEntry> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
-    };
+pub fn getpwnam_with_policy(name: &str, module: Option<NssModule>, policy: FallbackPolicy) -> NssResult<PasswdEntry> {
+    getpwnam_opts(name, module, &LookupOptions::new().policy(policy))
+}
 
-    for mod_enum in modules {
-        match unsafe { getpwuid_r_impl(uid, mod_enum, PASSWD_INIT_BUFLEN) } {
-            Ok(Some(entry)) => return Ok(entry),
-            Ok(None) => continue,
-            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => continue,
-            Err(NssError::LibraryError(_)) => continue, // Skip unavailable modules
-            Err(e) => return Err(e),
+/// Get password entry by username, tuned by `opts`.
+///
+/// With `LookupOptions::new()` this is `getpwnam`. The retry count and
+/// timeout apply to each module in the chain separately.
+///
+/// # Errors
+/// Returns `NssError::Timeout` if a module does not answer within the
+/// timeout, otherwise `NssError` if the user is not found or NSS operation
+/// fails.
+pub fn getpwnam_opts(name: &str, module: Option<NssModule>, opts: &LookupOptions) -> NssResult<PasswdEntry> {
+    let buflen = opts.buflen.unwrap_or(PASSWD_INIT_BUFLEN);
+    let fields = opts.fields;
+    let lossy = opts.lossy;
+
+    lookup_name_chain(NssOperation::GetPwNam, name, module, opts.policy, |mod_enum| {
+        // Resolved on the caller's thread, in case the call goes to a worker
+        let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::load(mod_enum)? };
+        let call = move |name: &str| unsafe {
+            let mut buffer = vec![0u8; metrics::initial_buflen(NssOperation::GetPwNam, buflen)];
+            getpwnam_r_call(getpwnam_r, name, mod_enum, &mut buffer, fields, lossy)
+        };
+        match opts.timeout {
+            Some(timeout) => {
+                let name = name.to_string();
+                opts.run_within(NssOperation::GetPwNam, mod_enum, timeout, move || call(&name))
+            }
+            None => opts.retry(|| call(name)),
         }
-    }
-
-    Err(NssError::NssOperationFailed {
-        errno: 0,
-        operation: NssOperation::GetPwUid,
-        return_code: NssReturnCode::NotFound,
-        module: NssModule::Files, // Placeholder
     })
 }
 
-type SetPwEntFn = unsafe extern "C" fn() -> c_int;
-type EndPwEntFn = unsafe extern "C" fn() -> c_int;
-type GetPwEntFn = unsafe extern "C" fn(
-    result: *mut passwd,
-    buffer: *mut c_char,
-    buflen: libc::size_t,
-    errnop: *mut c_int,
-) -> c_int;
-
-unsafe fn setpwent_impl(module: NssModule) -> NssResult<()> {
-    let func_ptr = get_nss_function(NssOperation::SetPwEnt, module)?;
-    let setpwent: SetPwEntFn = mem::transmute(func_ptr);
+/// Get password entry by username, giving up once `deadline` has passed.
+///
+/// The budget covers the whole default `passwd` chain: each module gets
+/// whatever time is left, and modules reached after the deadline are not
+/// asked at all. A module call cannot be cancelled, so one that overruns is
+/// left to finish on a background thread and its answer is discarded.
+///
+/// # Errors
+/// Returns `NssError::Timeout` if the deadline passes before a module
+/// returns the entry, otherwise `NssError` if the user is not found or NSS
+/// operation fails.
+pub fn getpwnam_deadline(name: &str, deadline: Instant) -> NssResult<PasswdEntry> {
+    deadline_lookup(name, deadline, |module, remaining| getpwnam_within(name, module, remaining))
+}
 
-    let ret_code = setpwent();
-    let nss_code = NssReturnCode::from(ret_code);
+/// Walk the default `passwd` chain for `name`, calling `within` with each
+/// module and the time left before `deadline`.
+fn deadline_lookup<F>(name: &str, deadline: Instant, mut within: F) -> NssResult<PasswdEntry>
+where
+    F: FnMut(NssModule, Duration) -> NssResult<Option<PasswdEntry>>,
+{
+    lookup_name_chain(NssOperation::GetPwNam, name, None, FallbackPolicy::default(), |mod_enum| {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(NssError::Timeout { operation: NssOperation::GetPwNam, module: mod_enum });
+        }
+        within(mod_enum, remaining)
+    })
+}
 
-    if nss_code != NssReturnCode::Success {
-        return Err(NssError::NssOperationFailed {
-            errno: 0,
-            operation: NssOperation::SetPwEnt,
-            return_code: nss_code,
-            module,
-        });
+/// Get password entry by username from the library file at `lib_path`,
+/// calling its `_nss_<module_name>_getpwnam_r` directly.
+///
+/// For validating a specific build of a module, such as a staged winbind or
+/// an LDAP module, before deploying it. The modules' cached libraries, search
+/// paths and symbol templates are neither used nor changed. `module_name` is
+/// any name made of `[a-z0-9_]`, whether or not it is one of `NssModule`'s.
+/// The entry has no `source_module`, since no configured module answered,
+/// and its `source` is `module_name` uppercased.
+///
+/// Errors are attributed to the built-in module of that name, if there is
+/// one. For other names a miss is `AllModulesFailed` without attempts, and a
+/// failed call is reported as `NssError::LibraryError`.
+///
+/// # Errors
+/// Returns `NssError::LibraryError` if `module_name` is not a valid module
+/// name or the file cannot be loaded or lacks the symbol,
+/// `NssError::AllModulesFailed` if the library does not have the user, or
+/// `NssError` if the NSS operation fails.
+pub fn getpwnam_via_lib(name: &str, lib_path: &Path, module_name: &str) -> NssResult<PasswdEntry> {
+    if module_name.is_empty()
+        || !module_name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    {
+        return Err(NssError::LibraryError(format!("Invalid NSS module name {module_name:?}")));
     }
 
-    Ok(())
+    let symbol = format!("_nss_{module_name}_{}", NssOperation::GetPwNam.function_name());
+    let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::from_raw(library_symbol(lib_path, &symbol)?) };
+    getpwnam_via_fn(name, getpwnam_r, module_name)
 }
 
-unsafe fn endpwent_impl(module: NssModule) -> NssResult<()> {
-    let func_ptr = get_nss_function(NssOperation::EndPwEnt, module)?;
-    let endpwent: EndPwEntFn = mem::transmute(func_ptr);
-
-    let ret_code = endpwent();
-    let nss_code = NssReturnCode::from(ret_code);
+/// Look up `name` with the already resolved `getpwnam_r` of the module
+/// called `module_name`; see `getpwnam_via_lib`.
+fn getpwnam_via_fn(name: &str, getpwnam_r: NssFn<GetPwNameFn>, module_name: &str) -> NssResult<PasswdEntry> {
+    let module = ALL_MODULES.iter().copied().find(|module| module.name() == module_name);
+    let mut buffer = vec![0u8; PASSWD_INIT_BUFLEN];
+    // The call needs a module to attribute errors to; for other names they
+    // are rewritten below
+    let result = unsafe {
+        getpwnam_r_call(getpwnam_r, name, module.unwrap_or(NssModule::Files), &mut buffer, Fields::ALL, false)
+    };
 
-    if nss_code != NssReturnCode::Success {
-        return Err(NssError::NssOperationFailed {
-            errno: 0,
-            operation: NssOperation::EndPwEnt,
-            return_code: nss_code,
-            module,
-        });
+    match (result, module) {
+        (Ok(Some(mut entry)), _) => {
+            entry.source = module_name.to_uppercase();
+            entry.source_module = None;
+            Ok(entry)
+        }
+        (Ok(None), module) => Err(NssError::AllModulesFailed {
+            operation: NssOperation::GetPwNam,
+            attempts: module.map(|m| (m, NssReturnCode::NotFound)).into_iter().collect(),
+        }),
+        (Err(e), Some(_)) => Err(e),
+        (Err(NssError::NssOperationFailed { errno, return_code, .. }), None) => Err(NssError::LibraryError(format!(
+            "_nss_{module_name}_getpwnam_r failed with errno {errno}: {return_code:?}"
+        ))),
+        (Err(NssError::PermissionDenied { .. }), None) => {
+            Err(NssError::LibraryError(format!("_nss_{module_name}_getpwnam_r was denied")))
+        }
+        (Err(e), None) => Err(e),
     }
-
-    Ok(())
 }
 
-unsafe fn getpwent_r_impl(
-    module: NssModule,
-    buffer_len: usize,
-) -> NssResult<Option<PasswdEntry>> {
-    let func_ptr = get_nss_function(NssOperation::GetPwEnt, module)?;
-    let getpwent_r: GetPwEntFn = mem::transmute(func_ptr);
+/// `getpwnam_r` against `module` on a worker thread, waiting at most `timeout`.
+fn getpwnam_within(name: &str, module: NssModule, timeout: Duration) -> NssResult<Option<PasswdEntry>> {
+    // Resolved here rather than on the worker, so the library is loaded (or
+    // fails to load) on the caller's thread
+    let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::load(module)? };
 
-    let mut result: passwd = mem::zeroed();
-    let mut buffer = vec![0u8; buffer_len];
-    let mut errno: c_int = 0;
+    let name = name.to_string();
+    call_within(NssOperation::GetPwNam, module, timeout, move || {
+        let mut buffer = vec![0u8; metrics::initial_buflen(NssOperation::GetPwNam, PASSWD_INIT_BUFLEN)];
+        unsafe { getpwnam_r_call(getpwnam_r, &name, module, &mut buffer, Fields::ALL, false) }
+    })
+}
 
-    let ret_code = getpwent_r(
-        &mut result,
-        buffer.as_mut_ptr().cast::<c_char>(),
-        buffer_len,
-        &mut errno,
-    );
+/// Get password entry by username from `module`, reporting the module's own
+/// return code and errno instead of an `NssResult`.
+///
+/// For wrappers such as CLI tools that map the precise NSS outcome to an exit
+/// code; prefer `getpwnam` otherwise. The entry is only present on `Success`,
+/// and not even then if the module returned an empty name. Its fields are
+/// decoded lossily, as with `LookupOptions::lossy`, so a success always comes
+/// with its entry.
+/// `ERANGE` is retried internally like everywhere else. A module library that
+/// cannot be loaded is reported as `Unavail` with errno 0, as glibc does.
+#[must_use]
+pub fn getpwnam_raw(name: &str, module: NssModule) -> (NssReturnCode, Option<PasswdEntry>, u32) {
+    let Ok(getpwnam_r) = (unsafe { NssFn::<GetPwNameFn>::load(module) }) else {
+        return (NssReturnCode::Unavail, None, 0);
+    };
+    let getpwnam_r = getpwnam_r.get();
+    // No account can have a NUL in its name
+    let Ok(name_c) = CString::new(name) else {
+        return (NssReturnCode::NotFound, None, 0);
+    };
 
-    match errno {
-        0 => {} // Success
-        libc::ERANGE => {
-            // Buffer too small, try with larger buffer
-            return getpwent_r_impl(module, buffer_len * 2);
-        }
-        _ => {
-            return Err(NssError::NssOperationFailed {
-                errno: errno.unsigned_abs(),
-                operation: NssOperation::GetPwEnt,
-                return_code: NssReturnCode::from(ret_code),
-                module,
-            });
+    let mut buffer = vec![0u8; metrics::initial_buflen(NssOperation::GetPwNam, PASSWD_INIT_BUFLEN)];
+    loop {
+        let mut result: passwd = unsafe { mem::zeroed() };
+        let mut errno: c_int = 0;
+        let ret_code = unsafe {
+            getpwnam_r(
+                name_c.as_ptr(),
+                &mut result,
+                buffer.as_mut_ptr().cast::<c_char>(),
+                buffer.len(),
+                &mut errno,
+            )
+        };
+
+        if errno == libc::ERANGE {
+            buffer.resize(buffer.len() * 2, 0);
+            continue;
         }
-    }
 
-    let nss_code = NssReturnCode::from(ret_code);
-    if nss_code != NssReturnCode::Success {
-        return Ok(None);
+        let nss_code = NssReturnCode::from(ret_code);
+        let entry = if nss_code == NssReturnCode::Success {
+            metrics::record_buflen(NssOperation::GetPwNam, buffer.len());
+            unsafe { parse_lookup_fields(&result, &module, Fields::ALL, true) }.ok().flatten()
+        } else {
+            None
+        };
+        return (nss_code, entry, errno.unsigned_abs());
     }
+}
 
-    parse_passwd_result(&result, &module)
+/// Get password entry by username from only the `index`th module of the
+/// default `passwd` order (see `default_modules`), counting from 0.
+///
+/// # Errors
+/// Returns `NssError::ModuleIndexOutOfRange` if fewer modules are configured,
+/// otherwise `NssError` if the user is not found or NSS operation fails.
+pub fn getpwnam_nth(name: &str, index: usize) -> NssResult<PasswdEntry> {
+    let modules = default_modules("passwd");
+    let module = *modules
+        .get(index)
+        .ok_or(NssError::ModuleIndexOutOfRange { index, count: modules.len() })?;
+    getpwnam(name, Some(module))
 }
 
-pub struct PasswdIterator {
-    module: NssModule,
-    initialized: bool,
+/// Reusable record buffer for `getpwnam_reuse`.
+///
+/// Create one per hot loop (or per thread) and pass it to every lookup: the
+/// buffer grows when a module reports `ERANGE` and keeps its size afterwards,
+/// so steady-state lookups allocate nothing for the record.
+#[derive(Debug)]
+pub struct PwLookupScratch {
+    buffer: Vec<u8>,
 }
 
-impl PasswdIterator {
+impl PwLookupScratch {
     #[must_use]
-    pub fn new(module: NssModule) -> Self {
-        PasswdIterator {
-            module,
-            initialized: false,
-        }
+    pub fn new() -> Self {
+        PwLookupScratch { buffer: vec![0u8; PASSWD_INIT_BUFLEN] }
     }
-}
-
-impl Iterator for PasswdIterator {
-    type Item = NssResult<PasswdEntry>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        unsafe {
-            if !self.initialized {
-                if let Err(e) = setpwent_impl(self.module) {
-                    return Some(Err(e));
-                }
-                self.initialized = true;
-            }
 
-            match getpwent_r_impl(self.module, PASSWD_INIT_BUFLEN) {
-                Ok(Some(entry)) => Some(Ok(entry)),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
-            }
-        }
+    /// Current buffer size in bytes.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
     }
 }
 
-impl Drop for PasswdIterator {
-    fn drop(&mut self) {
-        if self.initialized {
-            unsafe {
-                let _ = endpwent_impl(self.module);
-            }
-        }
+impl Default for PwLookupScratch {
+    fn default() -> Self {
+        PwLookupScratch::new()
     }
 }
 
-/// Create an iterator for password entries from the specified NSS module.
-#[must_use]
-pub fn iterpw(module: NssModule) -> PasswdIterator {
-    PasswdIterator::new(module)
+/// Like `getpwnam`, but reads the record into `scratch` instead of a fresh
+/// buffer.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwnam_reuse(name: &str, module: Option<NssModule>, scratch: &mut PwLookupScratch) -> NssResult<PasswdEntry> {
+    lookup_name_chain(NssOperation::GetPwNam, name, module, FallbackPolicy::default(), |mod_enum| unsafe {
+        getpwnam_r_into(name, mod_enum, &mut scratch.buffer, Fields::ALL)
+    })
 }
 
-/// Get all password entries from the specified NSS module(s).
+/// Get password entry by user ID.
 ///
 /// # Errors
-/// Returns `NssError` if NSS operation fails.
-pub fn getpwall(module: Option<NssModule>) -> NssResult<Vec<PasswdEntry>> {
-    let modules = match module {
-        Some(m) => vec![m],
-        None => vec![NssModule::Files, NssModule::Sss, NssModule::Winbind],
-    };
-
-    let mut all_entries = Vec::new();
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwuid(uid: uid_t, module: Option<NssModule>) -> NssResult<PasswdEntry> {
+    getpwuid_opts(uid, module, &LookupOptions::new())
+}
 
-    for mod_enum in modules {
-        let mut entries = Vec::new();
-        for result in iterpw(mod_enum) {
-            match result {
-                Ok(entry) => entries.push(entry),
-                Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }) => break,
-                Err(NssError::LibraryError(_)) => {
-                    // Library not available (e.g., winbind/sss not installed), skip this module
-                    break;
-                }
-                Err(e) => return Err(e),
-            }
+/// Get password entry by user ID, tuned by `opts`; see `getpwnam_opts`.
+///
+/// # Errors
+/// Returns `NssError::Timeout` if a module does not answer within the
+/// timeout, otherwise `NssError` if the user is not found or NSS operation
+/// fails.
+pub fn getpwuid_opts(uid: uid_t, module: Option<NssModule>, opts: &LookupOptions) -> NssResult<PasswdEntry> {
+    let buflen = opts.buflen.unwrap_or(PASSWD_INIT_BUFLEN);
+    let fields = opts.fields;
+    let lossy = opts.lossy;
+
+    lookup_chain(NssOperation::GetPwUid, module, opts.policy, |mod_enum| {
+        let getpwuid_r = unsafe { NssFn::<GetPwUidFn>::load(mod_enum)? };
+        let call = move || unsafe {
+            let buflen = metrics::initial_buflen(NssOperation::GetPwUid, buflen);
+            getpwuid_r_call(getpwuid_r, uid, mod_enum, buflen, |result| {
+                parse_lookup_fields(result, &mod_enum, fields, lossy)
+            })
+        };
+        match opts.timeout {
+            Some(timeout) => opts.run_within(NssOperation::GetPwUid, mod_enum, timeout, call),
+            None => opts.retry(call),
         }
-        all_entries.extend(entries);
-    }
-
-    Ok(all_entries)
+    })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Password entry of the calling process's effective user.
+///
+/// Shorthand for `getpwuid(geteuid(), None)`.
+///
+/// # Errors
+/// Returns `NssError` if no module has the user or an NSS operation fails.
+pub fn getpwuid_self() -> NssResult<PasswdEntry> {
+    getpwuid(unsafe { libc::geteuid() }, None)
+}
 
-    #[test]
-    fn test_passwd_entry_creation() {
-        let entry = PasswdEntry {
-            pw_name: "testuser".to_string(),
+/// Password entry of the logged-in user, by name.
+///
+/// The name is taken from `$USER`, or from `getlogin(3)` if that is unset, so
+/// unlike `getpwuid_self` this follows the login session rather than the
+/// effective uid (they differ under `sudo` or setuid programs).
+///
+/// # Errors
+/// Returns `NssError::NullPointer` if neither source yields a name, otherwise
+/// `NssError` if no module has the user or an NSS operation fails.
+pub fn getpwnam_self() -> NssResult<PasswdEntry> {
+    let name = match std::env::var("USER") {
+        Ok(name) if !name.is_empty() => name,
+        _ => unsafe {
+            let login = libc::getlogin();
+            if login.is_null() {
+                return Err(NssError::NullPointer);
+            }
+            CStr::from_ptr(login).to_string_lossy().into_owned()
+        },
+    };
+    getpwnam(&name, None)
+}
+
+/// Like `getpwnam`, but only decodes the string fields in `fields`.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwnam_fields(name: &str, module: Option<NssModule>, fields: Fields) -> NssResult<PasswdEntry> {
+    getpwnam_opts(name, module, &LookupOptions::new().fields(fields))
+}
+
+/// Home directory of the user `name`, decoding no other field.
+///
+/// The path keeps the exact bytes the module returned.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn home_dir(name: &str, module: Option<NssModule>) -> NssResult<PathBuf> {
+    Ok(PathBuf::from(getpwnam_fields(name, module, Fields::DIR)?.dir_os()))
+}
+
+/// Login shell of the user `name`, decoding no other field.
+///
+/// The path keeps the exact bytes the module returned.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn shell(name: &str, module: Option<NssModule>) -> NssResult<PathBuf> {
+    Ok(PathBuf::from(getpwnam_fields(name, module, Fields::SHELL)?.shell_os()))
+}
+
+/// Like `getpwuid`, but only decodes the string fields in `fields`.
+///
+/// Useful in hot loops that need just part of the entry, such as building a
+/// uid-to-name map with `Fields::NAME`.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwuid_fields(uid: uid_t, module: Option<NssModule>, fields: Fields) -> NssResult<PasswdEntry> {
+    getpwuid_opts(uid, module, &LookupOptions::new().fields(fields))
+}
+
+/// Whether `name` and `uid` describe the same account in both directions:
+/// `getpwnam(name)` has uid `uid` and `getpwuid(uid)` has name `name`.
+///
+/// A mapping that does not exist counts as a mismatch rather than an error.
+///
+/// # Errors
+/// Returns `NssError` if either lookup fails with a hard error.
+pub fn verify_name_uid(name: &str, uid: uid_t, module: Option<NssModule>) -> NssResult<bool> {
+    let found = |result: NssResult<PasswdEntry>| match result {
+        Ok(entry) => Ok(Some(entry)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    };
+
+    let forward = found(getpwnam(name, module))?;
+    if forward.is_none_or(|entry| entry.pw_uid != uid) {
+        return Ok(false);
+    }
+    let reverse = found(getpwuid(uid, module))?;
+    Ok(reverse.is_some_and(|entry| entry.pw_name == name))
+}
+
+/// Whether any user has the given UID.
+///
+/// Cheaper than `getpwuid` when only existence matters (e.g. when allocating
+/// a new UID): the record is not decoded.
+///
+/// # Errors
+/// Returns `NssError` if an NSS operation fails with a hard error.
+pub fn uid_exists(uid: uid_t, module: Option<NssModule>) -> NssResult<bool> {
+    match lookup_chain(NssOperation::GetPwUid, module, FallbackPolicy::default(), |mod_enum| unsafe {
+        getpwuid_r_with(uid, mod_enum, PASSWD_INIT_BUFLEN, |_| Ok(Some(())))
+    }) {
+        Ok(()) => Ok(true),
+        Err(NssError::AllModulesFailed { .. }) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Look up `name` in every module and return each module's outcome.
+///
+/// Unlike `getpwnam`, this does not stop at the first hit, which makes it
+/// possible to audit accounts defined differently by several modules. Every
+/// module appears in the result: a miss is reported as a `NotFound` error and
+/// a module that is unavailable or not installed as an `Unavail` error.
+#[must_use]
+pub fn getpwnam_all_sources(name: &str) -> Vec<(NssModule, NssResult<PasswdEntry>)> {
+    all_sources(&ALL_MODULES, NssOperation::GetPwNam, |module| unsafe {
+        getpwnam_r_impl(name, module, PASSWD_INIT_BUFLEN)
+    })
+}
+
+/// Modules in which a user called `name` exists, in `ALL_MODULES` order.
+///
+/// A lighter `getpwnam_all_sources` for showing where an account is
+/// defined: no string field is decoded, and modules that are unavailable,
+/// not installed or fail are simply left out.
+#[must_use]
+pub fn which_modules_have_user(name: &str) -> Vec<NssModule> {
+    ALL_MODULES
+        .iter()
+        .copied()
+        .filter(|&module| {
+            let mut buffer = vec![0u8; PASSWD_INIT_BUFLEN];
+            matches!(unsafe { getpwnam_r_into(name, module, &mut buffer, Fields(0)) }, Ok(Some(_)))
+        })
+        .collect()
+}
+
+fn all_sources<F>(
+    modules: &[NssModule],
+    operation: NssOperation,
+    mut lookup: F,
+) -> Vec<(NssModule, NssResult<PasswdEntry>)>
+where
+    F: FnMut(NssModule) -> NssResult<Option<PasswdEntry>>,
+{
+    modules
+        .iter()
+        .map(|&module| {
+            let failed = |return_code| NssError::NssOperationFailed {
+                errno: 0,
+                operation,
+                return_code,
+                module,
+            };
+            let outcome = match lookup(module) {
+                Ok(Some(entry)) => Ok(entry),
+                Ok(None) => Err(failed(NssReturnCode::NotFound)),
+                Err(NssError::LibraryError(_)) => Err(failed(NssReturnCode::Unavail)),
+                Err(e) => Err(e),
+            };
+            (module, outcome)
+        })
+        .collect()
+}
+
+/// Get password entry by username, querying all `modules` concurrently.
+///
+/// Each module is queried on its own thread and the first successful answer
+/// is returned, so a slow directory service does not delay a hit in FILES.
+/// Results that arrive together are resolved in favour of the module listed
+/// first. Lookups that are still running when an answer is found are not
+/// interrupted; they finish in the background and their results are discarded.
+///
+/// # Errors
+/// Returns `NssError` if no module has the user, or the first hard error (in
+/// list order) if none succeeded.
+pub fn getpwnam_race(name: &str, modules: &[NssModule]) -> NssResult<PasswdEntry> {
+    let name = name.to_string();
+    race_lookup(modules, move |module| unsafe {
+        getpwnam_r_impl(&name, module, PASSWD_INIT_BUFLEN)
+    })
+}
+
+fn race_lookup<F>(modules: &[NssModule], lookup: F) -> NssResult<PasswdEntry>
+where
+    F: Fn(NssModule) -> NssResult<Option<PasswdEntry>> + Send + Sync + 'static,
+{
+    let lookup = Arc::new(lookup);
+    let (tx, rx) = mpsc::channel();
+
+    for (index, &module) in modules.iter().enumerate() {
+        let tx = tx.clone();
+        let lookup = Arc::clone(&lookup);
+        thread::spawn(move || {
+            // The receiver is gone once another module answered
+            let _ = tx.send((index, lookup(module)));
+        });
+    }
+    drop(tx);
+
+    let mut outcomes: Vec<Option<NssResult<Option<PasswdEntry>>>> = modules.iter().map(|_| None).collect();
+    while let Ok((index, result)) = rx.recv() {
+        outcomes[index] = Some(result);
+        while let Ok((index, result)) = rx.try_recv() {
+            outcomes[index] = Some(result);
+        }
+
+        if let Some(slot) = outcomes.iter_mut().find(|o| matches!(o, Some(Ok(Some(_))))) {
+            if let Some(Ok(Some(entry))) = slot.take() {
+                return Ok(entry);
+            }
+        }
+    }
+
+    let mut attempts = Vec::new();
+    for (&module, outcome) in modules.iter().zip(outcomes) {
+        match outcome {
+            Some(Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, .. }))
+            | Some(Err(NssError::LibraryError(_))) => attempts.push((module, NssReturnCode::Unavail)),
+            Some(Err(e)) => return Err(e),
+            Some(Ok(_)) => attempts.push((module, NssReturnCode::NotFound)),
+            None => continue,
+        }
+    }
+
+    Err(NssError::AllModulesFailed { operation: NssOperation::GetPwNam, attempts })
+}
+
+struct SetPwEntFn;
+
+unsafe impl NssSymbol for SetPwEntFn {
+    type Fn = unsafe extern "C" fn() -> c_int;
+    const OPERATION: NssOperation = NssOperation::SetPwEnt;
+}
+struct EndPwEntFn;
+
+unsafe impl NssSymbol for EndPwEntFn {
+    type Fn = unsafe extern "C" fn() -> c_int;
+    const OPERATION: NssOperation = NssOperation::EndPwEnt;
+}
+struct GetPwEntFn;
+
+unsafe impl NssSymbol for GetPwEntFn {
+    type Fn = unsafe extern "C" fn(
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int;
+    const OPERATION: NssOperation = NssOperation::GetPwEnt;
+}
+
+unsafe fn setpwent_impl(module: NssModule) -> NssResult<()> {
+    let setpwent = NssFn::<SetPwEntFn>::load(module)?.get();
+
+    let ret_code = setpwent();
+    let nss_code = NssReturnCode::from(ret_code);
+
+    if nss_code != NssReturnCode::Success {
+        return Err(NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::SetPwEnt,
+            return_code: nss_code,
+            module,
+        });
+    }
+
+    Ok(())
+}
+
+pub(crate) unsafe fn endpwent_impl(module: NssModule) -> NssResult<()> {
+    let endpwent = NssFn::<EndPwEntFn>::load(module)?.get();
+
+    let ret_code = endpwent();
+    let nss_code = NssReturnCode::from(ret_code);
+
+    if nss_code != NssReturnCode::Success {
+        return Err(NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::EndPwEnt,
+            return_code: nss_code,
+            module,
+        });
+    }
+
+    Ok(())
+}
+
+/// Call `getpwent_r`, handing the next record to `on_found` while the buffer
+/// backing it is still alive.
+unsafe fn getpwent_r_with<T>(
+    module: NssModule,
+    buffer_len: usize,
+    on_found: impl FnOnce(&passwd) -> NssResult<Option<T>>,
+) -> NssResult<Option<T>> {
+    let buffer_len = metrics::initial_buflen(NssOperation::GetPwEnt, buffer_len);
+    let getpwent_r = NssFn::<GetPwEntFn>::load(module)?.get();
+
+    let mut result: passwd = mem::zeroed();
+    let mut buffer = vec![0u8; buffer_len];
+    let mut errno: c_int = 0;
+
+    let ret_code = getpwent_r(
+        &mut result,
+        buffer.as_mut_ptr().cast::<c_char>(),
+        buffer_len,
+        &mut errno,
+    );
+
+    let nss_code = NssReturnCode::from(ret_code);
+
+    // Modules signal the end of enumeration either with NOTFOUND or with
+    // ENOENT alongside a failure code; anything else is a real error
+    let end_of_data = nss_code == NssReturnCode::NotFound
+        || (errno == libc::ENOENT && nss_code != NssReturnCode::Success);
+
+    match errno {
+        libc::ERANGE => {
+            // Buffer too small, try with larger buffer
+            return getpwent_r_with(module, buffer_len * 2, on_found);
+        }
+        _ if end_of_data => return Ok(None),
+        0 => metrics::record_buflen(NssOperation::GetPwEnt, buffer_len), // Success
+        _ => {
+            return Err(NssError::from_errno(errno, NssOperation::GetPwEnt, nss_code, module));
+        }
+    }
+
+    if nss_code != NssReturnCode::Success {
+        return Err(NssError::NssOperationFailed {
+            errno: 0,
+            operation: NssOperation::GetPwEnt,
+            return_code: nss_code,
+            module,
+        });
+    }
+
+    on_found(&result)
+}
+
+/// Enumerates the passwd entries of one module.
+///
+/// Not `Send` or `Sync`: NSS modules keep the `setpwent` cursor in
+/// thread-local state, so an iterator resumed on another thread would read
+/// (or reset) that thread's enumeration instead of its own.
+pub struct PasswdIterator {
+    module: NssModule,
+    initialized: bool,
+    /// Records returned since the enumeration (re)started with `reset`.
+    position: usize,
+    /// `setpwent` calls issued to recover from `TryAgain`.
+    reinits: usize,
+    _thread_bound: PhantomData<*const ()>,
+}
+
+/// Times a `PasswdIterator` re-issues `setpwent` after `getpwent_r` reports
+/// `TryAgain`, before passing the error on.
+const MAX_ENUM_REINITS: usize = 2;
+
+impl PasswdIterator {
+    #[must_use]
+    pub fn new(module: NssModule) -> Self {
+        PasswdIterator {
+            module,
+            initialized: false,
+            position: 0,
+            reinits: 0,
+            _thread_bound: PhantomData,
+        }
+    }
+
+    /// Rewind to the start: end the current enumeration, if any, so that the
+    /// next `next()` calls `setpwent` again.
+    ///
+    /// This works on an exhausted iterator too. The same thread-local
+    /// constraints apply as for a fresh iterator: the restarted enumeration
+    /// belongs to the calling thread's NSS state.
+    pub fn reset(&mut self) {
+        if self.initialized {
+            unsafe {
+                let _ = endpwent_impl(self.module);
+            }
+            self.initialized = false;
+        }
+        self.position = 0;
+        self.reinits = 0;
+    }
+
+    /// Advance the enumeration, converting the record with `on_found`.
+    ///
+    /// Some modules lose their cursor after a transient failure and only
+    /// recover once `setpwent` is called again. On `TryAgain` the enumeration
+    /// is restarted and the records already returned are skipped, assuming
+    /// the module enumerates in the same order; this happens at most
+    /// `MAX_ENUM_REINITS` times before the error is returned.
+    fn next_with<T>(&mut self, mut on_found: impl FnMut(&passwd) -> NssResult<Option<T>>) -> Option<NssResult<T>> {
+        unsafe {
+            loop {
+                if !self.initialized {
+                    if let Err(e) = setpwent_impl(self.module) {
+                        return Some(Err(e));
+                    }
+                    self.initialized = true;
+
+                    for _ in 0..self.position {
+                        match getpwent_r_with(self.module, PASSWD_INIT_BUFLEN, |_| Ok(Some(()))) {
+                            Ok(Some(())) => {}
+                            Ok(None) => return None,
+                            Err(e) => return Some(Err(e)),
+                        }
+                    }
+                }
+
+                match getpwent_r_with(self.module, PASSWD_INIT_BUFLEN, &mut on_found) {
+                    Err(NssError::NssOperationFailed { return_code: NssReturnCode::TryAgain, .. })
+                        if self.reinits < MAX_ENUM_REINITS =>
+                    {
+                        self.reinits += 1;
+                        self.initialized = false;
+                    }
+                    result => {
+                        if matches!(result, Ok(Some(_))) {
+                            self.position += 1;
+                        }
+                        return result.transpose();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for PasswdIterator {
+    type Item = NssResult<PasswdEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let module = self.module;
+        self.next_with(|result| unsafe { parse_passwd_result(result, &module) })
+    }
+}
+
+impl Drop for PasswdIterator {
+    fn drop(&mut self) {
+        if self.initialized {
+            unsafe {
+                let _ = endpwent_impl(self.module);
+            }
+        }
+    }
+}
+
+const LAZY_NAME: usize = 0;
+const LAZY_GECOS: usize = 1;
+const LAZY_DIR: usize = 2;
+const LAZY_SHELL: usize = 3;
+
+/// Passwd entry whose string fields are decoded on first access.
+///
+/// The record's raw field bytes are copied into one allocation and each
+/// field becomes a `String` only when its accessor is first called, so
+/// scanning many entries for a match skips decoding the fields the scan does
+/// not look at. Decoding is lossy, as with `LookupOptions::lossy`; convert
+/// with `PasswdEntry::from` for the full entry including the raw bytes.
+#[derive(Debug, Clone)]
+pub struct LazyPasswdEntry {
+    uid: uid_t,
+    gid: gid_t,
+    module: NssModule,
+    bytes: Box<[u8]>,
+    /// Field `i` is `bytes[ends[i - 1]..ends[i]]`, starting from 0.
+    ends: [usize; 4],
+    decoded: [OnceCell<String>; 4],
+}
+
+impl LazyPasswdEntry {
+    unsafe fn from_raw(result: &passwd, module: NssModule) -> Option<Self> {
+        if result.pw_name.is_null() {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        let mut ends = [0; 4];
+        for (end, ptr) in ends.iter_mut().zip([result.pw_name, result.pw_gecos, result.pw_dir, result.pw_shell]) {
+            if !ptr.is_null() {
+                bytes.extend_from_slice(CStr::from_ptr(ptr).to_bytes());
+            }
+            *end = bytes.len();
+        }
+
+        Some(LazyPasswdEntry {
+            uid: result.pw_uid,
+            gid: result.pw_gid,
+            module,
+            bytes: bytes.into_boxed_slice(),
+            ends,
+            decoded: Default::default(),
+        })
+    }
+
+    fn raw_field(&self, field: usize) -> &[u8] {
+        let start = if field == 0 { 0 } else { self.ends[field - 1] };
+        &self.bytes[start..self.ends[field]]
+    }
+
+    fn field(&self, field: usize) -> &str {
+        self.decoded[field].get_or_init(|| String::from_utf8_lossy(self.raw_field(field)).into_owned())
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.field(LAZY_NAME)
+    }
+
+    #[must_use]
+    pub fn gecos(&self) -> &str {
+        self.field(LAZY_GECOS)
+    }
+
+    #[must_use]
+    pub fn dir(&self) -> &str {
+        self.field(LAZY_DIR)
+    }
+
+    #[must_use]
+    pub fn shell(&self) -> &str {
+        self.field(LAZY_SHELL)
+    }
+
+    #[must_use]
+    pub fn uid(&self) -> uid_t {
+        self.uid
+    }
+
+    #[must_use]
+    pub fn gid(&self) -> gid_t {
+        self.gid
+    }
+
+    /// Module that produced the entry.
+    #[must_use]
+    pub fn source_module(&self) -> NssModule {
+        self.module
+    }
+}
+
+impl From<LazyPasswdEntry> for PasswdEntry {
+    fn from(entry: LazyPasswdEntry) -> Self {
+        let (pw_name, name) = decode_bytes(entry.raw_field(LAZY_NAME));
+        let (pw_gecos, gecos) = decode_bytes(entry.raw_field(LAZY_GECOS));
+        let (pw_dir, dir) = decode_bytes(entry.raw_field(LAZY_DIR));
+        let (pw_shell, shell) = decode_bytes(entry.raw_field(LAZY_SHELL));
+
+        PasswdEntry {
+            pw_name,
+            pw_uid: entry.uid,
+            pw_gid: entry.gid,
+            pw_gecos,
+            pw_dir,
+            pw_shell,
+            source: entry.module.source_label(),
+            source_module: Some(entry.module),
+            extra: HashMap::new(),
+            raw: RawPasswdFields { name, gecos, dir, shell },
+        }
+    }
+}
+
+/// Enumerates the passwd entries of one module as `LazyPasswdEntry` values.
+///
+/// Bound to its thread for the same reason as `PasswdIterator`.
+pub struct LazyPasswdIterator {
+    inner: PasswdIterator,
+}
+
+impl LazyPasswdIterator {
+    /// Rewind to the start; see `PasswdIterator::reset`.
+    pub fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+impl Iterator for LazyPasswdIterator {
+    type Item = NssResult<LazyPasswdEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let module = self.inner.module;
+        self.inner.next_with(|result| Ok(unsafe { LazyPasswdEntry::from_raw(result, module) }))
+    }
+}
+
+/// Create an iterator of lazily decoded password entries from `module`.
+#[must_use]
+pub fn iterpw_lazy(module: NssModule) -> LazyPasswdIterator {
+    LazyPasswdIterator { inner: PasswdIterator::new(module) }
+}
+
+/// Create an iterator for password entries from the specified NSS module.
+#[must_use]
+pub fn iterpw(module: NssModule) -> PasswdIterator {
+    PasswdIterator::new(module)
+}
+
+/// Enumerate every default `passwd` module, tagging each item with its module.
+///
+/// Unlike `getpwall`, which silently skips a module that is unavailable, a
+/// failing module yields its error as a `(module, Err(..))` item and the
+/// stream moves on to the next module, so consumers can report e.g. "winbind
+/// unavailable" while still receiving the other modules' entries. Each module
+/// yields at most one error. Like `PasswdIterator`, the iterator must stay on
+/// the thread that created it.
+pub fn iterpw_all_tagged() -> impl Iterator<Item = (NssModule, NssResult<PasswdEntry>)> {
+    default_modules("passwd").into_iter().flat_map(|module| {
+        let mut failed = false;
+        iterpw(module).map_while(move |result| {
+            if failed {
+                return None;
+            }
+            failed = result.is_err();
+            Some((module, result))
+        })
+    })
+}
+
+/// Get all password entries from the specified NSS module(s).
+///
+/// Modules are visited in `default_modules` order, but entries within a
+/// module come in whatever order it enumerates them, which may change between
+/// calls. Use `getpwall_sorted` with `SortKey::Canonical` for a reproducible
+/// order.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall(module: Option<NssModule>) -> NssResult<Vec<PasswdEntry>> {
+    getpwall_with_capacity(module, 0)
+}
+
+/// Get all password entries, pre-sizing the result for `capacity` entries.
+///
+/// Useful when the caller knows roughly how many users to expect. When several
+/// modules are enumerated, space for as many entries as the first module
+/// returned is also reserved before each subsequent module.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall_with_capacity(module: Option<NssModule>, capacity: usize) -> NssResult<Vec<PasswdEntry>> {
+    let mut all_entries = Vec::with_capacity(capacity);
+    // Modules opened so far, to spot the first entry of each later module
+    let opened = Cell::new(0usize);
+    let mut current = 1;
+    let mut first_module_count = None;
+
+    enumerate_chain(
+        "passwd",
+        module,
+        |mod_enum| {
+            opened.set(opened.get() + 1);
+            iterpw(mod_enum)
+        },
+        |entry| {
+            if opened.get() != current {
+                // Everything gathered so far came from the first module
+                current = opened.get();
+                all_entries.reserve(*first_module_count.get_or_insert(all_entries.len()));
+            }
+            all_entries.push(entry);
+            ControlFlow::Continue(())
+        },
+    )?;
+
+    Ok(all_entries)
+}
+
+/// Number of password entries in the specified NSS module(s).
+///
+/// Enumerates like `getpwall`, including skipping unavailable or missing
+/// modules and summing over the default modules when none is given, but
+/// without decoding any entry.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn count_passwd(module: Option<NssModule>) -> NssResult<usize> {
+    let mut count = 0;
+    enumerate_chain(
+        "passwd",
+        module,
+        |mod_enum| {
+            let mut users = iterpw(mod_enum);
+            std::iter::from_fn(move || users.next_with(|_| Ok(Some(()))))
+        },
+        |()| {
+            count += 1;
+            ControlFlow::Continue(())
+        },
+    )?;
+    Ok(count)
+}
+
+/// Call `f` with each password entry from the specified NSS module(s), in
+/// `getpwall` order, without collecting them.
+///
+/// Returning `ControlFlow::Break` from `f` ends the enumeration at once; no
+/// further entries or modules are read. Unavailable or missing modules are
+/// skipped, as in `getpwall`.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn for_each_passwd<F>(module: Option<NssModule>, f: F) -> NssResult<()>
+where
+    F: FnMut(PasswdEntry) -> ControlFlow<()>,
+{
+    enumerate_chain("passwd", module, iterpw, f)
+}
+
+/// K-way merge of per-module runs that are each sorted by uid.
+struct MergeSortedPasswd {
+    errors: std::vec::IntoIter<NssError>,
+    runs: Vec<std::iter::Peekable<std::vec::IntoIter<PasswdEntry>>>,
+    last_uid: Option<uid_t>,
+}
+
+impl Iterator for MergeSortedPasswd {
+    type Item = NssResult<PasswdEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.errors.next() {
+            return Some(Err(e));
+        }
+
+        loop {
+            // min_by_key keeps the first of equal uids, i.e. the earliest module
+            let run = self
+                .runs
+                .iter_mut()
+                .filter_map(|run| Some((run.peek()?.pw_uid, run)))
+                .min_by_key(|(uid, _)| *uid)?
+                .1;
+            let entry = run.next()?;
+            if self.last_uid.replace(entry.pw_uid) != Some(entry.pw_uid) {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+/// Password entries from `modules`, merged into one stream in ascending uid
+/// order with one entry per uid.
+///
+/// NSS modules do not promise any enumeration order, so each module is
+/// enumerated up front into its own buffer and sorted by uid (cheap when it
+/// already is); the buffers are then merged lazily, without sorting the
+/// combined list. When several entries share a uid, the one from the module
+/// listed first in `modules` is kept, and within a module the first one
+/// enumerated.
+///
+/// Unavailable or missing modules are skipped, as in `getpwall`. Any other
+/// error ends that module's enumeration and is yielded before the entries.
+pub fn merge_sorted_passwd(modules: &[NssModule]) -> impl Iterator<Item = NssResult<PasswdEntry>> {
+    let mut errors = Vec::new();
+    let mut runs = Vec::with_capacity(modules.len());
+
+    for &mod_enum in modules {
+        let mut run = Vec::new();
+        let walked = enumerate_chain("passwd", Some(mod_enum), iterpw, |entry| {
+            run.push(entry);
+            ControlFlow::Continue(())
+        });
+        if let Err(e) = walked {
+            errors.push(e);
+        }
+        // Stable, so duplicates within a module keep enumeration order
+        run.sort_by_key(|entry| entry.pw_uid);
+        runs.push(run.into_iter().peekable());
+    }
+
+    MergeSortedPasswd { errors: errors.into_iter(), runs, last_uid: None }
+}
+
+/// Field used to order the results of `getpwall_sorted`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Uid,
+    Name,
+    Source,
+    /// `(source, pw_uid, pw_name)`, which does not depend on enumeration
+    /// order at all; use it for reproducible output such as audit diffs.
+    Canonical,
+}
+
+/// Get all password entries, sorted by `by`.
+///
+/// The sort is stable: entries with equal keys keep their `getpwall` order, so
+/// sorting by `Source` groups entries by module while preserving the order
+/// each module returned them in. Only `SortKey::Canonical` gives the same
+/// order every time regardless of how modules enumerate.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall_sorted(module: Option<NssModule>, by: SortKey) -> NssResult<Vec<PasswdEntry>> {
+    let mut entries = getpwall(module)?;
+    match by {
+        SortKey::Uid => entries.sort_by_key(|e| e.pw_uid),
+        SortKey::Name => entries.sort_by(|a, b| a.pw_name.cmp(&b.pw_name)),
+        SortKey::Source => entries.sort_by(|a, b| a.source.cmp(&b.source)),
+        // PasswdEntry's Ord is (pw_uid, pw_name)
+        SortKey::Canonical => entries.sort_by(|a, b| a.source.cmp(&b.source).then_with(|| a.cmp(b))),
+    }
+    Ok(entries)
+}
+
+/// All password entries keyed by username.
+///
+/// If a name appears more than once (e.g. defined by two modules), the last
+/// entry in `getpwall` order wins.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall_by_name(module: Option<NssModule>) -> NssResult<HashMap<String, PasswdEntry>> {
+    Ok(getpwall(module)?
+        .into_iter()
+        .map(|entry| (entry.pw_name.clone(), entry))
+        .collect())
+}
+
+/// All password entries keyed by UID.
+///
+/// If a UID appears more than once (e.g. two names sharing it, or the same
+/// account from two modules), the last entry in `getpwall` order wins; see
+/// `find_uid_collisions` to detect the former.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall_by_uid(module: Option<NssModule>) -> NssResult<HashMap<uid_t, PasswdEntry>> {
+    Ok(getpwall(module)?
+        .into_iter()
+        .map(|entry| (entry.pw_uid, entry))
+        .collect())
+}
+
+/// UIDs that `module` maps to more than one username, in ascending order.
+///
+/// Each UID comes with every name that has it, in enumeration order. Only
+/// one module is checked, since the same account served by FILES and a
+/// directory service is not a collision.
+///
+/// # Errors
+/// Returns `NssError` if enumeration fails.
+pub fn find_uid_collisions(module: NssModule) -> NssResult<Vec<(uid_t, Vec<String>)>> {
+    let entries = getpwall(Some(module))?;
+    Ok(id_collisions(entries.into_iter().map(|e| (e.pw_uid, e.pw_name))))
+}
+
+/// Ids shared by more than one name, ascending, each with its names in order.
+pub(crate) fn id_collisions(entries: impl IntoIterator<Item = (u32, String)>) -> Vec<(u32, Vec<String>)> {
+    let mut names_by_id: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for (id, name) in entries {
+        names_by_id.entry(id).or_default().push(name);
+    }
+
+    names_by_id.into_iter().filter(|(_, names)| names.len() > 1).collect()
+}
+
+/// Get one page of password entries: skip `offset` entries, then take `limit`.
+///
+/// Entries are numbered across modules in the same order as `getpwall`. The
+/// enumeration handle cannot be resumed between calls, so each page
+/// re-enumerates from the start: the cost of fetching a page is O(`offset` +
+/// `limit`) entries from the backing module, and walking all pages of N users
+/// is O(N²). Enumeration stops as soon as the page is full, so early pages are
+/// cheap even against large directories.
+///
+/// # Errors
+/// Returns `NssError` if NSS operation fails.
+pub fn getpwall_page(module: Option<NssModule>, offset: usize, limit: usize) -> NssResult<Vec<PasswdEntry>> {
+    let mut page = Vec::new();
+    if limit == 0 {
+        return Ok(page);
+    }
+
+    let mut skipped = 0;
+    enumerate_chain("passwd", module, iterpw, |entry| {
+        if skipped < offset {
+            skipped += 1;
+            return ControlFlow::Continue(());
+        }
+        page.push(entry);
+        if page.len() >= limit {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    })?;
+
+    Ok(page)
+}
+
+/// Up to `limit` entries from `module` whose name starts with `prefix`, in
+/// enumeration order, e.g. for username typeahead.
+///
+/// NSS has no prefix query, so this enumerates the module and stops once
+/// `limit` matches are found. It therefore only works for modules that allow
+/// enumeration: SSS with `enumerate = false` returns no entries.
+///
+/// # Errors
+/// Returns `NssError` if enumeration fails.
+pub fn getpwnam_prefix(prefix: &str, module: NssModule, limit: usize) -> NssResult<Vec<PasswdEntry>> {
+    prefix_matches(module, limit, |name| name.starts_with(prefix))
+}
+
+/// Like `getpwnam_prefix`, but compares names case-insensitively.
+///
+/// # Errors
+/// Returns `NssError` if enumeration fails.
+pub fn getpwnam_prefix_ignore_case(prefix: &str, module: NssModule, limit: usize) -> NssResult<Vec<PasswdEntry>> {
+    let prefix = prefix.to_lowercase();
+    prefix_matches(module, limit, |name| name.to_lowercase().starts_with(&prefix))
+}
+
+fn prefix_matches<F>(module: NssModule, limit: usize, matches: F) -> NssResult<Vec<PasswdEntry>>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut found = Vec::new();
+    if limit == 0 {
+        return Ok(found);
+    }
+
+    enumerate_chain("passwd", Some(module), iterpw, |entry| {
+        if matches(&entry.pw_name) {
+            found.push(entry);
+            if found.len() >= limit {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    })?;
+
+    Ok(found)
+}
+
+/// Get all password entries, continuing past modules that fail.
+///
+/// Unlike `getpwall`, a hard error from one module does not discard the entries
+/// gathered from the others. Each failing module is reported alongside its error,
+/// and any entries it yielded before failing are kept. Unavailable or missing
+/// modules are skipped silently, as in `getpwall`.
+#[must_use]
+pub fn getpwall_best_effort(module: Option<NssModule>) -> (Vec<PasswdEntry>, Vec<(NssModule, NssError)>) {
+    let mut all_entries = Vec::new();
+    let errors = enumerate_chain_best_effort("passwd", module, iterpw, |entry| {
+        all_entries.push(entry);
+        ControlFlow::Continue(())
+    });
+
+    (all_entries, errors)
+}
+
+/// A `PasswdEntry` string field that was not valid UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Name of the `PasswdEntry` field, e.g. `"pw_gecos"`.
+    pub field: &'static str,
+    /// Why decoding failed.
+    pub message: String,
+}
+
+/// Get password entry by username, keeping only the fields that decode.
+///
+/// Meant for inspecting corrupted directory data. Each string field is
+/// checked on its own; one that is not valid UTF-8 is left empty instead of
+/// failing the lookup, and reported as a `FieldError`. Its original bytes are
+/// still available from the matching `*_os` accessor.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwnam_best_effort(name: &str, module: Option<NssModule>) -> NssResult<(PasswdEntry, Vec<FieldError>)> {
+    Ok(strip_undecodable_fields(getpwnam_opts(name, module, &LookupOptions::new().lossy(true))?))
+}
+
+fn strip_undecodable_fields(mut entry: PasswdEntry) -> (PasswdEntry, Vec<FieldError>) {
+    let mut errors = Vec::new();
+    let raw = &entry.raw;
+    for (field, raw, value) in [
+        ("pw_name", &raw.name, &mut entry.pw_name),
+        ("pw_gecos", &raw.gecos, &mut entry.pw_gecos),
+        ("pw_dir", &raw.dir, &mut entry.pw_dir),
+        ("pw_shell", &raw.shell, &mut entry.pw_shell),
+    ] {
+        // Raw bytes are only kept for fields that failed to decode
+        if let Some(bytes) = raw {
+            if let Err(e) = std::str::from_utf8(bytes.as_bytes()) {
+                value.clear();
+                errors.push(FieldError { field, message: e.to_string() });
+            }
+        }
+    }
+    (entry, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::lock_global_config;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::nss_common::{clear_source_label, set_source_label};
+    use std::cell::Cell;
+
+    thread_local! {
+        static MOCK_PWENT_POS: Cell<usize> = const { Cell::new(0) };
+        static MOCK_SETPWENT_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn mock_setpwent_counting() -> c_int {
+        MOCK_SETPWENT_CALLS.with(|calls| calls.set(calls.get() + 1));
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_success() -> c_int {
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_try_again() -> c_int {
+        NssReturnCode::TryAgain as c_int
+    }
+
+    unsafe extern "C" fn mock_unavail() -> c_int {
+        NssReturnCode::Unavail as c_int
+    }
+
+    /// Emit the next of `names` as a passwd entry (uid 1000 + position).
+    unsafe fn mock_next_pwent(result: *mut passwd, names: &[&'static CStr]) -> c_int {
+        let pos = MOCK_PWENT_POS.with(|p| p.replace(p.get() + 1));
+        let Some(name) = names.get(pos) else {
+            MOCK_PWENT_POS.with(|p| p.set(0));
+            return NssReturnCode::NotFound as c_int;
+        };
+
+        (*result).pw_name = name.as_ptr().cast_mut();
+        (*result).pw_uid = 1000 + pos as uid_t;
+        (*result).pw_gid = 1000;
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwent_files(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        mock_next_pwent(result, &[c"alice", c"bob"])
+    }
+
+    unsafe extern "C" fn mock_getpwent_winbind(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        mock_next_pwent(result, &[c"carol"])
+    }
+
+    /// Ends enumeration the way SSS does: a failure code with errno ENOENT.
+    unsafe extern "C" fn mock_getpwent_enoent(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        let code = mock_next_pwent(result, &[c"alice"]);
+        if code == NssReturnCode::NotFound as c_int {
+            *errnop = libc::ENOENT;
+            return NssReturnCode::Unavail as c_int;
+        }
+        code
+    }
+
+    unsafe extern "C" fn mock_getpwent_eio(
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        *errnop = libc::EIO;
+        NssReturnCode::TryAgain as c_int
+    }
+
+    /// alice and bob share uid 1000; carol has 1002.
+    unsafe extern "C" fn mock_getpwent_shared_uid(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        let code = mock_next_pwent(result, &[c"alice", c"bob", c"carol"]);
+        if code == NssReturnCode::Success as c_int && (*result).pw_uid == 1001 {
+            (*result).pw_uid = 1000;
+        }
+        code
+    }
+
+    #[test]
+    fn test_passwd_entry_creation() {
+        let entry = PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1000,
+            pw_gecos: "Test User".to_string(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: "/bin/bash".to_string(),
+            source: "files".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        };
+
+        assert_eq!(entry.pw_name, "testuser");
+        assert_eq!(entry.pw_uid, 1000);
+        assert_eq!(entry.pw_gid, 1000);
+        assert_eq!(entry.pw_gecos, "Test User");
+        assert_eq!(entry.pw_dir, "/home/testuser");
+        assert_eq!(entry.pw_shell, "/bin/bash");
+        assert_eq!(entry.source, "files");
+        assert_eq!(entry.name_os(), OsStr::new("testuser"));
+        assert_eq!(entry.dir_os(), OsStr::new("/home/testuser"));
+    }
+
+    unsafe extern "C" fn mock_getpwuid_empty_name(
+        uid: uid_t,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        (*result).pw_name = c"".as_ptr().cast_mut();
+        (*result).pw_uid = uid;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_empty_name_is_not_found() {
+        let mut raw: passwd = unsafe { mem::zeroed() };
+        raw.pw_name = c"".as_ptr().cast_mut();
+        raw.pw_uid = 1000;
+
+        assert!(unsafe { parse_lookup_fields(&raw, &NssModule::Files, Fields::ALL, false) }.unwrap().is_none());
+        // Enumeration still sees the entry
+        assert!(unsafe { parse_passwd_result(&raw, &NssModule::Files) }.unwrap().is_some());
+
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_empty_name as *mut libc::c_void);
+        assert!(getpwuid(1000, Some(NssModule::Files)).unwrap_err().is_not_found());
+        assert!(getpwuid_fields(1000, Some(NssModule::Files), Fields::SHELL).unwrap_err().is_not_found());
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_non_utf8_name_preserved_in_os_string() {
+        let name = CString::new(b"us\xffer".to_vec()).unwrap();
+        let dir = CString::new("/home/user").unwrap();
+        let mut raw: passwd = unsafe { mem::zeroed() };
+        raw.pw_name = name.as_ptr().cast_mut();
+        raw.pw_dir = dir.as_ptr().cast_mut();
+        raw.pw_uid = 1000;
+        raw.pw_gid = 1000;
+
+        assert!(matches!(
+            unsafe { parse_passwd_result(&raw, &NssModule::Files) },
+            Err(NssError::InvalidUtf8)
+        ));
+
+        let entry = unsafe { parse_passwd_fields(&raw, &NssModule::Files, Fields::ALL, true) }
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(entry.name_os().as_bytes(), b"us\xffer");
+        assert_eq!(entry.pw_name, "us\u{fffd}er");
+        assert_eq!(entry.dir_os(), OsStr::new("/home/user"));
+        assert!(entry.pw_shell.is_empty());
+        assert!(entry.shell_os().is_empty());
+    }
+
+
+    #[test]
+    fn test_passwd_iterator_creation() {
+        let iterator = PasswdIterator::new(NssModule::Files);
+        assert_eq!(iterator.module, NssModule::Files);
+        assert!(!iterator.initialized);
+    }
+
+    #[test]
+    fn test_passwd_iterator_function() {
+        let iterator = iterpw(NssModule::Files);
+        assert_eq!(iterator.module, NssModule::Files);
+        assert!(!iterator.initialized);
+    }
+
+    #[test]
+    fn test_getpwall_best_effort_continues_past_module_error() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_try_again as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwEnt, mock_getpwent_winbind as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        // The strict variant gives up on the first hard error
+        assert!(getpwall(None).is_err());
+
+        let (entries, errors) = getpwall_best_effort(None);
+        let names: Vec<&str> = entries.iter().map(|e| e.pw_name.as_str()).collect();
+        assert_eq!(names, ["alice", "bob", "carol"]);
+        assert_eq!(entries[0].source, "FILES");
+        assert_eq!(entries[2].source, "WINBIND");
+        assert_eq!(entries[0].source_module, Some(NssModule::Files));
+        assert_eq!(entries[2].source_module, Some(NssModule::Winbind));
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, NssModule::Sss);
+        assert!(matches!(
+            errors[0].1,
+            NssError::NssOperationFailed { return_code: NssReturnCode::TryAgain, .. }
+        ));
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwall_with_capacity_matches_getpwall() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let expected: Vec<String> = getpwall(Some(NssModule::Files)).unwrap().into_iter().map(|e| e.pw_name).collect();
+        let entries = getpwall_with_capacity(Some(NssModule::Files), 64).unwrap();
+
+        assert!(entries.capacity() >= 64);
+        let names: Vec<String> = entries.into_iter().map(|e| e.pw_name).collect();
+        assert_eq!(names, expected);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_count_passwd_sums_available_modules() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwEnt, mock_getpwent_winbind as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        assert_eq!(count_passwd(Some(NssModule::Files)).unwrap(), 2);
+        assert_eq!(count_passwd(Some(NssModule::Sss)).unwrap(), 0);
+        assert_eq!(count_passwd(None).unwrap(), 3);
+
+        clear_mock_functions();
+    }
+
+    thread_local! {
+        static MOCK_PWENT_ROTATION: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Enumerates alice, bob and carol (uids 1000-1002) starting one further
+    /// along each time `setpwent` is called, like a directory with no stable
+    /// order.
+    unsafe extern "C" fn mock_setpwent_rotating() -> c_int {
+        MOCK_PWENT_ROTATION.with(|r| r.set(r.get() + 1));
+        MOCK_PWENT_POS.with(|p| p.set(0));
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwent_rotating(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        const NAMES: [&CStr; 3] = [c"alice", c"bob", c"carol"];
+        let pos = MOCK_PWENT_POS.with(|p| p.replace(p.get() + 1));
+        if pos == NAMES.len() {
+            return NssReturnCode::NotFound as c_int;
+        }
+
+        let index = (pos + MOCK_PWENT_ROTATION.with(Cell::get)) % NAMES.len();
+        (*result).pw_name = NAMES[index].as_ptr().cast_mut();
+        (*result).pw_uid = 1000 + index as uid_t;
+        (*result).pw_gid = 1000;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwall_sorted_canonical_is_reproducible() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_setpwent_rotating as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_rotating as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let names = |entries: Vec<PasswdEntry>| -> Vec<String> { entries.into_iter().map(|e| e.pw_name).collect() };
+        let unsorted = names(getpwall(Some(NssModule::Files)).unwrap());
+        let first = names(getpwall_sorted(Some(NssModule::Files), SortKey::Canonical).unwrap());
+        let second = names(getpwall_sorted(Some(NssModule::Files), SortKey::Canonical).unwrap());
+        assert_eq!(unsorted, ["bob", "carol", "alice"]);
+        assert_eq!(first, ["alice", "bob", "carol"]);
+        assert_eq!(first, second);
+
+        clear_mock_functions();
+    }
+
+    /// Emit the next of `entries` as a passwd entry with the given uid.
+    unsafe fn mock_next_pwent_uid(result: *mut passwd, entries: &[(&'static CStr, uid_t)]) -> c_int {
+        let pos = MOCK_PWENT_POS.with(|p| p.replace(p.get() + 1));
+        let Some(&(name, uid)) = entries.get(pos) else {
+            MOCK_PWENT_POS.with(|p| p.set(0));
+            return NssReturnCode::NotFound as c_int;
+        };
+
+        (*result).pw_name = name.as_ptr().cast_mut();
+        (*result).pw_uid = uid;
+        (*result).pw_gid = 1000;
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwent_odd_uids(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        mock_next_pwent_uid(result, &[(c"alice", 1001), (c"carol", 1003), (c"erin", 1005)])
+    }
+
+    /// Out of uid order, and sharing 1003 with `mock_getpwent_odd_uids`.
+    unsafe extern "C" fn mock_getpwent_even_uids(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        mock_next_pwent_uid(result, &[(c"dave", 1004), (c"bob", 1002), (c"sss_carol", 1003)])
+    }
+
+    #[test]
+    fn test_merge_sorted_passwd_interleaves_modules() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_odd_uids as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwEnt, mock_getpwent_even_uids as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let merged: Vec<(String, uid_t)> = merge_sorted_passwd(&[NssModule::Files, NssModule::Sss])
+            .map(|result| result.map(|e| (e.pw_name, e.pw_uid)))
+            .collect::<NssResult<_>>()
+            .unwrap();
+        let expected = [("alice", 1001), ("bob", 1002), ("carol", 1003), ("dave", 1004), ("erin", 1005)];
+        assert_eq!(merged, expected.map(|(name, uid)| (name.to_string(), uid)));
+
+        // The first listed module wins a shared uid
+        let carol = merge_sorted_passwd(&[NssModule::Sss, NssModule::Files])
+            .find(|result| result.as_ref().is_ok_and(|e| e.pw_uid == 1003))
+            .unwrap()
+            .unwrap();
+        assert_eq!(carol.pw_name, "sss_carol");
+
+        clear_mock_functions();
+    }
+
+    thread_local! {
+        static MOCK_PWENT_FAILED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// Like `mock_setpwent_counting`, also rewinding the mock enumeration.
+    unsafe extern "C" fn mock_setpwent_rewinding() -> c_int {
+        MOCK_PWENT_POS.with(|p| p.set(0));
+        mock_setpwent_counting()
+    }
+
+    /// Like `mock_getpwent_files`, but the second record fails once with
+    /// `TryAgain` and the module needs a new `setpwent` to continue.
+    unsafe extern "C" fn mock_getpwent_lost_cursor(
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        if MOCK_PWENT_POS.with(Cell::get) == 1 && !MOCK_PWENT_FAILED.with(|f| f.replace(true)) {
+            MOCK_PWENT_POS.with(|p| p.set(usize::MAX));
+            return NssReturnCode::TryAgain as c_int;
+        }
+        if MOCK_PWENT_POS.with(Cell::get) == usize::MAX {
+            return NssReturnCode::TryAgain as c_int;
+        }
+        mock_getpwent_files(result, buffer, buflen, errnop)
+    }
+
+    #[test]
+    fn test_iterator_reinits_after_try_again() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_setpwent_rewinding as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_lost_cursor as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let names: Vec<String> = iterpw(NssModule::Files).map(|e| e.unwrap().pw_name).collect();
+        assert_eq!(names, ["alice", "bob"]);
+        assert_eq!(MOCK_SETPWENT_CALLS.with(Cell::get), 2);
+
+        // A module that never recovers gets a bounded number of retries
+        MOCK_SETPWENT_CALLS.with(|c| c.set(0));
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_try_again as *mut libc::c_void);
+        let mut users = iterpw(NssModule::Files);
+        let err = users.next().unwrap().unwrap_err();
+        assert!(matches!(err, NssError::NssOperationFailed { return_code: NssReturnCode::TryAgain, .. }));
+        assert_eq!(MOCK_SETPWENT_CALLS.with(Cell::get), 1 + MAX_ENUM_REINITS);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_for_each_passwd_stops_on_break() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwEnt, mock_getpwent_winbind as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let mut seen = Vec::new();
+        for_each_passwd(None, |entry| {
+            seen.push(entry.pw_name);
+            ControlFlow::Break(())
+        })
+        .unwrap();
+        assert_eq!(seen, ["alice"]);
+        // Only one getpwent call was made
+        assert_eq!(MOCK_PWENT_POS.with(Cell::get), 1);
+        MOCK_PWENT_POS.with(|p| p.set(0));
+
+        let mut count = 0;
+        for_each_passwd(None, |_| {
+            count += 1;
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(count, 3);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwall_page_spans_modules() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwEnt, mock_getpwent_winbind as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        assert!(getpwall_page(None, 3, 10).unwrap().is_empty());
+        assert!(getpwall_page(None, 0, 0).unwrap().is_empty());
+
+        // Checked last: a full page stops enumeration mid-way through the mock
+        let page = getpwall_page(None, 1, 2).unwrap();
+        let names: Vec<&str> = page.iter().map(|e| e.pw_name.as_str()).collect();
+        assert_eq!(names, ["bob", "carol"]);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_iterpw_all_tagged_reports_unavailable_module() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::SetPwEnt, mock_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwEnt, mock_getpwent_winbind as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let items: Vec<(NssModule, Result<String, NssReturnCode>)> = iterpw_all_tagged()
+            .map(|(module, result)| {
+                let result = result.map(|e| e.pw_name).map_err(|e| match e {
+                    NssError::NssOperationFailed { return_code, .. } => return_code,
+                    other => panic!("unexpected error {other}"),
+                });
+                (module, result)
+            })
+            .collect();
+
+        assert_eq!(
+            items,
+            [
+                (NssModule::Files, Ok("alice".to_string())),
+                (NssModule::Files, Ok("bob".to_string())),
+                (NssModule::Sss, Err(NssReturnCode::Unavail)),
+                (NssModule::Winbind, Ok("carol".to_string())),
+            ]
+        );
+
+        clear_mock_functions();
+    }
+
+    unsafe extern "C" fn mock_getpwent_with_shell(
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        (*result).pw_shell = c"/bin/sh".as_ptr().cast_mut();
+        mock_next_pwent(result, &[c"alice", c"bob"])
+    }
+
+    #[test]
+    fn test_lazy_entry_decodes_only_accessed_fields() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_with_shell as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let entries: Vec<LazyPasswdEntry> = iterpw_lazy(NssModule::Files).collect::<NssResult<_>>().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let bob = &entries[1];
+        assert_eq!(bob.name(), "bob");
+        assert_eq!(bob.uid(), 1001);
+        assert!(bob.decoded[LAZY_NAME].get().is_some());
+        assert!(bob.decoded[LAZY_SHELL].get().is_none());
+        assert!(bob.decoded[LAZY_DIR].get().is_none());
+
+        assert_eq!(bob.shell(), "/bin/sh");
+        assert!(bob.decoded[LAZY_SHELL].get().is_some());
+        assert_eq!(bob.dir(), "");
+
+        let entry = PasswdEntry::from(entries[0].clone());
+        assert_eq!(entry.pw_name, "alice");
+        assert_eq!(entry.pw_shell, "/bin/sh");
+        assert_eq!(entry.source_module, Some(NssModule::Files));
+
+        clear_mock_functions();
+    }
+
+    fn enumerate_files(getpwent: *mut libc::c_void) -> Vec<NssResult<PasswdEntry>> {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, getpwent);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+        let results = iterpw(NssModule::Files).take(5).collect();
+        clear_mock_functions();
+        results
+    }
+
+    #[test]
+    fn test_reset_restarts_enumeration() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_setpwent_counting as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let mut iterator = iterpw(NssModule::Files);
+        assert_eq!(iterator.by_ref().count(), 2);
+        assert_eq!(MOCK_SETPWENT_CALLS.with(Cell::get), 1);
+
+        iterator.reset();
+        assert!(!iterator.initialized);
+        assert_eq!(iterator.next().unwrap().unwrap().pw_name, "alice");
+        assert_eq!(MOCK_SETPWENT_CALLS.with(Cell::get), 2);
+
+        // Resetting a fresh iterator does nothing
+        let mut fresh = iterpw(NssModule::Files);
+        fresh.reset();
+        assert_eq!(MOCK_SETPWENT_CALLS.with(Cell::get), 2);
+
+        drop(iterator);
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwent_notfound_ends_enumeration() {
+        let results = enumerate_files(mock_getpwent_files as *mut libc::c_void);
+        let names: Vec<String> = results.into_iter().map(|r| r.unwrap().pw_name).collect();
+        assert_eq!(names, ["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_getpwent_enoent_ends_enumeration() {
+        let results = enumerate_files(mock_getpwent_enoent as *mut libc::c_void);
+        let names: Vec<String> = results.into_iter().map(|r| r.unwrap().pw_name).collect();
+        assert_eq!(names, ["alice"]);
+    }
+
+    #[test]
+    fn test_getpwent_other_errno_is_propagated() {
+        let results = enumerate_files(mock_getpwent_eio as *mut libc::c_void);
+        assert!(matches!(
+            results.first(),
+            Some(Err(NssError::NssOperationFailed { errno, return_code: NssReturnCode::TryAgain, .. }))
+                if *errno == libc::EIO.unsigned_abs()
+        ));
+    }
+
+    #[test]
+    fn test_getpwall_by_uid_last_wins() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_shared_uid as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let by_uid = getpwall_by_uid(Some(NssModule::Files)).unwrap();
+        assert_eq!(by_uid.len(), 2);
+        assert_eq!(by_uid[&1000].pw_name, "bob");
+        assert_eq!(by_uid[&1002].pw_name, "carol");
+
+        let by_name = getpwall_by_name(Some(NssModule::Files)).unwrap();
+        assert_eq!(by_name.len(), 3);
+        assert_eq!(by_name["alice"].pw_uid, 1000);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_find_uid_collisions() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_shared_uid as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        let collisions = find_uid_collisions(NssModule::Files).unwrap();
+        assert_eq!(collisions, [(1000, vec!["alice".to_string(), "bob".to_string()])]);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_prefix() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_success as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::GetPwEnt, mock_getpwent_files as *mut libc::c_void);
+        set_mock_function(NssModule::Files, NssOperation::EndPwEnt, mock_success as *mut libc::c_void);
+
+        assert!(getpwnam_prefix("B", NssModule::Files, 10).unwrap().is_empty());
+        assert!(getpwnam_prefix("b", NssModule::Files, 0).unwrap().is_empty());
+        let matches = getpwnam_prefix_ignore_case("B", NssModule::Files, 10).unwrap();
+        let names: Vec<&str> = matches.iter().map(|e| e.pw_name.as_str()).collect();
+        assert_eq!(names, ["bob"]);
+
+        // Checked last: reaching the limit stops enumeration mid-way through the mock
+        let matches = getpwnam_prefix("", NssModule::Files, 1).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].pw_name, "alice");
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwall_best_effort_skips_unavailable_module() {
+        set_mock_function(NssModule::Files, NssOperation::SetPwEnt, mock_unavail as *mut libc::c_void);
+
+        let (entries, errors) = getpwall_best_effort(Some(NssModule::Files));
+        assert!(entries.is_empty());
+        assert!(errors.is_empty());
+
+        clear_mock_functions();
+    }
+
+    fn named_entry(name: &str, module: NssModule) -> PasswdEntry {
+        PasswdEntry {
+            pw_name: name.to_string(),
             pw_uid: 1000,
             pw_gid: 1000,
-            pw_gecos: "Test User".to_string(),
-            pw_dir: "/home/testuser".to_string(),
-            pw_shell: "/bin/bash".to_string(),
-            source: "files".to_string(),
-        };
+            pw_gecos: String::new(),
+            pw_dir: String::new(),
+            pw_shell: String::new(),
+            source: module.upper_name().to_string(),
+            source_module: Some(module),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
 
-        assert_eq!(entry.pw_name, "testuser");
+    #[test]
+    fn test_passwd_entry_ordering() {
+        let root = PasswdEntry { pw_uid: 0, ..named_entry("root", NssModule::Files) };
+        let mut entries = [
+            named_entry("carol", NssModule::Files),
+            named_entry("alice", NssModule::Winbind),
+            root.clone(),
+            named_entry("bob", NssModule::Files),
+        ];
+        entries.sort();
+
+        let names: Vec<&str> = entries.iter().map(|e| e.pw_name.as_str()).collect();
+        assert_eq!(names, ["root", "alice", "bob", "carol"]);
+
+        // Source does not participate in comparisons
+        assert_eq!(named_entry("alice", NssModule::Files), named_entry("alice", NssModule::Winbind));
+        let set: std::collections::BTreeSet<PasswdEntry> =
+            [named_entry("alice", NssModule::Files), named_entry("alice", NssModule::Sss), root].into();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_all_sources_reports_every_module() {
+        let outcomes = all_sources(
+            &[NssModule::Files, NssModule::Sss, NssModule::Winbind],
+            NssOperation::GetPwNam,
+            |module| match module {
+                NssModule::Files => Ok(Some(named_entry("alice", module))),
+                NssModule::Sss => Ok(Some(PasswdEntry { pw_uid: 2000, ..named_entry("alice", module) })),
+                NssModule::Winbind => Err(NssError::LibraryError("not installed".to_string())),
+            },
+        );
+
+        assert_eq!(outcomes.len(), 3);
+        let uids: Vec<uid_t> = outcomes
+            .iter()
+            .filter_map(|(_, outcome)| outcome.as_ref().ok().map(|e| e.pw_uid))
+            .collect();
+        assert_eq!(uids, [1000, 2000]);
+        assert_eq!(outcomes[1].0, NssModule::Sss);
+
+        assert_eq!(outcomes[2].0, NssModule::Winbind);
+        assert!(matches!(
+            outcomes[2].1,
+            Err(NssError::NssOperationFailed { return_code: NssReturnCode::Unavail, module: NssModule::Winbind, .. })
+        ));
+    }
+
+    #[test]
+    fn test_race_lookup_fast_module_wins() {
+        let entry = race_lookup(&[NssModule::Winbind, NssModule::Files], |module| {
+            if module == NssModule::Winbind {
+                std::thread::sleep(std::time::Duration::from_millis(300));
+            }
+            Ok(Some(named_entry("alice", module)))
+        })
+        .unwrap();
+
+        assert_eq!(entry.source, "FILES");
+    }
+
+    #[test]
+    fn test_race_lookup_skips_errors_and_misses() {
+        let entry = race_lookup(
+            &[NssModule::Files, NssModule::Sss, NssModule::Winbind],
+            |module| match module {
+                NssModule::Files => Ok(None),
+                NssModule::Sss => Err(NssError::LibraryError("not installed".to_string())),
+                NssModule::Winbind => Ok(Some(named_entry("alice", module))),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(entry.source, "WINBIND");
+    }
+
+    #[test]
+    fn test_race_lookup_not_found() {
+        let result = race_lookup(&[NssModule::Files, NssModule::Sss], |_| Ok(None));
+        assert!(result.unwrap_err().is_not_found());
+    }
+
+    unsafe extern "C" fn mock_getpwnam_eacces(
+        _name: *const c_char,
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        *errnop = libc::EACCES;
+        NssReturnCode::Unavail as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwuid_unparseable(
+        uid: uid_t,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        if uid != 0 {
+            return NssReturnCode::NotFound as c_int;
+        }
+        // A found record with no name would not parse into a `PasswdEntry`
+        (*result).pw_uid = 0;
+        NssReturnCode::Success as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwuid_full(
+        uid: uid_t,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        (*result).pw_name = c"alice".as_ptr().cast_mut();
+        (*result).pw_uid = uid;
+        (*result).pw_gid = 100;
+        (*result).pw_gecos = c"Alice".as_ptr().cast_mut();
+        (*result).pw_dir = c"/home/alice".as_ptr().cast_mut();
+        (*result).pw_shell = c"/bin/sh".as_ptr().cast_mut();
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwuid_fields_skips_unrequested() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_full as *mut libc::c_void);
+
+        let entry = getpwuid_fields(1000, Some(NssModule::Files), Fields::NAME).unwrap();
+        assert_eq!(entry.pw_name, "alice");
         assert_eq!(entry.pw_uid, 1000);
-        assert_eq!(entry.pw_gid, 1000);
-        assert_eq!(entry.pw_gecos, "Test User");
-        assert_eq!(entry.pw_dir, "/home/testuser");
-        assert_eq!(entry.pw_shell, "/bin/bash");
-        assert_eq!(entry.source, "files");
+        assert_eq!(entry.pw_gid, 100);
+        assert!(entry.pw_gecos.is_empty());
+        assert!(entry.pw_dir.is_empty());
+        assert!(entry.pw_shell.is_empty());
+
+        let entry = getpwuid_fields(1000, Some(NssModule::Files), Fields::NAME | Fields::SHELL).unwrap();
+        assert_eq!(entry.pw_shell, "/bin/sh");
+        assert!(entry.pw_dir.is_empty());
+
+        let entry = getpwuid_fields(1000, Some(NssModule::Files), Fields::ALL).unwrap();
+        assert_eq!(entry.pw_dir, "/home/alice");
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_uid_exists_skips_parsing() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwUid, mock_getpwuid_unparseable as *mut libc::c_void);
+
+        assert!(uid_exists(0, Some(NssModule::Files)).unwrap());
+        assert!(!uid_exists(4242, Some(NssModule::Files)).unwrap());
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_permission_denied() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_eacces as *mut libc::c_void);
+
+        match getpwnam("root", Some(NssModule::Files)) {
+            Err(NssError::PermissionDenied { operation, module }) => {
+                assert_eq!(operation, NssOperation::GetPwNam);
+                assert_eq!(module, NssModule::Files);
+            }
+            other => panic!("Expected PermissionDenied, got {:?}", other),
+        }
+
+        clear_mock_functions();
     }
 
+    unsafe extern "C" fn mock_getpwnam_not_found(
+        _name: *const c_char,
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        NssReturnCode::NotFound as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwnam_not_found_enoent(
+        _name: *const c_char,
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        *errnop = libc::ENOENT;
+        NssReturnCode::NotFound as c_int
+    }
 
     #[test]
-    fn test_passwd_iterator_creation() {
-        let iterator = PasswdIterator::new(NssModule::Files);
-        assert_eq!(iterator.module, NssModule::Files);
-        assert!(!iterator.initialized);
+    fn test_home_dir_and_shell() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_bad_gecos as *mut libc::c_void);
+        assert_eq!(home_dir("alice", Some(NssModule::Files)).unwrap(), PathBuf::from("/home/alice"));
+        assert_eq!(shell("alice", Some(NssModule::Files)).unwrap(), PathBuf::from("/bin/sh"));
+
+        let entry = getpwnam_fields("alice", Some(NssModule::Files), Fields::DIR).unwrap();
+        assert!(entry.pw_name.is_empty());
+        assert!(entry.pw_shell.is_empty());
+
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        assert!(home_dir("alice", Some(NssModule::Files)).unwrap_err().is_not_found());
+        clear_mock_functions();
     }
 
     #[test]
-    fn test_passwd_iterator_function() {
-        let iterator = iterpw(NssModule::Files);
-        assert_eq!(iterator.module, NssModule::Files);
-        assert!(!iterator.initialized);
+    fn test_getpwnam_raw_reports_code_and_errno() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found_enoent as *mut libc::c_void);
+        let (code, entry, errno) = getpwnam_raw("nobody_here", NssModule::Files);
+        assert_eq!(code, NssReturnCode::NotFound);
+        assert!(entry.is_none());
+        assert_eq!(errno, libc::ENOENT as u32);
+
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_bad_gecos as *mut libc::c_void);
+        let (code, entry, errno) = getpwnam_raw("alice", NssModule::Files);
+        assert_eq!(code, NssReturnCode::Success);
+        assert_eq!(entry.unwrap().pw_name, "alice");
+        assert_eq!(errno, 0);
+
+        clear_mock_functions();
+    }
+
+    unsafe extern "C" fn mock_getpwnam_unavail(
+        _name: *const c_char,
+        _result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        NssReturnCode::Unavail as c_int
+    }
+
+    unsafe extern "C" fn mock_getpwnam_alice(
+        _name: *const c_char,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        (*result).pw_name = c"alice".as_ptr().cast_mut();
+        (*result).pw_uid = 1000;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_nss_fn_loads_typed_getpwnam() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_alice as *mut libc::c_void);
+
+        let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::load(NssModule::Files) }.unwrap().get();
+        let mut result: passwd = unsafe { mem::zeroed() };
+        let mut buffer = [0 as c_char; 64];
+        let mut errno: c_int = 0;
+        let ret_code = unsafe { getpwnam_r(c"alice".as_ptr(), &mut result, buffer.as_mut_ptr(), buffer.len(), &mut errno) };
+        assert_eq!(NssReturnCode::from(ret_code), NssReturnCode::Success);
+        assert_eq!(result.pw_uid, 1000);
+
+        clear_mock_functions();
+    }
+
+    thread_local! {
+        static MOCK_FLAKY_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Fails the first call with `EIO`; later calls succeed without touching
+    /// `errnop`, as glibc modules do.
+    unsafe extern "C" fn mock_getpwnam_flaky(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        if MOCK_FLAKY_CALLS.with(|calls| calls.replace(calls.get() + 1)) == 0 {
+            *errnop = libc::EIO;
+            return NssReturnCode::TryAgain as c_int;
+        }
+        mock_getpwnam_alice(name, result, buffer, buflen, errnop)
+    }
+
+    #[test]
+    fn test_failed_lookup_does_not_leak_errno() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_flaky as *mut libc::c_void);
+
+        let error = getpwnam("alice", Some(NssModule::Files)).unwrap_err();
+        assert!(matches!(error, NssError::NssOperationFailed { errno: 5, .. }));
+
+        // Each call passes a freshly zeroed `errnop`, and the thread's own
+        // errno is never consulted, so neither can fail the next lookup
+        unsafe { *libc::__errno_location() = libc::EIO };
+        let entry = getpwnam("alice", Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.pw_name, "alice");
+
+        clear_mock_functions();
+    }
+
+    thread_local! {
+        static MOCK_WINBIND_CALLS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn mock_getpwnam_winbind_counting(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        MOCK_WINBIND_CALLS.with(|calls| calls.set(calls.get() + 1));
+        mock_getpwnam_not_found(name, result, buffer, buflen, errnop)
+    }
+
+    #[test]
+    fn test_files_authoritative_skips_winbind() {
+        let _config = lock_global_config();
+        crate::nsswitch::set_default_modules(vec![NssModule::Files, NssModule::Winbind]).unwrap();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwNam, mock_getpwnam_winbind_counting as *mut libc::c_void);
+
+        // Opt-in: the whole chain is asked by default
+        assert!(getpwnam("svc_backup", None).unwrap_err().is_not_found());
+        assert_eq!(MOCK_WINBIND_CALLS.with(Cell::get), 1);
+
+        crate::nsswitch::set_files_authoritative(crate::nsswitch::is_local_service_name);
+        assert!(getpwnam("svc_backup", None).unwrap_err().is_not_found());
+        assert_eq!(MOCK_WINBIND_CALLS.with(Cell::get), 1);
+        let mut scratch = PwLookupScratch::new();
+        assert!(getpwnam_reuse("svc_backup", None, &mut scratch).unwrap_err().is_not_found());
+        assert_eq!(MOCK_WINBIND_CALLS.with(Cell::get), 1);
+
+        // Names outside the pattern still reach winbind
+        assert!(getpwnam("AD\\jsmith", None).unwrap_err().is_not_found());
+        assert_eq!(MOCK_WINBIND_CALLS.with(Cell::get), 2);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_via_lib_rejects_invalid_module_name() {
+        for module_name in ["", "LDAP", "nss-ldap", "../files"] {
+            let err = getpwnam_via_lib("root", Path::new("/nonexistent/libnss_ldap.so.2"), module_name).unwrap_err();
+            assert!(matches!(err, NssError::LibraryError(ref message) if message.contains("Invalid")), "{err:?}");
+        }
+
+        let err = getpwnam_via_lib("root", Path::new("/nonexistent/libnss_ldap.so.2"), "ldap").unwrap_err();
+        assert!(matches!(err, NssError::LibraryError(_)), "{err:?}");
+    }
+
+    #[test]
+    fn test_getpwnam_via_fn_labels_entry_with_module_name() {
+        let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::from_raw(mock_getpwnam_alice as *mut libc::c_void) };
+        let entry = getpwnam_via_fn("alice", getpwnam_r, "ldap").unwrap();
+        assert_eq!(entry.pw_name, "alice");
+        assert_eq!(entry.pw_uid, 1000);
+        assert_eq!(entry.source, "LDAP");
+        assert_eq!(entry.source_module, None);
+
+        let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::from_raw(mock_getpwnam_not_found as *mut libc::c_void) };
+        let err = getpwnam_via_fn("alice", getpwnam_r, "ldap").unwrap_err();
+        assert!(err.is_not_found());
+        assert!(matches!(err, NssError::AllModulesFailed { ref attempts, .. } if attempts.is_empty()), "{err:?}");
+
+        let err = getpwnam_via_fn("alice", getpwnam_r, "winbind").unwrap_err();
+        assert!(matches!(
+            err,
+            NssError::AllModulesFailed { operation: NssOperation::GetPwNam, ref attempts }
+                if *attempts == [(NssModule::Winbind, NssReturnCode::NotFound)]
+        ), "{err:?}");
+
+        let getpwnam_r = unsafe { NssFn::<GetPwNameFn>::from_raw(mock_getpwnam_unavail as *mut libc::c_void) };
+        let err = getpwnam_via_fn("alice", getpwnam_r, "ldap").unwrap_err();
+        assert!(matches!(err, NssError::LibraryError(ref message) if message.contains("_nss_ldap_getpwnam_r")), "{err:?}");
+    }
+
+    thread_local! {
+        static MOCK_FLAKY_BUFLEN: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Like `mock_getpwnam_flaky`, remembering the buffer size it was given.
+    unsafe extern "C" fn mock_getpwnam_flaky_sized(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        MOCK_FLAKY_BUFLEN.with(|len| len.set(buflen));
+        mock_getpwnam_flaky(name, result, buffer, buflen, errnop)
+    }
+
+    #[test]
+    fn test_getpwnam_opts_buflen_and_retries() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_flaky_sized as *mut libc::c_void);
+
+        let opts = LookupOptions::new().buflen(8192).retries(1);
+        let entry = getpwnam_opts("alice", Some(NssModule::Files), &opts).unwrap();
+        assert_eq!(entry.pw_name, "alice");
+        assert_eq!(MOCK_FLAKY_CALLS.with(Cell::get), 2);
+        assert_eq!(MOCK_FLAKY_BUFLEN.with(Cell::get), 8192);
+
+        // The defaults match getpwnam: no retry
+        MOCK_FLAKY_CALLS.with(|calls| calls.set(0));
+        assert!(getpwnam_opts("alice", Some(NssModule::Files), &LookupOptions::new()).is_err());
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_nth_queries_configured_module() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_alice as *mut libc::c_void);
+        let modules = default_modules("passwd");
+        assert_eq!(modules[0], NssModule::Files);
+
+        let entry = getpwnam_nth("alice", 0).unwrap();
+        assert_eq!(entry.source_module, Some(modules[0]));
+        assert_eq!(entry.pw_uid, 1000);
+
+        set_mock_function(modules[1], NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        assert!(getpwnam_nth("alice", 1).unwrap_err().is_not_found());
+
+        assert!(matches!(
+            getpwnam_nth("alice", modules.len()),
+            Err(NssError::ModuleIndexOutOfRange { index, count }) if index == modules.len() && count == modules.len()
+        ));
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_custom_module_source_label() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_alice as *mut libc::c_void);
+        set_source_label(NssModule::Sss, "ldap");
+
+        let entry = getpwnam("alice", Some(NssModule::Sss)).unwrap();
+        assert_eq!(entry.source, "LDAP");
+        assert_eq!(entry.source_module, Some(NssModule::Sss));
+
+        clear_source_label(NssModule::Sss);
+        assert_eq!(getpwnam("alice", Some(NssModule::Sss)).unwrap().source, "SSS");
+
+        clear_mock_functions();
+    }
+
+    const MOCK_SCRATCH_BUFLEN: usize = 4096;
+
+    /// Needs a 4096-byte buffer and, like a real module, builds the name in it.
+    unsafe extern "C" fn mock_getpwnam_needs_4k(
+        name: *const c_char,
+        result: *mut passwd,
+        buffer: *mut c_char,
+        buflen: libc::size_t,
+        errnop: *mut c_int,
+    ) -> c_int {
+        if buflen < MOCK_SCRATCH_BUFLEN {
+            *errnop = libc::ERANGE;
+            return NssReturnCode::TryAgain as c_int;
+        }
+
+        let name = CStr::from_ptr(name).to_bytes_with_nul();
+        std::ptr::copy_nonoverlapping(name.as_ptr().cast::<c_char>(), buffer, name.len());
+        (*result).pw_name = buffer;
+        (*result).pw_uid = 1000 + name.len() as uid_t;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwnam_reuse_keeps_grown_buffer() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_needs_4k as *mut libc::c_void);
+
+        let mut scratch = PwLookupScratch::new();
+        assert_eq!(scratch.capacity(), PASSWD_INIT_BUFLEN);
+
+        let alice = getpwnam_reuse("alice", Some(NssModule::Files), &mut scratch).unwrap();
+        assert_eq!(alice.pw_name, "alice");
+        assert_eq!(scratch.capacity(), MOCK_SCRATCH_BUFLEN);
+
+        let bob = getpwnam_reuse("bob", Some(NssModule::Files), &mut scratch).unwrap();
+        assert_eq!(bob.pw_name, "bob");
+        assert_eq!(bob.pw_uid, 1004);
+        // The earlier entry owns its data and is unaffected by buffer reuse
+        assert_eq!(alice.pw_name, "alice");
+        assert_eq!(scratch.capacity(), MOCK_SCRATCH_BUFLEN);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_fallback_policies() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_eacces as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_alice as *mut libc::c_void);
+
+        let entry = getpwnam_with_policy("alice", None, FallbackPolicy::ContinueOnError).unwrap();
+        assert_eq!(entry.source, "SSS");
+        assert_eq!(entry.source_module, Some(NssModule::Sss));
+        assert!(matches!(
+            getpwnam("alice", None),
+            Err(NssError::PermissionDenied { module: NssModule::Files, .. })
+        ));
+
+        assert!(matches!(
+            getpwnam_with_policy("alice", None, FallbackPolicy::StopOnError),
+            Err(NssError::PermissionDenied { module: NssModule::Files, .. })
+        ));
+
+        // With nothing found, the error wins over a later "not found"
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        assert!(matches!(
+            getpwnam_with_policy("alice", None, FallbackPolicy::ContinueOnError),
+            Err(NssError::PermissionDenied { module: NssModule::Files, .. })
+        ));
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_getpwnam_records_attempts() {
+        let _config = lock_global_config();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+
+        let error = getpwnam("nobody-here", None).unwrap_err();
+        match error {
+            NssError::AllModulesFailed { operation, ref attempts } => {
+                assert_eq!(operation, NssOperation::GetPwNam);
+                assert_eq!(
+                    *attempts,
+                    vec![
+                        (NssModule::Files, NssReturnCode::NotFound),
+                        (NssModule::Sss, NssReturnCode::Unavail),
+                        (NssModule::Winbind, NssReturnCode::NotFound),
+                    ]
+                );
+            }
+            ref other => panic!("Expected AllModulesFailed, got {:?}", other),
+        }
+        assert_eq!(crate::error::explain(&error), "user not found in FILES, WINBIND; SSS unavailable");
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_not_found_names_only_queried_modules() {
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+
+        let error = getpwnam("nobody-here", Some(NssModule::Winbind)).unwrap_err();
+        assert!(error.is_not_found());
+        assert!(matches!(
+            error,
+            NssError::AllModulesFailed { ref attempts, .. } if *attempts == [(NssModule::Winbind, NssReturnCode::NotFound)]
+        ));
+        assert!(!error.to_string().contains("FILES"));
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_disabled_module_skipped_by_default_lookup() {
+        let _config = lock_global_config();
+        use crate::nsswitch::{disable_module, enable_module};
+
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_unavail as *mut libc::c_void);
+        set_mock_function(NssModule::Winbind, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+
+        let attempted = || match getpwnam("nobody-here", None) {
+            Err(NssError::AllModulesFailed { attempts, .. }) => attempts.into_iter().map(|(m, _)| m).collect::<Vec<_>>(),
+            other => panic!("Expected AllModulesFailed, got {:?}", other),
+        };
+
+        disable_module(NssModule::Winbind);
+        assert_eq!(attempted(), [NssModule::Files, NssModule::Sss]);
+        // An explicit module is still honored
+        assert!(getpwnam("nobody-here", Some(NssModule::Winbind)).unwrap_err().is_not_found());
+
+        enable_module(NssModule::Winbind);
+        assert_eq!(attempted(), [NssModule::Files, NssModule::Sss, NssModule::Winbind]);
+
+        clear_mock_functions();
+    }
+
+    #[test]
+    fn test_race_lookup_records_attempts() {
+        let result = race_lookup(&[NssModule::Files, NssModule::Sss], |module| match module {
+            NssModule::Sss => Err(NssError::LibraryError("not installed".to_string())),
+            _ => Ok(None),
+        });
+
+        match result {
+            Err(NssError::AllModulesFailed { attempts, .. }) => assert_eq!(
+                attempts,
+                vec![(NssModule::Files, NssReturnCode::NotFound), (NssModule::Sss, NssReturnCode::Unavail)]
+            ),
+            other => panic!("Expected AllModulesFailed, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_split_domain_user() {
+        assert_eq!(split_domain_user("DOM\\user", DEFAULT_WINBIND_SEPARATOR), (Some("DOM"), "user"));
+        assert_eq!(split_domain_user("user", DEFAULT_WINBIND_SEPARATOR), (None, "user"));
+        assert_eq!(split_domain_user("DOM+user", '+'), (Some("DOM"), "user"));
+        // Backslash is only special when it is the configured separator
+        assert_eq!(split_domain_user("DOM\\user", '+'), (None, "DOM\\user"));
+
+        let entry = named_entry("DOM+alice", NssModule::Winbind);
+        assert_eq!(entry.domain('+'), Some("DOM"));
+        assert_eq!(entry.domain(DEFAULT_WINBIND_SEPARATOR), None);
+    }
+
+    unsafe extern "C" fn mock_getpwnam_bad_gecos(
+        _name: *const c_char,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        (*result).pw_name = c"alice".as_ptr().cast_mut();
+        (*result).pw_uid = 1000;
+        (*result).pw_gecos = c"Al\xffce".as_ptr().cast_mut();
+        (*result).pw_dir = c"/home/alice".as_ptr().cast_mut();
+        (*result).pw_shell = c"/bin/sh".as_ptr().cast_mut();
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwnam_best_effort_reports_bad_gecos() {
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_bad_gecos as *mut libc::c_void);
+
+        // Strict by default, as the whole entry cannot be represented
+        assert!(matches!(getpwnam("alice", Some(NssModule::Files)), Err(NssError::InvalidUtf8)));
+        let lossy = getpwnam_opts("alice", Some(NssModule::Files), &LookupOptions::new().lossy(true)).unwrap();
+        assert_eq!(lossy.pw_gecos, "Al\u{fffd}ce");
+
+        let (entry, errors) = getpwnam_best_effort("alice", Some(NssModule::Files)).unwrap();
+        assert_eq!(entry.pw_name, "alice");
+        assert_eq!(entry.pw_dir, "/home/alice");
+        assert!(entry.pw_gecos.is_empty());
+        assert_eq!(entry.gecos_os().as_bytes(), b"Al\xffce");
+        let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["pw_gecos"]);
+
+        clear_mock_functions();
+    }
+
+    unsafe extern "C" fn mock_getpwnam_slow_hit(
+        _name: *const c_char,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        thread::sleep(Duration::from_millis(200));
+        (*result).pw_name = c"slowuser".as_ptr().cast_mut();
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwnam_deadline_spans_chain() {
+        let _config = lock_global_config();
+        crate::nsswitch::set_default_modules(vec![NssModule::Files, NssModule::Sss, NssModule::Winbind]).unwrap();
+
+        // FILES misses only once the budget is spent, so SSS is out of time
+        // before it is asked
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let mut asked = Vec::new();
+        let err = deadline_lookup("slowuser", deadline, |module, _| {
+            asked.push(module);
+            while Instant::now() < deadline {
+                thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            }
+            Ok(None)
+        })
+        .unwrap_err();
+        assert!(matches!(err, NssError::Timeout { module: NssModule::Sss, .. }), "{err:?}");
+        assert_eq!(asked, [NssModule::Files]);
+
+        // A module that overruns its share is abandoned
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_not_found as *mut libc::c_void);
+        set_mock_function(NssModule::Sss, NssOperation::GetPwNam, mock_getpwnam_slow_hit as *mut libc::c_void);
+        let err = getpwnam_deadline("slowuser", Instant::now() + Duration::from_millis(50)).unwrap_err();
+        assert!(matches!(err, NssError::Timeout { module: NssModule::Sss, .. }), "{err:?}");
+
+        let entry = getpwnam_deadline("slowuser", Instant::now() + Duration::from_secs(5)).unwrap();
+        assert_eq!(entry.pw_name, "slowuser");
+        assert_eq!(entry.source, "SSS");
+
+        clear_mock_functions();
     }
 
     // Note: Most NSS function tests would require actual NSS libraries to be present
@@ -3,7 +3,7 @@ use pyo3::types::PyDict;
 use libc::gid_t;
 use crate::{GroupEntry, GroupIterator};
 use crate::group::{getgrnam as rust_getgrnam, getgrgid as rust_getgrgid, itergrp as rust_itergrp};
-use super::nss_common::PyNssModule;
+use super::nss_common::{build_results, to_json_string, PyNssModule};
 
 #[pyclass]
 #[derive(Debug, Clone)]
@@ -38,6 +38,26 @@ impl PyGroupEntry {
         dict.set_item("source", &self.source)?;
         Ok(dict.into())
     }
+
+    /// `to_dict()` as a JSON string; see `PyPasswdEntry.to_json`.
+    #[pyo3(signature = (pretty=false))]
+    fn to_json(&self, pretty: bool) -> PyResult<String> {
+        #[derive(serde::Serialize)]
+        struct Json<'a> {
+            gr_name: &'a str,
+            gr_gid: gid_t,
+            gr_mem: &'a [String],
+            source: &'a str,
+        }
+
+        let json = Json {
+            gr_name: &self.gr_name,
+            gr_gid: self.gr_gid,
+            gr_mem: &self.gr_mem,
+            source: &self.source,
+        };
+        to_json_string(&json, pretty)
+    }
 }
 
 impl From<GroupEntry> for PyGroupEntry {
@@ -51,7 +71,8 @@ impl From<GroupEntry> for PyGroupEntry {
     }
 }
 
-#[pyclass]
+// Unsendable: the underlying enumeration is bound to the creating thread
+#[pyclass(unsendable)]
 pub struct PyGroupIterator {
     inner: GroupIterator,
 }
@@ -92,13 +113,12 @@ impl From<GroupIterator> for PyGroupIterator {
 #[pyo3(signature = (name, *, module=None))]
 pub fn getgrnam(py: Python<'_>, name: &str, module: Option<PyNssModule>) -> PyResult<PyGroupEntry> {
     use pyo3::exceptions::PyKeyError;
-    use crate::{NssError, NssReturnCode};
 
     let nss_module = module.map(|m| m.into());
     let result = py.allow_threads(|| rust_getgrnam(name, nss_module));
     match result {
         Ok(entry) => Ok(entry.into()),
-        Err(NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, .. }) => {
+        Err(e) if e.is_not_found() => {
             Err(PyKeyError::new_err(format!("getgrnam(): name not found: '{}'", name)))
         },
         Err(e) => Err(PyErr::from(e)),
@@ -120,7 +140,6 @@ pub fn getgrnam(py: Python<'_>, name: &str, module: Option<PyNssModule>) -> PyRe
 #[pyo3(signature = (gid, *, module=None))]
 pub fn getgrgid(py: Python<'_>, gid: &Bound<'_, pyo3::PyAny>, module: Option<PyNssModule>) -> PyResult<PyGroupEntry> {
     use pyo3::exceptions::{PyKeyError, PyOverflowError};
-    use crate::{NssError, NssReturnCode};
 
     // Try to extract gid_t, convert OverflowError to KeyError
     let gid_val: gid_t = match gid.extract() {
@@ -136,7 +155,7 @@ pub fn getgrgid(py: Python<'_>, gid: &Bound<'_, pyo3::PyAny>, module: Option<PyN
     let result = py.allow_threads(|| rust_getgrgid(gid_val, nss_module));
     match result {
         Ok(entry) => Ok(entry.into()),
-        Err(NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, .. }) => {
+        Err(e) if e.is_not_found() => {
             Err(PyKeyError::new_err(format!("getgrgid(): gid not found: '{}'", gid)))
         },
         Err(e) => Err(PyErr::from(e)),
@@ -157,10 +176,10 @@ pub fn getgrgid(py: Python<'_>, gid: &Bound<'_, pyo3::PyAny>, module: Option<PyN
 ///     modules storing the handle for the grent in thread-local variable.
 #[pyfunction]
 #[pyo3(signature = (module=PyNssModule::FILES))]
-pub fn itergrp(py: Python<'_>, module: PyNssModule) -> PyResult<PyGroupIterator> {
-    let nss_module = module.into();
-    let iterator = py.allow_threads(|| rust_itergrp(nss_module));
-    Ok(iterator.into())
+pub fn itergrp(module: PyNssModule) -> PyResult<PyGroupIterator> {
+    // Creating the iterator does no NSS work, and it cannot leave this
+    // thread, so there is nothing to release the GIL for
+    Ok(rust_itergrp(module.into()).into())
 }
 
 /// Returns all group entries on server (similar to grp.getgrall()).
@@ -168,64 +187,38 @@ pub fn itergrp(py: Python<'_>, module: PyNssModule) -> PyResult<PyGroupIterator>
 /// Args:
 ///     module: NSS module from which to retrieve the entries
 ///     as_dict: return group database entries as dictionaries
+///     wrap: return a PyNssResults object instead of a dict; it supports
+///           results["FILES"], len() over all entries and flat iteration
 ///
 /// Returns:
 ///     dict: Dictionary keyed by NSS module, e.g.
 ///           {'FILES': [<PyGroupEntry>, <PyGroupEntry>], 'WINBIND': [], 'SSS': []}
+///     PyNssResults: if wrap is set
 #[pyfunction]
-#[pyo3(signature = (*, module=None, as_dict=false))]
-pub fn getgrall(module: Option<PyNssModule>, as_dict: bool, py: Python<'_>) -> PyResult<PyObject> {
+#[pyo3(signature = (*, module=None, as_dict=false, wrap=false))]
+pub fn getgrall(module: Option<PyNssModule>, as_dict: bool, wrap: bool, py: Python<'_>) -> PyResult<PyObject> {
     use crate::group::getgrall as rust_getgrall;
-    use pyo3::types::PyDict;
 
     // Convert PyNssModule option to NssModule option
     let nss_module = module.map(|m| m.into());
 
-    let entries_result = py.allow_threads(|| rust_getgrall(nss_module));
-    match entries_result {
-        Ok(entries) => {
-            if as_dict {
-                // Return dictionary keyed by uppercase module name
-                let result_dict = PyDict::new(py);
-                let mut entries_by_module: std::collections::HashMap<String, Vec<PyGroupEntry>> = std::collections::HashMap::new();
-
-                for entry in entries {
-                    let source = entry.source.to_uppercase();
-                    let py_entry = PyGroupEntry::from(entry);
-                    entries_by_module.entry(source).or_default().push(py_entry);
-                }
-
-                for (module_name, module_entries) in entries_by_module {
-                    let py_entries: Vec<PyObject> = module_entries.into_iter()
-                        .map(|entry| entry.to_dict(py))
-                        .collect::<PyResult<Vec<_>>>()?;
-                    result_dict.set_item(module_name, py_entries)?;
-                }
-
-                Ok(result_dict.into())
-            } else {
-                // Return dictionary keyed by uppercase module name with PyGroupEntry objects
-                let result_dict = PyDict::new(py);
-                let mut entries_by_module: std::collections::HashMap<String, Vec<PyGroupEntry>> = std::collections::HashMap::new();
-
-                for entry in entries {
-                    let source = entry.source.to_uppercase();
-                    let py_entry = PyGroupEntry::from(entry);
-                    entries_by_module.entry(source).or_default().push(py_entry);
-                }
-
-                for (module_name, module_entries) in entries_by_module {
-                    let py_objects: Vec<PyObject> = module_entries.into_iter()
-                        .map(|entry| Py::new(py, entry).unwrap().into_any())
-                        .collect();
-                    result_dict.set_item(module_name, py_objects)?;
-                }
-
-                Ok(result_dict.into())
-            }
-        }
-        Err(e) => Err(PyErr::from(e)),
+    let entries = py.allow_threads(|| rust_getgrall(nss_module))?;
+
+    // Key results by the source each entry was stamped with, so keys always
+    // match `entry.source`
+    let mut converted = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let source = entry.source.clone();
+        let py_entry = PyGroupEntry::from(entry);
+        let obj = if as_dict {
+            py_entry.to_dict(py)?
+        } else {
+            Py::new(py, py_entry)?.into_any()
+        };
+        converted.push((source, obj));
     }
+
+    build_results(py, converted, wrap)
 }
 
 pub fn init_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
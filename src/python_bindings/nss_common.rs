@@ -1,6 +1,8 @@
 use pyo3::prelude::*;
-use pyo3::exceptions::PyException;
-use crate::{NssError as RustNssError, NssModule};
+use pyo3::exceptions::{PyException, PyKeyError};
+use pyo3::types::{PyDict, PyList};
+use std::path::PathBuf;
+use crate::{NssError as RustNssError, NssModule, NssReturnCode};
 
 #[pyclass]
 #[derive(Debug, Clone)]
@@ -56,16 +58,166 @@ impl From<PyNssModule> for NssModule {
     }
 }
 
+/// Result of getpwall()/getgrall() with wrap=True.
+///
+/// Behaves like the module-keyed dict for indexing (results["FILES"]) and like
+/// a flat sequence of entries for len() and iteration.
+#[pyclass]
+pub struct PyNssResults {
+    by_module: Vec<(String, Vec<PyObject>)>,
+}
+
+#[pymethods]
+impl PyNssResults {
+    fn __len__(&self) -> usize {
+        self.by_module.iter().map(|(_, entries)| entries.len()).sum()
+    }
+
+    fn __getitem__<'py>(&self, py: Python<'py>, module: &str) -> PyResult<Bound<'py, PyList>> {
+        match self.by_module.iter().find(|(name, _)| name == module) {
+            Some((_, entries)) => PyList::new(py, entries.iter().map(|e| e.clone_ref(py))),
+            None => Err(PyKeyError::new_err(module.to_string())),
+        }
+    }
+
+    fn __contains__(&self, module: &str) -> bool {
+        self.by_module.iter().any(|(name, _)| name == module)
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let flat: Vec<PyObject> = self.by_module.iter()
+            .flat_map(|(_, entries)| entries.iter().map(|e| e.clone_ref(py)))
+            .collect();
+        Ok(PyList::new(py, flat)?.try_iter()?.into_any())
+    }
+
+    fn __repr__(&self) -> String {
+        let counts: Vec<String> = self.by_module.iter()
+            .map(|(name, entries)| format!("'{}': {}", name, entries.len()))
+            .collect();
+        format!("NssResults({{{}}})", counts.join(", "))
+    }
+
+    /// Module names present in the results.
+    fn keys(&self) -> Vec<String> {
+        self.by_module.iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+/// Group converted entries by their source module for getpwall()/getgrall().
+///
+/// Returns a plain dict keyed by module name, or a PyNssResults when `wrap` is set.
+pub fn build_results(py: Python<'_>, entries: Vec<(String, PyObject)>, wrap: bool) -> PyResult<PyObject> {
+    let mut by_module: Vec<(String, Vec<PyObject>)> = Vec::new();
+    for (source, entry) in entries {
+        match by_module.iter_mut().find(|(name, _)| *name == source) {
+            Some((_, module_entries)) => module_entries.push(entry),
+            None => by_module.push((source, vec![entry])),
+        }
+    }
+
+    if wrap {
+        return Ok(Py::new(py, PyNssResults { by_module })?.into_any());
+    }
+
+    let result_dict = PyDict::new(py);
+    for (module_name, module_entries) in by_module {
+        result_dict.set_item(module_name, module_entries)?;
+    }
+    Ok(result_dict.into())
+}
+
 pyo3::create_exception!(truenas_nss, NssError, PyException);
+// Subclasses of NssError, so `except NssError` still catches everything
+pyo3::create_exception!(truenas_nss, NssLibraryError, NssError, "An NSS module library could not be loaded.");
+pyo3::create_exception!(truenas_nss, NssUnavailableError, NssError, "An NSS module reported its service unavailable.");
+pyo3::create_exception!(truenas_nss, NssPermissionError, NssError, "An NSS module denied the lookup.");
 
 impl From<RustNssError> for PyErr {
     fn from(err: RustNssError) -> Self {
-        NssError::new_err(err.to_string())
+        let message = err.to_string();
+        match err {
+            RustNssError::LibraryError(_) => NssLibraryError::new_err(message),
+            RustNssError::NssOperationFailed {
+                return_code: NssReturnCode::Unavail | NssReturnCode::TryAgain,
+                ..
+            } => NssUnavailableError::new_err(message),
+            RustNssError::PermissionDenied { .. } => NssPermissionError::new_err(message),
+            _ => NssError::new_err(message),
+        }
+    }
+}
+
+/// Serialize `value` with `serde_json` for the entries' `to_json()`: compact,
+/// or indented by two spaces when `pretty`.
+pub fn to_json_string<T: serde::Serialize>(value: &T, pretty: bool) -> PyResult<String> {
+    let json = if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    };
+    json.map_err(|e| NssError::new_err(e.to_string()))
+}
+
+/// Set the directory NSS module libraries are loaded from.
+///
+/// Args:
+///     path: Existing directory to search, or None for the default
+///
+/// Raises:
+///     NssError: If path is not an existing directory
+///
+/// Modules that have already been loaded are not reloaded.
+#[pyfunction]
+#[pyo3(signature = (path))]
+pub fn set_modules_dir(path: Option<PathBuf>) -> PyResult<()> {
+    let path = path.unwrap_or_else(|| PathBuf::from(crate::nss_common::NSS_MODULES_DIR));
+    if !path.is_dir() {
+        return Err(NssError::new_err(format!("not a directory: '{}'", path.display())));
     }
+    crate::nss_common::set_module_search_paths(vec![path]);
+    Ok(())
+}
+
+/// Set the modules queried, in order, when no module is given to a lookup.
+///
+/// Args:
+///     modules: Non-empty list of PyNssModule, or None to go back to the
+///         nsswitch.conf order
+///
+/// Raises:
+///     NssError: If the list is empty or names a module twice
+#[pyfunction]
+#[pyo3(signature = (modules))]
+pub fn set_default_modules(modules: Option<Vec<PyNssModule>>) -> PyResult<()> {
+    let Some(modules) = modules else {
+        crate::nsswitch::clear_default_modules();
+        return Ok(());
+    };
+
+    crate::nsswitch::set_default_modules(modules.into_iter().map(NssModule::from).collect())?;
+    Ok(())
+}
+
+/// Path of the library loaded for a module, or None if it is not loaded yet.
+///
+/// Args:
+///     module: PyNssModule to check
+#[pyfunction]
+#[pyo3(signature = (module))]
+pub fn loaded_module_path(module: PyNssModule) -> Option<PathBuf> {
+    crate::nss_common::loaded_module_path(module.into())
 }
 
 pub fn init_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyNssModule>()?;
+    m.add_class::<PyNssResults>()?;
     m.add("NssError", m.py().get_type::<NssError>())?;
+    m.add("NssLibraryError", m.py().get_type::<NssLibraryError>())?;
+    m.add("NssUnavailableError", m.py().get_type::<NssUnavailableError>())?;
+    m.add("NssPermissionError", m.py().get_type::<NssPermissionError>())?;
+    m.add_function(wrap_pyfunction!(set_modules_dir, m)?)?;
+    m.add_function(wrap_pyfunction!(set_default_modules, m)?)?;
+    m.add_function(wrap_pyfunction!(loaded_module_path, m)?)?;
     Ok(())
 }
\ No newline at end of file
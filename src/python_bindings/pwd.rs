@@ -1,9 +1,9 @@
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
-use libc::uid_t;
+use libc::{gid_t, uid_t};
 use crate::{PasswdEntry, PasswdIterator};
 use crate::passwd::{getpwnam as rust_getpwnam, getpwuid as rust_getpwuid, iterpw as rust_iterpw};
-use super::nss_common::PyNssModule;
+use super::nss_common::{build_results, to_json_string, PyNssModule};
 
 #[pyclass]
 #[derive(Debug, Clone)]
@@ -13,7 +13,7 @@ pub struct PyPasswdEntry {
     #[pyo3(get)]
     pub pw_uid: uid_t,
     #[pyo3(get)]
-    pub pw_gid: uid_t,
+    pub pw_gid: gid_t,
     #[pyo3(get)]
     pub pw_gecos: String,
     #[pyo3(get)]
@@ -26,6 +26,30 @@ pub struct PyPasswdEntry {
 
 #[pymethods]
 impl PyPasswdEntry {
+    #[new]
+    #[pyo3(signature = (pw_name, pw_uid, pw_gid, pw_gecos=String::new(), pw_dir=String::new(), pw_shell=String::new(), source=String::new()))]
+    fn new(
+        pw_name: String,
+        pw_uid: uid_t,
+        pw_gid: gid_t,
+        pw_gecos: String,
+        pw_dir: String,
+        pw_shell: String,
+        source: String,
+    ) -> Self {
+        PyPasswdEntry { pw_name, pw_uid, pw_gid, pw_gecos, pw_dir, pw_shell, source }
+    }
+
+    /// Name to show in user pickers: the first comma-separated GECOS field
+    /// (the full name, by convention), or `pw_name` if that field is empty.
+    #[getter]
+    fn display_name(&self) -> String {
+        match self.pw_gecos.split(',').next().map(str::trim) {
+            Some(full_name) if !full_name.is_empty() => full_name.to_string(),
+            _ => self.pw_name.clone(),
+        }
+    }
+
     fn __str__(&self) -> String {
         format!("{}:x:{}:{}:{}:{}:{}",
                 self.pw_name, self.pw_uid, self.pw_gid,
@@ -47,8 +71,40 @@ impl PyPasswdEntry {
         dict.set_item("pw_dir", &self.pw_dir)?;
         dict.set_item("pw_shell", &self.pw_shell)?;
         dict.set_item("source", &self.source)?;
+        dict.set_item("display_name", self.display_name())?;
         Ok(dict.into())
     }
+
+    /// `to_dict()` as a JSON string, with the same keys in the same order.
+    ///
+    /// Compact by default; `pretty` indents by two spaces. Non-ASCII text is
+    /// written as UTF-8 rather than escaped.
+    #[pyo3(signature = (pretty=false))]
+    fn to_json(&self, pretty: bool) -> PyResult<String> {
+        #[derive(serde::Serialize)]
+        struct Json<'a> {
+            pw_name: &'a str,
+            pw_uid: uid_t,
+            pw_gid: gid_t,
+            pw_gecos: &'a str,
+            pw_dir: &'a str,
+            pw_shell: &'a str,
+            source: &'a str,
+            display_name: String,
+        }
+
+        let json = Json {
+            pw_name: &self.pw_name,
+            pw_uid: self.pw_uid,
+            pw_gid: self.pw_gid,
+            pw_gecos: &self.pw_gecos,
+            pw_dir: &self.pw_dir,
+            pw_shell: &self.pw_shell,
+            source: &self.source,
+            display_name: self.display_name(),
+        };
+        to_json_string(&json, pretty)
+    }
 }
 
 impl From<PasswdEntry> for PyPasswdEntry {
@@ -65,7 +121,8 @@ impl From<PasswdEntry> for PyPasswdEntry {
     }
 }
 
-#[pyclass]
+// Unsendable: the underlying enumeration is bound to the creating thread
+#[pyclass(unsendable)]
 pub struct PyPasswdIterator {
     inner: PasswdIterator,
 }
@@ -106,13 +163,12 @@ impl From<PasswdIterator> for PyPasswdIterator {
 #[pyo3(signature = (name, *, module=None))]
 pub fn getpwnam(py: Python<'_>, name: &str, module: Option<PyNssModule>) -> PyResult<PyPasswdEntry> {
     use pyo3::exceptions::PyKeyError;
-    use crate::{NssError, NssReturnCode};
 
     let nss_module = module.map(|m| m.into());
     let result = py.allow_threads(|| rust_getpwnam(name, nss_module));
     match result {
         Ok(entry) => Ok(entry.into()),
-        Err(NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, .. }) => {
+        Err(e) if e.is_not_found() => {
             Err(PyKeyError::new_err(format!("getpwnam(): name not found: '{}'", name)))
         },
         Err(e) => Err(PyErr::from(e)),
@@ -134,7 +190,6 @@ pub fn getpwnam(py: Python<'_>, name: &str, module: Option<PyNssModule>) -> PyRe
 #[pyo3(signature = (uid, *, module=None))]
 pub fn getpwuid(py: Python<'_>, uid: &Bound<'_, pyo3::PyAny>, module: Option<PyNssModule>) -> PyResult<PyPasswdEntry> {
     use pyo3::exceptions::{PyKeyError, PyOverflowError};
-    use crate::{NssError, NssReturnCode};
 
     // Try to extract uid_t, convert OverflowError to KeyError
     let uid_val: uid_t = match uid.extract() {
@@ -150,7 +205,7 @@ pub fn getpwuid(py: Python<'_>, uid: &Bound<'_, pyo3::PyAny>, module: Option<PyN
     let result = py.allow_threads(|| rust_getpwuid(uid_val, nss_module));
     match result {
         Ok(entry) => Ok(entry.into()),
-        Err(NssError::NssOperationFailed { return_code: NssReturnCode::NotFound, .. }) => {
+        Err(e) if e.is_not_found() => {
             Err(PyKeyError::new_err(format!("getpwuid(): uid not found: '{}'", uid)))
         },
         Err(e) => Err(PyErr::from(e)),
@@ -171,10 +226,10 @@ pub fn getpwuid(py: Python<'_>, uid: &Bound<'_, pyo3::PyAny>, module: Option<PyN
 ///     modules storing the handle for the pwent in thread-local variable.
 #[pyfunction]
 #[pyo3(signature = (module=PyNssModule::FILES))]
-pub fn iterpw(py: Python<'_>, module: PyNssModule) -> PyResult<PyPasswdIterator> {
-    let nss_module = module.into();
-    let iterator = py.allow_threads(|| rust_iterpw(nss_module));
-    Ok(iterator.into())
+pub fn iterpw(module: PyNssModule) -> PyResult<PyPasswdIterator> {
+    // Creating the iterator does no NSS work, and it cannot leave this
+    // thread, so there is nothing to release the GIL for
+    Ok(rust_iterpw(module.into()).into())
 }
 
 /// Returns all password entries on server (similar to pwd.getpwall()).
@@ -182,64 +237,38 @@ pub fn iterpw(py: Python<'_>, module: PyNssModule) -> PyResult<PyPasswdIterator>
 /// Args:
 ///     module: NSS module from which to retrieve the entries
 ///     as_dict: return password database entries as dictionaries
+///     wrap: return a PyNssResults object instead of a dict; it supports
+///           results["FILES"], len() over all entries and flat iteration
 ///
 /// Returns:
 ///     dict: Dictionary keyed by NSS module, e.g.
 ///           {'FILES': [<PyPasswdEntry>, <PyPasswdEntry>], 'WINBIND': [], 'SSS': []}
+///     PyNssResults: if wrap is set
 #[pyfunction]
-#[pyo3(signature = (*, module=None, as_dict=false))]
-pub fn getpwall(module: Option<PyNssModule>, as_dict: bool, py: Python<'_>) -> PyResult<PyObject> {
+#[pyo3(signature = (*, module=None, as_dict=false, wrap=false))]
+pub fn getpwall(module: Option<PyNssModule>, as_dict: bool, wrap: bool, py: Python<'_>) -> PyResult<PyObject> {
     use crate::passwd::getpwall as rust_getpwall;
-    use pyo3::types::PyDict;
 
     // Convert PyNssModule option to NssModule option
     let nss_module = module.map(|m| m.into());
 
-    let entries_result = py.allow_threads(|| rust_getpwall(nss_module));
-    match entries_result {
-        Ok(entries) => {
-            if as_dict {
-                // Return dictionary keyed by uppercase module name
-                let result_dict = PyDict::new(py);
-                let mut entries_by_module: std::collections::HashMap<String, Vec<PyPasswdEntry>> = std::collections::HashMap::new();
-
-                for entry in entries {
-                    let source = entry.source.to_uppercase();
-                    let py_entry = PyPasswdEntry::from(entry);
-                    entries_by_module.entry(source).or_default().push(py_entry);
-                }
-
-                for (module_name, module_entries) in entries_by_module {
-                    let py_entries: Vec<PyObject> = module_entries.into_iter()
-                        .map(|entry| entry.to_dict(py))
-                        .collect::<PyResult<Vec<_>>>()?;
-                    result_dict.set_item(module_name, py_entries)?;
-                }
-
-                Ok(result_dict.into())
-            } else {
-                // Return dictionary keyed by uppercase module name with PyPasswdEntry objects
-                let result_dict = PyDict::new(py);
-                let mut entries_by_module: std::collections::HashMap<String, Vec<PyPasswdEntry>> = std::collections::HashMap::new();
-
-                for entry in entries {
-                    let source = entry.source.to_uppercase();
-                    let py_entry = PyPasswdEntry::from(entry);
-                    entries_by_module.entry(source).or_default().push(py_entry);
-                }
-
-                for (module_name, module_entries) in entries_by_module {
-                    let py_objects: Vec<PyObject> = module_entries.into_iter()
-                        .map(|entry| Py::new(py, entry).unwrap().into_any())
-                        .collect();
-                    result_dict.set_item(module_name, py_objects)?;
-                }
-
-                Ok(result_dict.into())
-            }
-        }
-        Err(e) => Err(PyErr::from(e)),
+    let entries = py.allow_threads(|| rust_getpwall(nss_module))?;
+
+    // Key results by the source each entry was stamped with, so keys always
+    // match `entry.source`
+    let mut converted = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let source = entry.source.clone();
+        let py_entry = PyPasswdEntry::from(entry);
+        let obj = if as_dict {
+            py_entry.to_dict(py)?
+        } else {
+            Py::new(py, py_entry)?.into_any()
+        };
+        converted.push((source, obj));
     }
+
+    build_results(py, converted, wrap)
 }
 
 pub fn init_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::PasswdEntry;
+
+/// Location of the list of valid login shells.
+pub const SHELLS_PATH: &str = "/etc/shells";
+
+/// Shells that mark an account as non-login even if listed in `SHELLS_PATH`.
+const NOLOGIN_SHELLS: [&str; 2] = ["/usr/sbin/nologin", "/sbin/nologin"];
+
+/// Parsed `SHELLS_PATH`, read on first use. Read errors are not cached.
+static SYSTEM_SHELLS: OnceLock<HashSet<OsString>> = OnceLock::new();
+
+/// Parse a shells file: one path per line, `#` comments and blank lines ignored.
+fn read_shells(path: &Path) -> io::Result<HashSet<OsString>> {
+    let contents = fs::read(path)?;
+    Ok(contents
+        .split(|&b| b == b'\n')
+        .map(|line| line.trim_ascii())
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(|line| OsString::from_vec(line.to_vec()))
+        .collect())
+}
+
+impl PasswdEntry {
+    /// Whether `pw_shell` is a valid login shell, i.e. listed in `SHELLS_PATH`.
+    ///
+    /// An empty shell and `nologin` are never valid. The shells file is read
+    /// once per process and the parsed list reused.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the shells file cannot be read.
+    pub fn has_valid_shell(&self) -> io::Result<bool> {
+        let shells = match SYSTEM_SHELLS.get() {
+            Some(shells) => shells,
+            None => {
+                let shells = read_shells(Path::new(SHELLS_PATH))?;
+                SYSTEM_SHELLS.get_or_init(|| shells)
+            }
+        };
+        Ok(self.shell_listed(shells))
+    }
+
+    fn shell_listed(&self, shells: &HashSet<OsString>) -> bool {
+        let shell = self.shell_os();
+        if shell.is_empty() || NOLOGIN_SHELLS.iter().any(|nologin| shell.as_bytes() == nologin.as_bytes()) {
+            return false;
+        }
+        shells.contains(shell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::passwd::RawPasswdFields;
+    use crate::NssModule;
+
+    fn with_shell(shell: &str) -> PasswdEntry {
+        PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1000,
+            pw_gecos: String::new(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: shell.to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_shells_file_membership() {
+        let path = std::env::temp_dir().join(format!("truenas_nss_shells_{}", std::process::id()));
+        fs::write(&path, "# valid login shells\n/bin/bash\n\n  /usr/bin/zsh  \n/usr/sbin/nologin\n").unwrap();
+        let shells = read_shells(&path);
+        fs::remove_file(&path).unwrap();
+        let shells = shells.unwrap();
+
+        assert!(with_shell("/bin/bash").shell_listed(&shells));
+        assert!(with_shell("/usr/bin/zsh").shell_listed(&shells));
+        assert!(!with_shell("/bin/fish").shell_listed(&shells));
+        // Non-login by convention, even when listed
+        assert!(!with_shell("/usr/sbin/nologin").shell_listed(&shells));
+        assert!(!with_shell("").shell_listed(&shells));
+        assert!(!shells.contains(OsString::from("# valid login shells").as_os_str()));
+    }
+
+    #[test]
+    fn test_missing_shells_file_is_an_error() {
+        let result = read_shells(Path::new("/nonexistent/truenas_nss/shells"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
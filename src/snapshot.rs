@@ -0,0 +1,137 @@
+//! Point-in-time copies of the passwd database, for detecting changes
+//! between runs.
+
+use std::collections::BTreeMap;
+
+use crate::{getpwall, NssModule, NssResult, PasswdEntry};
+
+/// Passwd entries captured at one moment, keyed by `pw_name`.
+#[derive(Debug, Clone, Default)]
+pub struct PasswdSnapshot {
+    entries: BTreeMap<String, PasswdEntry>,
+}
+
+/// Differences between two `PasswdSnapshot`s, each list sorted by name.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    /// Entries whose name is only in the newer snapshot.
+    pub added: Vec<PasswdEntry>,
+    /// Entries whose name is only in the older snapshot, as they were then.
+    pub removed: Vec<PasswdEntry>,
+    /// Entries present in both whose fingerprint changed, as they are now.
+    pub modified: Vec<PasswdEntry>,
+}
+
+impl SnapshotDiff {
+    /// Whether nothing changed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl PasswdSnapshot {
+    /// Capture every entry `getpwall(module)` returns.
+    ///
+    /// If a name appears more than once, the last entry in `getpwall` order
+    /// is kept.
+    ///
+    /// # Errors
+    /// Returns `NssError` if enumeration fails.
+    pub fn capture(module: Option<NssModule>) -> NssResult<Self> {
+        Ok(Self::from_entries(getpwall(module)?))
+    }
+
+    /// Build a snapshot from entries obtained elsewhere, e.g. a saved copy.
+    pub fn from_entries(entries: impl IntoIterator<Item = PasswdEntry>) -> Self {
+        PasswdSnapshot {
+            entries: entries.into_iter().map(|entry| (entry.pw_name.clone(), entry)).collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&PasswdEntry> {
+        self.entries.get(name)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// What changed from `previous` to `self`.
+    ///
+    /// Entries are matched by name, so a rename shows up as one removal and
+    /// one addition. An entry counts as modified when its `fingerprint`
+    /// differs, which ignores the module that served it.
+    #[must_use]
+    pub fn diff(&self, previous: &PasswdSnapshot) -> SnapshotDiff {
+        let mut diff = SnapshotDiff::default();
+
+        for (name, entry) in &self.entries {
+            match previous.entries.get(name) {
+                None => diff.added.push(entry.clone()),
+                Some(old) if old.fingerprint() != entry.fingerprint() => diff.modified.push(entry.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = previous
+            .entries
+            .iter()
+            .filter(|(name, _)| !self.entries.contains_key(*name))
+            .map(|(_, entry)| entry.clone())
+            .collect();
+
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::passwd::RawPasswdFields;
+
+    fn user(name: &str, uid: u32, shell: &str) -> PasswdEntry {
+        PasswdEntry {
+            pw_name: name.to_string(),
+            pw_uid: uid,
+            pw_gid: 100,
+            pw_gecos: String::new(),
+            pw_dir: format!("/home/{}", name),
+            pw_shell: shell.to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_diff_reports_changed_shell() {
+        let before = PasswdSnapshot::from_entries([
+            user("alice", 1000, "/bin/sh"),
+            user("bob", 1001, "/bin/sh"),
+            user("carol", 1002, "/bin/sh"),
+        ]);
+        let after = PasswdSnapshot::from_entries([
+            user("alice", 1000, "/bin/sh"),
+            user("bob", 1001, "/bin/zsh"),
+            user("dave", 1003, "/bin/sh"),
+        ]);
+
+        let diff = after.diff(&before);
+        let names = |entries: &[PasswdEntry]| entries.iter().map(|e| e.pw_name.clone()).collect::<Vec<_>>();
+        assert_eq!(names(&diff.modified), ["bob"]);
+        assert_eq!(diff.modified[0].pw_shell, "/bin/zsh");
+        assert_eq!(names(&diff.added), ["dave"]);
+        assert_eq!(names(&diff.removed), ["carol"]);
+
+        assert!(after.diff(&after).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+//! SSS attributes beyond the POSIX passwd fields.
+//!
+//! `libnss_sss` only implements the standard NSS entry points. SSSD serves
+//! everything else (user principal name, object SID, ...) through
+//! `libsss_nss_idmap`, which is opened on first use and never required: when
+//! it is missing, entries simply carry no extra attributes.
+
+use libc::{c_char, c_int, c_void, dlopen, dlsym, RTLD_LAZY};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::mem;
+use std::sync::OnceLock;
+
+use crate::{getpwnam, NssModule, NssResult, PasswdEntry};
+
+const IDMAP_LIBRARY: &CStr = c"libsss_nss_idmap.so.0";
+
+/// `struct sss_nss_kv` from `sss_nss_idmap.h`.
+#[repr(C)]
+struct SssKv {
+    key: *mut c_char,
+    value: *mut c_char,
+}
+
+type GetOrigByNameFn =
+    unsafe extern "C" fn(fq_name: *const c_char, kv_list: *mut *mut SssKv, id_type: *mut c_int) -> c_int;
+type FreeKvFn = unsafe extern "C" fn(kv_list: *mut SssKv);
+
+struct IdmapLibrary {
+    get_orig_by_name: GetOrigByNameFn,
+    free_kv: FreeKvFn,
+}
+
+/// `libsss_nss_idmap`'s functions, or `None` if it is not installed. Like NSS
+/// modules, the library is never closed.
+fn idmap_library() -> Option<&'static IdmapLibrary> {
+    static LIBRARY: OnceLock<Option<IdmapLibrary>> = OnceLock::new();
+
+    LIBRARY
+        .get_or_init(|| unsafe {
+            let handle = dlopen(IDMAP_LIBRARY.as_ptr(), RTLD_LAZY);
+            if handle.is_null() {
+                return None;
+            }
+            let get_orig_by_name = dlsym(handle, c"sss_nss_getorigbyname".as_ptr());
+            let free_kv = dlsym(handle, c"sss_nss_free_kv".as_ptr());
+            if get_orig_by_name.is_null() || free_kv.is_null() {
+                return None;
+            }
+            Some(IdmapLibrary {
+                get_orig_by_name: mem::transmute::<*mut c_void, GetOrigByNameFn>(get_orig_by_name),
+                free_kv: mem::transmute::<*mut c_void, FreeKvFn>(free_kv),
+            })
+        })
+        .as_ref()
+}
+
+/// SSSD's original-object attributes for `name`, empty if unavailable.
+fn extra_attributes(name: &str) -> HashMap<String, String> {
+    let mut extra = HashMap::new();
+    let (Some(library), Ok(name_c)) = (idmap_library(), CString::new(name)) else {
+        return extra;
+    };
+
+    unsafe {
+        let mut kv_list: *mut SssKv = std::ptr::null_mut();
+        let mut id_type: c_int = 0;
+        if (library.get_orig_by_name)(name_c.as_ptr(), &mut kv_list, &mut id_type) != 0 || kv_list.is_null() {
+            return extra;
+        }
+
+        // The list ends at the first entry with a NULL key
+        let mut kv = kv_list;
+        while !(*kv).key.is_null() {
+            if !(*kv).value.is_null() {
+                extra.insert(
+                    CStr::from_ptr((*kv).key).to_string_lossy().into_owned(),
+                    CStr::from_ptr((*kv).value).to_string_lossy().into_owned(),
+                );
+            }
+            kv = kv.add(1);
+        }
+        (library.free_kv)(kv_list);
+    }
+
+    extra
+}
+
+/// Get password entry by username, with `extra` filled in when SSS served it.
+///
+/// Looks the user up exactly as `getpwnam(name, None)`. If the entry came from
+/// SSS, `extra` gets the attributes SSSD reports for the original object
+/// through `libsss_nss_idmap` (e.g. `userPrincipalName` for AD users); it is
+/// left empty for other modules, or when that library is not installed.
+///
+/// # Errors
+/// Returns `NssError` if the user is not found or NSS operation fails.
+pub fn getpwnam_ex(name: &str) -> NssResult<PasswdEntry> {
+    let mut entry = getpwnam(name, None)?;
+    if entry.source_module == Some(NssModule::Sss) {
+        entry.extra = extra_attributes(&entry.pw_name);
+    }
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nss_common::test_support::lock_global_config;
+    use crate::nss_common::test_support::{clear_mock_functions, set_mock_function};
+    use crate::nsswitch::{clear_default_modules, set_default_modules};
+    use crate::{NssOperation, NssReturnCode};
+    use libc::passwd;
+
+    unsafe extern "C" fn mock_getpwnam_alice(
+        name: *const c_char,
+        result: *mut passwd,
+        _buffer: *mut c_char,
+        _buflen: libc::size_t,
+        _errnop: *mut c_int,
+    ) -> c_int {
+        if CStr::from_ptr(name) != c"alice" {
+            return NssReturnCode::NotFound as c_int;
+        }
+        (*result).pw_name = c"alice".as_ptr().cast_mut();
+        (*result).pw_uid = 1000;
+        (*result).pw_gid = 1000;
+        NssReturnCode::Success as c_int
+    }
+
+    #[test]
+    fn test_getpwnam_ex_non_sss_extra_empty() {
+        let _config = lock_global_config();
+        set_default_modules(vec![NssModule::Files, NssModule::Sss]).unwrap();
+        set_mock_function(NssModule::Files, NssOperation::GetPwNam, mock_getpwnam_alice as *mut c_void);
+
+        let entry = getpwnam_ex("alice").unwrap();
+        assert_eq!(entry.source_module, Some(NssModule::Files));
+        assert!(entry.extra.is_empty());
+
+        // The plain lookup never fills it in
+        assert!(getpwnam("alice", Some(NssModule::Files)).unwrap().extra.is_empty());
+
+        clear_default_modules();
+        clear_mock_functions();
+    }
+}
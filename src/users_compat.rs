@@ -0,0 +1,154 @@
+//! Conversions to and from the `users` crate, for codebases migrating from it.
+//!
+//! Field mapping is lossy in both directions:
+//! - `users::User` has no GECOS field, so `pw_gecos` is dropped when converting
+//!   to a `User` and empty when converting back.
+//! - Neither `users` type records which module produced it, so `source` is
+//!   dropped, and is empty on entries converted from `users` types.
+//! - `users::User` carries a password field; it is left at the crate's default.
+
+use std::collections::HashMap;
+use std::os::unix::ffi::OsStrExt;
+use users::os::unix::{GroupExt, UserExt};
+
+use crate::passwd::{decode_bytes, RawPasswdFields};
+use crate::{GroupEntry, PasswdEntry};
+
+impl From<&PasswdEntry> for users::User {
+    fn from(entry: &PasswdEntry) -> Self {
+        users::User::new(entry.pw_uid, entry.name_os(), entry.pw_gid)
+            .with_home_dir(entry.dir_os())
+            .with_shell(entry.shell_os())
+    }
+}
+
+impl From<PasswdEntry> for users::User {
+    fn from(entry: PasswdEntry) -> Self {
+        users::User::from(&entry)
+    }
+}
+
+impl From<&users::User> for PasswdEntry {
+    fn from(user: &users::User) -> Self {
+        let (pw_name, name) = decode_bytes(user.name().as_bytes());
+        let (pw_dir, dir) = decode_bytes(user.home_dir().as_os_str().as_bytes());
+        let (pw_shell, shell) = decode_bytes(user.shell().as_os_str().as_bytes());
+
+        PasswdEntry {
+            pw_name,
+            pw_uid: user.uid(),
+            pw_gid: user.primary_group_id(),
+            pw_gecos: String::new(),
+            pw_dir,
+            pw_shell,
+            source: String::new(),
+            source_module: None,
+            extra: HashMap::new(),
+            raw: RawPasswdFields { name, gecos: None, dir, shell },
+        }
+    }
+}
+
+impl From<users::User> for PasswdEntry {
+    fn from(user: users::User) -> Self {
+        PasswdEntry::from(&user)
+    }
+}
+
+impl From<&GroupEntry> for users::Group {
+    fn from(entry: &GroupEntry) -> Self {
+        entry
+            .gr_mem
+            .iter()
+            .fold(users::Group::new(entry.gr_gid, &entry.gr_name), |group, member| {
+                group.add_member(member)
+            })
+    }
+}
+
+impl From<GroupEntry> for users::Group {
+    fn from(entry: GroupEntry) -> Self {
+        users::Group::from(&entry)
+    }
+}
+
+/// Names and members that are not valid UTF-8 are converted lossily; invalid
+/// members are counted in `invalid_members`.
+impl From<&users::Group> for GroupEntry {
+    fn from(group: &users::Group) -> Self {
+        let gr_mem: Vec<String> = group
+            .members()
+            .iter()
+            .map(|member| member.to_string_lossy().into_owned())
+            .collect();
+
+        GroupEntry {
+            gr_name: group.name().to_string_lossy().into_owned(),
+            gr_gid: group.gid(),
+            gr_mem,
+            source: String::new(),
+            source_module: None,
+            invalid_members: group.members().iter().filter(|m| m.to_str().is_none()).count(),
+        }
+    }
+}
+
+impl From<users::Group> for GroupEntry {
+    fn from(group: users::Group) -> Self {
+        GroupEntry::from(&group)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NssModule;
+
+    #[test]
+    fn test_passwd_round_trip() {
+        let entry = PasswdEntry {
+            pw_name: "testuser".to_string(),
+            pw_uid: 1000,
+            pw_gid: 1001,
+            pw_gecos: "Test User".to_string(),
+            pw_dir: "/home/testuser".to_string(),
+            pw_shell: "/bin/bash".to_string(),
+            source: "FILES".to_string(),
+            source_module: Some(NssModule::Files),
+            extra: HashMap::new(),
+            raw: RawPasswdFields::default(),
+        };
+
+        let user = users::User::from(&entry);
+        assert_eq!(user.name(), "testuser");
+        assert_eq!(user.primary_group_id(), 1001);
+
+        let back = PasswdEntry::from(user);
+        assert_eq!(back.pw_name, entry.pw_name);
+        assert_eq!(back.pw_uid, entry.pw_uid);
+        assert_eq!(back.pw_gid, entry.pw_gid);
+        assert_eq!(back.pw_dir, entry.pw_dir);
+        assert_eq!(back.pw_shell, entry.pw_shell);
+        // No equivalent in `users::User`
+        assert!(back.pw_gecos.is_empty());
+        assert!(back.source.is_empty());
+    }
+
+    #[test]
+    fn test_group_round_trip() {
+        let entry = GroupEntry {
+            gr_name: "staff".to_string(),
+            gr_gid: 50,
+            gr_mem: vec!["alice".to_string(), "bob".to_string()],
+            source: "WINBIND".to_string(),
+            source_module: Some(NssModule::Winbind),
+            invalid_members: 0,
+        };
+
+        let back = GroupEntry::from(users::Group::from(&entry));
+        assert_eq!(back.gr_name, entry.gr_name);
+        assert_eq!(back.gr_gid, entry.gr_gid);
+        assert_eq!(back.gr_mem, entry.gr_mem);
+        assert!(back.source.is_empty());
+    }
+}
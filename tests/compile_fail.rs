@@ -0,0 +1,7 @@
+//! Compile-time guarantees checked with `trybuild`.
+
+#[test]
+fn iterators_are_thread_bound() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -1,4 +1,7 @@
-use truenas_rust_nss::{getpwnam, getpwuid, getgrnam, getgrgid, getpwall, getgrall, iterpw, itergrp, NssModule};
+use truenas_rust_nss::{count_passwd, count_group, home_dir, getpwnam, getpwnam_prefix, getpwnam_via_lib, getpwuid, getpwuid_self, uid_exists, which_modules_have_user, verify_name_uid, gid_exists, getgrnam, getgrgid, getpwall, getpwall_with_capacity, getpwall_page, getpwall_sorted, getpwall_by_name, getpwall_by_uid, getgrall, getgrall_by_name, getgrall_by_gid, getgrall_with_members, getgrouplist_compat, iterpw, itergrp, module_capabilities, health_check, gethostbyaddr, set_module_search_paths, register_module_handle, loaded_module_path, module_soname, is_cached, warm_up, NssModule, NssOperation, SortKey};
+use truenas_rust_nss::nss_common::{get_nss_function, raw_symbol, FILES_NSS_PATH, NSS_MODULES_DIR};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 
 #[cfg(test)]
 mod integration_tests {
@@ -14,7 +17,7 @@ mod integration_tests {
             Ok(user) => {
                 assert_eq!(user.pw_name, "root");
                 assert_eq!(user.pw_uid, 0);
-                assert_eq!(user.source, "files");
+                assert_eq!(user.source, "FILES");
                 assert!(!user.pw_shell.is_empty());
 
             }
@@ -31,7 +34,7 @@ mod integration_tests {
             Ok(user) => {
                 assert_eq!(user.pw_name, "root");
                 assert_eq!(user.pw_uid, 0);
-                assert_eq!(user.source, "files");
+                assert_eq!(user.source, "FILES");
 
             }
             Err(e) => {
@@ -47,7 +50,7 @@ mod integration_tests {
             Ok(group) => {
                 assert_eq!(group.gr_name, "root");
                 assert_eq!(group.gr_gid, 0);
-                assert_eq!(group.source, "files");
+                assert_eq!(group.source, "FILES");
 
             }
             Err(e) => {
@@ -63,7 +66,7 @@ mod integration_tests {
             Ok(group) => {
                 assert_eq!(group.gr_name, "root");
                 assert_eq!(group.gr_gid, 0);
-                assert_eq!(group.source, "files");
+                assert_eq!(group.source, "FILES");
 
             }
             Err(e) => {
@@ -81,7 +84,7 @@ mod integration_tests {
 
                 // Check that all users have the files source
                 for user in &users {
-                    assert_eq!(user.source, "files");
+                    assert_eq!(user.source, "FILES");
                     assert!(!user.pw_name.is_empty());
                 }
 
@@ -93,6 +96,63 @@ mod integration_tests {
         }
     }
 
+    #[test]
+    #[ignore = "Requires system NSS libraries - slow test"]
+    fn test_getpwall_with_capacity_files() {
+        let expected = getpwall(Some(NssModule::Files)).expect("getpwall failed");
+        let users = getpwall_with_capacity(Some(NssModule::Files), 256).expect("getpwall_with_capacity failed");
+
+        let expected: Vec<(String, u32)> = expected.into_iter().map(|u| (u.pw_name, u.pw_uid)).collect();
+        let users: Vec<(String, u32)> = users.into_iter().map(|u| (u.pw_name, u.pw_uid)).collect();
+        assert_eq!(users, expected);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries - slow test"]
+    fn test_getpwall_sorted_by_uid_files() {
+        let users = getpwall_sorted(Some(NssModule::Files), SortKey::Uid).expect("getpwall_sorted failed");
+        assert!(!users.is_empty(), "Expected at least one user");
+        assert!(users.windows(2).all(|w| w[0].pw_uid <= w[1].pw_uid));
+        assert_eq!(users.len(), getpwall(Some(NssModule::Files)).unwrap().len());
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries - slow test"]
+    fn test_getpwall_page_files() {
+        let first = getpwall_page(Some(NssModule::Files), 0, 1).expect("getpwall_page failed");
+        let second = getpwall_page(Some(NssModule::Files), 1, 1).expect("getpwall_page failed");
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert_ne!(first[0].pw_name, second[0].pw_name);
+
+        let all = getpwall(Some(NssModule::Files)).expect("getpwall failed");
+        assert_eq!(second[0].pw_name, all[1].pw_name);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_uid_gid_exists_files() {
+        assert!(uid_exists(0, Some(NssModule::Files)).expect("uid_exists failed"));
+        assert!(gid_exists(0, Some(NssModule::Files)).expect("gid_exists failed"));
+
+        assert!(!uid_exists(3_999_999_999, Some(NssModule::Files)).expect("uid_exists failed"));
+        assert!(!gid_exists(3_999_999_999, Some(NssModule::Files)).expect("gid_exists failed"));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_getgrouplist_compat_root() {
+        let root = getpwnam("root", Some(NssModule::Files)).expect("root user not found");
+        let gids = getgrouplist_compat("root", root.pw_gid).expect("getgrouplist_compat failed");
+
+        assert_eq!(gids[0], root.pw_gid);
+        let mut unique = gids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), gids.len());
+    }
+
     #[test]
     #[ignore = "Requires system NSS libraries - slow test"]
     fn test_getgrall_files() {
@@ -102,7 +162,7 @@ mod integration_tests {
 
                 // Check that all groups have the files source
                 for group in &groups {
-                    assert_eq!(group.source, "files");
+                    assert_eq!(group.source, "FILES");
                     assert!(!group.gr_name.is_empty());
                 }
 
@@ -128,7 +188,7 @@ mod integration_tests {
             match result {
                 Ok(user) => {
                     assert!(!user.pw_name.is_empty());
-                    assert_eq!(user.source, "files");
+                    assert_eq!(user.source, "FILES");
                     count += 1;
                 }
                 Err(e) => {
@@ -159,7 +219,7 @@ mod integration_tests {
             match result {
                 Ok(group) => {
                     assert!(!group.gr_name.is_empty());
-                    assert_eq!(group.source, "files");
+                    assert_eq!(group.source, "FILES");
                     count += 1;
                 }
                 Err(e) => {
@@ -185,7 +245,7 @@ mod integration_tests {
                 assert_eq!(user.pw_name, "root");
                 assert_eq!(user.pw_uid, 0);
                 // Source should be one of the available modules
-                assert!(["files", "sss", "winbind"].contains(&user.source.as_str()));
+                assert!(["FILES", "SSS", "WINBIND"].contains(&user.source.as_str()));
             }
             Err(e) => {
                 eprintln!("Warning: Multiple module fallback test failed (may be expected): {}", e);
@@ -193,6 +253,235 @@ mod integration_tests {
         }
     }
 
+    #[test]
+    #[ignore = "Requires system NSS libraries"]
+    fn test_module_capabilities_files() {
+        let capabilities = module_capabilities(NssModule::Files).expect("FILES module failed to load");
+        assert_eq!(capabilities.get(&NssOperation::GetPwNam), Some(&true));
+        // libnss_files implements every operation we wrap
+        assert!(capabilities.values().all(|&supported| supported));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_module_search_paths_skip_bogus_directory() {
+        // Once loaded the library is cached, so the search order is never
+        // consulted again and the assertions below would prove nothing.
+        if is_cached(NssModule::Files) {
+            eprintln!("FILES module already loaded; skipping search path test");
+            return;
+        }
+
+        struct RestoreSearchPaths;
+
+        impl Drop for RestoreSearchPaths {
+            fn drop(&mut self) {
+                set_module_search_paths(vec![PathBuf::from(NSS_MODULES_DIR)]);
+            }
+        }
+
+        let _restore = RestoreSearchPaths;
+        set_module_search_paths(vec![
+            PathBuf::from("/nonexistent/nss-modules"),
+            PathBuf::from(NSS_MODULES_DIR),
+        ]);
+
+        let entry = getpwnam("root", Some(NssModule::Files)).expect("FILES module failed to load");
+        assert_eq!(entry.pw_uid, 0);
+        assert_eq!(
+            loaded_module_path(NssModule::Files).as_deref(),
+            Some(Path::new(FILES_NSS_PATH))
+        );
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and a localhost entry in /etc/hosts"]
+    fn test_gethostbyaddr_localhost_files() {
+        let addr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let host = gethostbyaddr(addr, Some(NssModule::Files)).expect("127.0.0.1 not found");
+        assert!(!host.h_name.is_empty());
+        assert!(host.h_addr_list.contains(&addr));
+        assert_eq!(host.source, "FILES");
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries"]
+    fn test_raw_symbol_files() {
+        let raw = unsafe { raw_symbol(NssModule::Files, "getpwnam_r") }.expect("symbol not resolved");
+        let wrapped = unsafe { get_nss_function(NssOperation::GetPwNam, NssModule::Files) }.unwrap();
+        assert_eq!(raw, wrapped);
+
+        assert!(unsafe { raw_symbol(NssModule::Files, "no_such_symbol") }.is_err());
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_getpwnam_prefix_files() {
+        let matches = getpwnam_prefix("ro", NssModule::Files, 10).expect("FILES enumeration failed");
+        assert!(matches.iter().any(|e| e.pw_name == "root"));
+        assert!(matches.iter().all(|e| e.pw_name.starts_with("ro")));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_getpwall_maps_files() {
+        let by_name = getpwall_by_name(Some(NssModule::Files)).expect("FILES enumeration failed");
+        let by_uid = getpwall_by_uid(Some(NssModule::Files)).expect("FILES enumeration failed");
+        assert_eq!(by_name["root"].pw_uid, 0);
+        assert_eq!(by_uid[&0].pw_name, "root");
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root group"]
+    fn test_getgrall_maps_files() {
+        let by_name = getgrall_by_name(Some(NssModule::Files)).expect("FILES enumeration failed");
+        let by_gid = getgrall_by_gid(Some(NssModule::Files)).expect("FILES enumeration failed");
+        assert_eq!(by_name["root"].gr_gid, 0);
+        assert_eq!(by_gid[&0].gr_name, "root");
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries"]
+    fn test_warm_up_caches_files() {
+        warm_up(NssModule::Files).expect("FILES warm-up failed");
+        assert!(is_cached(NssModule::Files));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries"]
+    fn test_files_supports_enumeration() {
+        assert!(NssModule::Files.supports_enumeration().expect("FILES module failed to load"));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_health_check_files() {
+        let report = health_check(&[NssModule::Files]);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].module, NssModule::Files);
+        assert!(report[0].available);
+        assert!(report[0].resolved, "FILES failed to resolve uid 0: {:?}", report[0].error);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root group"]
+    fn test_root_group_all_members_includes_root() {
+        let group = getgrgid(0, Some(NssModule::Files)).expect("root group not found");
+        let members = group.all_members(Some(NssModule::Files)).expect("passwd enumeration failed");
+        assert!(members.iter().any(|m| m == "root"), "root missing from {:?}", members);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root group"]
+    fn test_getgrall_with_members_resolves_root_group() {
+        let groups = getgrall_with_members(Some(NssModule::Files), 4).expect("FILES enumeration failed");
+        let (root, users) = groups.iter().find(|(g, _)| g.gr_gid == 0).expect("root group not found");
+        for user in users {
+            assert!(root.gr_mem.contains(&user.pw_name), "{} is not a member of root", user.pw_name);
+        }
+        for member in &root.gr_mem {
+            if getpwnam(member, Some(NssModule::Files)).is_ok() {
+                assert!(users.iter().any(|u| &u.pw_name == member), "{} was not resolved", member);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and the effective user to exist"]
+    fn test_getpwuid_self_matches_geteuid() {
+        let entry = getpwuid_self().expect("effective user not found");
+        assert_eq!(entry.pw_uid, unsafe { libc::geteuid() });
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_verify_name_uid_files() {
+        assert!(verify_name_uid("root", 0, Some(NssModule::Files)).expect("FILES lookup failed"));
+        assert!(!verify_name_uid("root", 1, Some(NssModule::Files)).expect("FILES lookup failed"));
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_register_module_handle_files() {
+        let path = std::ffi::CString::new(FILES_NSS_PATH).unwrap();
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_LAZY) };
+        assert!(!handle.is_null(), "dlopen of {} failed", FILES_NSS_PATH);
+
+        let was_cached = is_cached(NssModule::Files);
+        // The handle is never closed, as register_module_handle requires
+        match unsafe { register_module_handle(NssModule::Files, handle) } {
+            Ok(()) => assert_eq!(loaded_module_path(NssModule::Files).as_deref(), Some(Path::new(FILES_NSS_PATH))),
+            // Another test in this process loaded FILES first
+            Err(_) => assert!(was_cached),
+        }
+
+        let entry = getpwnam("root", Some(NssModule::Files)).expect("root not found via FILES");
+        assert_eq!(entry.pw_uid, 0);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_getpwnam_via_lib_files() {
+        let entry = getpwnam_via_lib("root", Path::new(FILES_NSS_PATH), "files").expect("root not found via libnss_files");
+        assert_eq!(entry.pw_uid, 0);
+        assert_eq!(entry.source, "FILES");
+        assert_eq!(entry.source_module, None);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_which_modules_have_user() {
+        assert!(which_modules_have_user("root").contains(&NssModule::Files));
+        assert!(which_modules_have_user("nonexistent_user_12345").is_empty());
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries"]
+    fn test_module_soname_files() {
+        let soname = module_soname(NssModule::Files).expect("FILES soname not found");
+        assert!(soname.contains("libnss_files"), "unexpected soname {soname}");
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_count_files() {
+        let users = count_passwd(Some(NssModule::Files)).expect("FILES passwd enumeration failed");
+        assert!(users >= 1);
+        assert_eq!(users, getpwall(Some(NssModule::Files)).unwrap().len());
+        assert!(count_group(Some(NssModule::Files)).expect("FILES group enumeration failed") >= 1);
+    }
+
+    #[test]
+    #[ignore = "Requires system NSS libraries and root user"]
+    fn test_home_dir_root() {
+        let home = home_dir("root", Some(NssModule::Files)).expect("root not found via FILES");
+        assert!(!home.as_os_str().is_empty());
+    }
+
+    /// Hammers the shared library cache from many threads at once.
+    ///
+    /// Run manually under ThreadSanitizer to catch data races, e.g.:
+    /// `RUSTFLAGS=-Zsanitizer=thread cargo +nightly test -Zbuild-std
+    /// --target x86_64-unknown-linux-gnu --test integration_tests -- --ignored test_concurrent_getpwnam_stress`
+    #[test]
+    #[ignore = "Stress test - run manually, ideally under ThreadSanitizer"]
+    fn test_concurrent_getpwnam_stress() {
+        let threads: Vec<_> = (0..32)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    for _ in 0..500 {
+                        let user = getpwnam("root", None).expect("root lookup failed");
+                        assert_eq!(user.pw_uid, 0);
+                    }
+                })
+            })
+            .collect();
+
+        for handle in threads {
+            handle.join().expect("lookup thread panicked");
+        }
+    }
+
     #[test]
     fn test_nonexistent_user() {
         // This test should work even without NSS libraries, as it tests error handling
@@ -3,6 +3,8 @@
 Integration tests for TrueNAS NSS Python bindings
 """
 
+import json
+import tempfile
 import pytest
 import truenas_nss
 from truenas_nss import nss_common, pwd, grp
@@ -28,6 +30,58 @@ class TestNssCommon:
         """Test that NssError exception exists"""
         assert hasattr(nss_common, 'NssError')
 
+    def test_library_load_failure_raises_nss_library_error(self):
+        """Test that a module library that cannot be loaded raises NssLibraryError"""
+        assert issubclass(nss_common.NssLibraryError, nss_common.NssError)
+        assert issubclass(nss_common.NssUnavailableError, nss_common.NssError)
+        assert issubclass(nss_common.NssPermissionError, nss_common.NssError)
+
+        iterator = pwd.iterpw(nss_common.PyNssModule.WINBIND)
+        try:
+            next(iterator)
+        except nss_common.NssLibraryError as e:
+            # Still caught by handlers written against the base class
+            assert isinstance(e, nss_common.NssError)
+        except StopIteration:
+            pytest.skip("winbind NSS library is installed")
+        else:
+            pytest.skip("winbind NSS library is installed")
+
+    def test_set_modules_dir_rejects_bad_path(self):
+        """Test that set_modules_dir only accepts existing directories"""
+        with pytest.raises(nss_common.NssError):
+            nss_common.set_modules_dir("/nonexistent/nss/modules")
+        with tempfile.NamedTemporaryFile() as not_a_dir:
+            with pytest.raises(nss_common.NssError):
+                nss_common.set_modules_dir(not_a_dir.name)
+
+    def test_set_modules_dir_used_by_later_lookups(self):
+        """Test that a module not yet loaded is searched for in the new directory"""
+        if nss_common.loaded_module_path(nss_common.PyNssModule.WINBIND) is not None:
+            pytest.skip("winbind is already loaded, so the search directory is not consulted")
+
+        with tempfile.TemporaryDirectory() as empty_dir:
+            nss_common.set_modules_dir(empty_dir)
+            try:
+                # The empty directory has no libnss_winbind, so this fails cleanly
+                with pytest.raises(nss_common.NssLibraryError):
+                    next(pwd.iterpw(nss_common.PyNssModule.WINBIND))
+            finally:
+                nss_common.set_modules_dir(None)
+
+    def test_set_default_modules(self):
+        """Test that lookups without a module follow set_default_modules"""
+        with pytest.raises(nss_common.NssError):
+            nss_common.set_default_modules([])
+        with pytest.raises(nss_common.NssError):
+            nss_common.set_default_modules([nss_common.PyNssModule.FILES, nss_common.PyNssModule.FILES])
+
+        nss_common.set_default_modules([nss_common.PyNssModule.FILES])
+        try:
+            assert pwd.getpwnam("root").source == "FILES"
+        finally:
+            nss_common.set_default_modules(None)
+
     def test_invalid_module_name(self):
         """Test that invalid module names raise NssError"""
         with pytest.raises(nss_common.NssError):
@@ -75,6 +129,24 @@ class TestPwd:
             # It's okay if root doesn't exist in the test environment
             pytest.skip(f"Root user not found: {e}")
 
+    def test_display_name(self):
+        """Test that display_name prefers the GECOS full name"""
+        entry = pwd.PyPasswdEntry("alice", 1000, 1000, pw_gecos="Alice Smith,Room 1")
+        assert entry.display_name == "Alice Smith"
+        assert entry.to_dict()['display_name'] == "Alice Smith"
+
+        entry = pwd.PyPasswdEntry("bob", 1001, 1001, pw_gecos="")
+        assert entry.display_name == "bob"
+        assert entry.to_dict()['display_name'] == "bob"
+
+    def test_to_json_matches_to_dict(self):
+        """Test that to_json() matches json.dumps(to_dict())"""
+        entry = pwd.PyPasswdEntry("alice", 1000, 1000, pw_gecos="Alice Smith", pw_dir="/home/alice",
+                                  pw_shell="/bin/sh", source="FILES")
+        assert entry.to_json() == json.dumps(entry.to_dict(), separators=(",", ":"), ensure_ascii=False)
+        assert entry.to_json(pretty=True) == json.dumps(entry.to_dict(), indent=2, ensure_ascii=False)
+        assert json.loads(entry.to_json())['pw_name'] == "alice"
+
     def test_getpwuid_zero(self):
         """Test getpwuid for UID 0 (root)"""
         try:
@@ -152,6 +224,25 @@ class TestPwd:
             pytest.skip(f"getpwall test failed: {e}")
 
 
+    def test_getpwall_wrapped(self):
+        """Test getpwall(wrap=True) result behaves like a dict and a flat sequence"""
+        files_module = nss_common.PyNssModule("files")
+        results = pwd.getpwall(module=files_module, wrap=True)
+        assert isinstance(results, nss_common.PyNssResults)
+
+        files_entries = results["FILES"]
+        assert len(results) == len(files_entries)
+        assert "FILES" in results
+        assert results.keys() == ["FILES"]
+
+        flat = list(results)
+        assert len(flat) == len(files_entries)
+        assert [e.pw_name for e in flat] == [e.pw_name for e in files_entries]
+
+        with pytest.raises(KeyError):
+            results["BOGUS"]
+
+
 class TestGrp:
     """Test grp module functionality"""
 
@@ -192,6 +283,16 @@ class TestGrp:
             # It's okay if root group doesn't exist in the test environment
             pytest.skip(f"Root group not found: {e}")
 
+    def test_to_json_matches_to_dict(self):
+        """Test that to_json() matches json.dumps(to_dict())"""
+        try:
+            entry = grp.getgrnam("root")
+        except (KeyError, nss_common.NssError) as e:
+            pytest.skip(f"Root group not found: {e}")
+
+        assert entry.to_json() == json.dumps(entry.to_dict(), separators=(",", ":"), ensure_ascii=False)
+        assert entry.to_json(pretty=True) == json.dumps(entry.to_dict(), indent=2, ensure_ascii=False)
+
     def test_getgrgid_zero(self):
         """Test getgrgid for GID 0 (root)"""
         try:
@@ -275,6 +376,17 @@ class TestGrp:
             pytest.skip(f"getgrall test failed: {e}")
 
 
+    def test_getgrall_wrapped(self):
+        """Test getgrall(wrap=True) result behaves like a dict and a flat sequence"""
+        files_module = nss_common.PyNssModule("files")
+        results = grp.getgrall(module=files_module, wrap=True, as_dict=True)
+
+        files_entries = results["FILES"]
+        assert len(results) == len(files_entries)
+        flat = list(results)
+        assert [e['gr_name'] for e in flat] == [e['gr_name'] for e in files_entries]
+
+
 class TestModuleIntegration:
     """Test integration between modules"""
 
@@ -294,6 +406,25 @@ class TestModuleIntegration:
         except nss_common.NssError as e:
             pytest.skip(f"Module parameter test failed: {e}")
 
+    def test_source_casing_stable(self):
+        """Test that result keys, entry.source and dict sources agree and are uppercase"""
+        try:
+            pw_results = pwd.getpwall(module=nss_common.PyNssModule.FILES)
+            gr_results = grp.getgrall(module=nss_common.PyNssModule.FILES)
+            pw_dicts = pwd.getpwall(module=nss_common.PyNssModule.FILES, as_dict=True)
+        except nss_common.NssError as e:
+            pytest.skip(f"FILES module unavailable: {e}")
+
+        for results in (pw_results, gr_results):
+            for module_name, entries in results.items():
+                assert module_name == module_name.upper()
+                assert all(entry.source == module_name for entry in entries)
+
+        for module_name, entries in pw_dicts.items():
+            assert all(entry['source'] == module_name for entry in entries)
+
+        assert pwd.getpwnam("root", module=nss_common.PyNssModule.FILES).source == "FILES"
+
 
 if __name__ == "__main__":
     pytest.main([__file__])
\ No newline at end of file
@@ -0,0 +1,8 @@
+use truenas_rust_nss::{iterpw, itergrp, NssModule};
+
+fn assert_send<T: Send>(_: T) {}
+
+fn main() {
+    assert_send(iterpw(NssModule::Files));
+    assert_send(itergrp(NssModule::Files));
+}